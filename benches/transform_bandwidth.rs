@@ -0,0 +1,115 @@
+//! Compares the bytes per tick of the full and the quantized transform replication
+//!
+//! ```console
+//! cargo bench --bench transform_bandwidth
+//! ```
+
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tanks::prelude::*;
+
+/// The ticks of the simulated match, one minute at 60 ticks per second
+const TICKS: usize = 3600;
+const TICK_SECS: f32 = 1.0 / 60.0;
+const TANKS: usize = 16;
+/// The tanks that drive on a slope, so their rotation is not a plain yaw
+const TILTED_TANKS: usize = 4;
+const SHELLS: usize = 32;
+
+#[derive(Default)]
+struct Totals {
+    full_bytes: usize,
+    quantized_bytes: usize,
+    max_position_error: f32,
+    max_rotation_error: f32,
+}
+
+impl Totals {
+    fn add(&mut self, transform: &Transform) {
+        self.full_bytes += bincode::serialized_size(transform).unwrap() as usize;
+
+        let quantized = QuantizedTransform::from_transform(transform);
+        let mut message = Vec::new();
+        quantized.write(&mut message);
+        self.quantized_bytes += message.len();
+
+        let decoded = QuantizedTransform::read(&mut message.as_slice())
+            .unwrap()
+            .to_transform();
+        self.max_position_error = self
+            .max_position_error
+            .max(decoded.translation.distance(transform.translation));
+        self.max_rotation_error = self
+            .max_rotation_error
+            .max(decoded.rotation.angle_between(transform.rotation));
+    }
+}
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut totals = Totals::default();
+
+    let tanks = (0..TANKS)
+        .map(|index| {
+            let center = Vec3::new(
+                rng.gen_range(-100.0..100.0),
+                0.5,
+                rng.gen_range(-100.0..100.0),
+            );
+            let radius = rng.gen_range(5.0..20.0);
+            let tilt = match index < TILTED_TANKS {
+                true => rng.gen_range(0.05..0.3),
+                false => 0.0,
+            };
+            (center, radius, tilt)
+        })
+        .collect::<Vec<_>>();
+    let shells = (0..SHELLS)
+        .map(|_| {
+            let start = Vec3::new(
+                rng.gen_range(-100.0..100.0),
+                1.0,
+                rng.gen_range(-100.0..100.0),
+            );
+            let rotation = Quat::from_euler(EulerRot::YXZ, rng.gen_range(0.0..TAU), 0.05, 0.0);
+            (start, rotation)
+        })
+        .collect::<Vec<_>>();
+
+    for tick in 0..TICKS {
+        let time = tick as f32 * TICK_SECS;
+
+        for (center, radius, tilt) in tanks.iter() {
+            let angle = time * 0.5;
+            let translation = *center + Vec3::new(angle.cos(), 0.0, angle.sin()) * *radius;
+            let rotation = Quat::from_euler(EulerRot::YXZ, -angle, *tilt, 0.0);
+            totals.add(&Transform::from_translation(translation).with_rotation(rotation));
+        }
+
+        // Shells live for two seconds before they are fired again from the same spot
+        let flight = time % 2.0;
+        for (start, rotation) in shells.iter() {
+            let translation = *start + *rotation * Vec3::Z * 40.0 * flight;
+            totals.add(&Transform::from_translation(translation).with_rotation(*rotation));
+        }
+    }
+
+    let full = totals.full_bytes as f32 / TICKS as f32;
+    let quantized = totals.quantized_bytes as f32 / TICKS as f32;
+    println!(
+        "{} tanks ({} tilted) and {} shells over {} ticks",
+        TANKS, TILTED_TANKS, SHELLS, TICKS
+    );
+    println!("full:      {:>8.1} bytes per tick", full);
+    println!(
+        "quantized: {:>8.1} bytes per tick ({:.0}% of full)",
+        quantized,
+        quantized / full * 100.0
+    );
+    println!(
+        "max error: {:.4} m, {:.5} rad",
+        totals.max_position_error, totals.max_rotation_error
+    );
+}
@@ -0,0 +1,13 @@
+use bevy::prelude::*;
+use tanks::prelude::*;
+
+fn main() {
+    let port = std::env::var("TANKS_MASTER_PORT")
+        .ok()
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(DEFAULT_MASTER_PORT);
+
+    let mut app = App::new();
+    app.add_plugins(MasterServerPlugin { port });
+    app.run();
+}
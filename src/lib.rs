@@ -11,7 +11,14 @@ pub mod tanks_server;
 #[cfg(feature = "client")]
 pub mod tanks_client;
 
+#[cfg(feature = "master")]
+pub mod tanks_master;
+
+pub mod netsim;
 pub mod network;
+pub mod quantization;
+pub mod registry;
+pub mod replay;
 
 #[derive(Clone, Eq, PartialEq, Debug, Hash, Default, States)]
 pub enum GameStates {
@@ -19,9 +26,26 @@ pub enum GameStates {
     AssetLoading,
     MainMenu,
     Connecting,
+    /// The client is connected and picks the room to join
+    Rooms,
+    /// The client is connected and waits in the lobby of its room for the match
+    Lobby,
     Playing,
 }
 
+/// The InGame state exists while the client is in a room of a server, in the lobby or in a
+/// match. The connection and the replicated entities are scoped to it.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Hash)]
+pub struct InGame;
+
+impl ComputedStates for InGame {
+    type SourceStates = GameStates;
+
+    fn compute(state: GameStates) -> Option<Self> {
+        matches!(state, GameStates::Lobby | GameStates::Playing).then_some(InGame)
+    }
+}
+
 #[derive(AssetCollection, Resource)]
 pub struct GameAssets {
     #[asset(path = "models/tank.glb#Scene0")]
@@ -58,8 +82,16 @@ pub mod prelude {
     #[cfg(feature = "client")]
     pub use super::tanks_client::prelude::*;
 
+    #[cfg(feature = "master")]
+    pub use super::tanks_master::prelude::*;
+
+    pub use super::netsim::prelude::*;
     pub use super::network::prelude::*;
+    pub use super::quantization::prelude::*;
+    pub use super::registry::prelude::*;
+    pub use super::replay::prelude::*;
 
     pub use super::GameAssets;
     pub use super::GameStates;
+    pub use super::InGame;
 }
@@ -0,0 +1,342 @@
+//! Simulation of bad network conditions for local testing
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bevy::{prelude::*, time::TimeSystem};
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(target_family = "wasm"))]
+pub use socket::SimulatedSocket;
+
+pub mod prelude {
+    pub use super::{NetworkConditions, NetworkSimulatorPlugin, SharedNetworkConditions};
+
+    #[cfg(not(target_family = "wasm"))]
+    pub use super::SimulatedSocket;
+}
+
+/// The NetworkConditions are applied to every packet that goes through a `SimulatedSocket`, in
+/// both directions. They are read from the `TANKS_SIM_*` environment variables and can be
+/// changed at runtime from the inspector of the `DebugPlugin`.
+#[derive(Resource, Reflect, Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[reflect(Resource)]
+pub struct NetworkConditions {
+    /// The delay added to each packet (in milliseconds)
+    pub latency_ms: u32,
+    /// The maximum random variation of the delay (in milliseconds)
+    pub jitter_ms: u32,
+    /// The probability that a packet is dropped
+    pub packet_loss: f32,
+    /// The probability that a packet is sent twice
+    pub duplicate: f32,
+    /// The seed of the random generator, the same seed drops the same packets
+    pub seed: u64,
+}
+
+impl NetworkConditions {
+    pub fn from_env() -> Self {
+        let default = Self::default();
+
+        Self {
+            latency_ms: env_var("TANKS_SIM_LATENCY_MS").unwrap_or(default.latency_ms),
+            jitter_ms: env_var("TANKS_SIM_JITTER_MS").unwrap_or(default.jitter_ms),
+            packet_loss: env_var("TANKS_SIM_PACKET_LOSS").unwrap_or(default.packet_loss),
+            duplicate: env_var("TANKS_SIM_DUPLICATE").unwrap_or(default.duplicate),
+            seed: env_var("TANKS_SIM_SEED").unwrap_or(default.seed),
+        }
+    }
+
+    /// Whether the packets are affected at all
+    pub fn is_enabled(&self) -> bool {
+        self.latency_ms > 0 || self.jitter_ms > 0 || self.packet_loss > 0.0 || self.duplicate > 0.0
+    }
+}
+
+fn env_var<T: std::str::FromStr>(name: &str) -> Option<T> {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+}
+
+/// The conditions shared with the sockets, they are kept in sync with the `NetworkConditions`
+/// resource. The sockets schedule the packets on the clock of the app, the elapsed time that is
+/// also passed to the transports, so a run does not depend on the wall clock.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct SharedNetworkConditions {
+    conditions: Arc<Mutex<NetworkConditions>>,
+    clock: Arc<Mutex<Duration>>,
+}
+
+impl SharedNetworkConditions {
+    pub fn get(&self) -> NetworkConditions {
+        self.conditions.lock().unwrap().clone()
+    }
+
+    /// The time the transports were updated with so far
+    pub fn now(&self) -> Duration {
+        *self.clock.lock().unwrap()
+    }
+}
+
+/// This plugin loads the `NetworkConditions` and shares them with the simulated sockets
+#[derive(Debug, Clone)]
+pub struct NetworkSimulatorPlugin;
+
+impl Plugin for NetworkSimulatorPlugin {
+    fn build(&self, app: &mut App) {
+        let conditions = NetworkConditions::from_env();
+        if conditions.is_enabled() {
+            warn!("Simulating network conditions: {:?}", conditions);
+        }
+
+        app.register_type::<NetworkConditions>();
+        app.insert_resource(SharedNetworkConditions {
+            conditions: Arc::new(Mutex::new(conditions.clone())),
+            clock: default(),
+        });
+        app.insert_resource(conditions);
+
+        app.add_systems(First, advance_network_clock.after(TimeSystem));
+        app.add_systems(
+            PreUpdate,
+            sync_network_conditions.run_if(resource_changed::<NetworkConditions>),
+        );
+    }
+}
+
+fn sync_network_conditions(
+    conditions: Res<NetworkConditions>,
+    shared: Res<SharedNetworkConditions>,
+) {
+    *shared.conditions.lock().unwrap() = conditions.clone();
+}
+
+// The transports are updated with the delta of the same time
+fn advance_network_clock(time: Res<Time>, shared: Res<SharedNetworkConditions>) {
+    *shared.clock.lock().unwrap() += time.delta();
+}
+
+#[cfg(not(target_family = "wasm"))]
+mod socket {
+    use std::{io, net::SocketAddr, time::Duration};
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use renet2_netcode::{ClientSocket, NetcodeTransportError, ServerSocket};
+
+    use super::{NetworkConditions, SharedNetworkConditions};
+
+    #[derive(Debug)]
+    struct DelayedPacket {
+        due: Duration,
+        addr: SocketAddr,
+        payload: Vec<u8>,
+    }
+
+    /// A socket that delays, drops and duplicates the packets of the wrapped socket according to
+    /// the `NetworkConditions`
+    #[derive(Debug)]
+    pub struct SimulatedSocket<S> {
+        inner: S,
+        conditions: SharedNetworkConditions,
+        /// The random generators of each direction, so the packets that are dropped in one
+        /// direction do not depend on the traffic in the other
+        incoming_rng: StdRng,
+        outgoing_rng: StdRng,
+        incoming: Vec<DelayedPacket>,
+        outgoing: Vec<DelayedPacket>,
+        buffer: Vec<u8>,
+    }
+
+    impl<S> SimulatedSocket<S> {
+        pub fn new(inner: S, conditions: SharedNetworkConditions) -> Self {
+            let seed = conditions.get().seed;
+
+            Self {
+                inner,
+                conditions,
+                incoming_rng: StdRng::seed_from_u64(seed),
+                outgoing_rng: StdRng::seed_from_u64(seed.wrapping_add(1)),
+                incoming: Vec::new(),
+                outgoing: Vec::new(),
+                buffer: vec![0; 2048],
+            }
+        }
+
+        /// Queues the copies of a packet that survive the conditions. A reliable socket (like the
+        /// WebSocket) delivers every packet once and in order, so only the latency applies to it.
+        fn schedule(
+            rng: &mut StdRng,
+            now: Duration,
+            conditions: &NetworkConditions,
+            reliable: bool,
+            queue: &mut Vec<DelayedPacket>,
+            addr: SocketAddr,
+            payload: &[u8],
+        ) {
+            if !reliable && rng.gen::<f32>() < conditions.packet_loss {
+                return;
+            }
+
+            let copies = match !reliable && rng.gen::<f32>() < conditions.duplicate {
+                true => 2,
+                false => 1,
+            };
+            for _ in 0..copies {
+                let jitter = match conditions.jitter_ms {
+                    0 => 0,
+                    _ if reliable => 0,
+                    jitter => rng.gen_range(-(jitter as i64)..=jitter as i64),
+                };
+                let delay = (conditions.latency_ms as i64 + jitter).max(0) as u64;
+
+                queue.push(DelayedPacket {
+                    due: now + Duration::from_millis(delay),
+                    addr,
+                    payload: payload.to_vec(),
+                });
+            }
+        }
+
+        /// Removes the packets that are due from the queue, in the order they are due
+        fn take_due(queue: &mut Vec<DelayedPacket>, now: Duration) -> Vec<DelayedPacket> {
+            let (mut due, pending) = queue.drain(..).partition(|packet| packet.due <= now);
+            *queue = pending;
+            due.sort_by_key(|packet: &DelayedPacket| packet.due);
+            due
+        }
+
+        fn would_block() -> NetcodeTransportError {
+            NetcodeTransportError::IO(io::ErrorKind::WouldBlock.into())
+        }
+    }
+
+    macro_rules! impl_simulated_socket {
+        ($trait:ident) => {
+            fn is_encrypted(&self) -> bool {
+                self.inner.is_encrypted()
+            }
+
+            fn is_reliable(&self) -> bool {
+                self.inner.is_reliable()
+            }
+
+            fn addr(&self) -> io::Result<SocketAddr> {
+                self.inner.addr()
+            }
+
+            fn is_closed(&mut self) -> bool {
+                self.inner.is_closed()
+            }
+
+            fn close(&mut self) {
+                self.inner.close();
+            }
+
+            fn preupdate(&mut self) {
+                self.inner.preupdate();
+
+                let conditions = self.conditions.get();
+                let now = self.conditions.now();
+                let reliable = self.inner.is_reliable();
+                loop {
+                    match <S as $trait>::try_recv(&mut self.inner, &mut self.buffer) {
+                        Ok((len, addr)) => Self::schedule(
+                            &mut self.incoming_rng,
+                            now,
+                            &conditions,
+                            reliable,
+                            &mut self.incoming,
+                            addr,
+                            &self.buffer[..len],
+                        ),
+                        Err(NetcodeTransportError::IO(e))
+                            if e.kind() == io::ErrorKind::WouldBlock =>
+                        {
+                            break
+                        }
+                        Err(error) => {
+                            bevy::log::warn!("Simulated socket failed to receive: {}", error);
+                            break;
+                        }
+                    }
+                }
+            }
+
+            fn try_recv(
+                &mut self,
+                buffer: &mut [u8],
+            ) -> Result<(usize, SocketAddr), NetcodeTransportError> {
+                let now = self.conditions.now();
+                let Some(index) = self
+                    .incoming
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, packet)| packet.due <= now)
+                    .min_by_key(|(_, packet)| packet.due)
+                    .map(|(index, _)| index)
+                else {
+                    return Err(Self::would_block());
+                };
+
+                let packet = self.incoming.swap_remove(index);
+                let len = packet.payload.len().min(buffer.len());
+                buffer[..len].copy_from_slice(&packet.payload[..len]);
+                Ok((len, packet.addr))
+            }
+
+            fn postupdate(&mut self) {
+                for packet in Self::take_due(&mut self.outgoing, self.conditions.now()) {
+                    if let Err(error) =
+                        <S as $trait>::send(&mut self.inner, packet.addr, &packet.payload)
+                    {
+                        bevy::log::debug!("Simulated socket failed to send: {}", error);
+                    }
+                }
+
+                self.inner.postupdate();
+            }
+
+            fn send(
+                &mut self,
+                addr: SocketAddr,
+                packet: &[u8],
+            ) -> Result<(), NetcodeTransportError> {
+                let conditions = self.conditions.get();
+                let reliable = self.inner.is_reliable();
+                Self::schedule(
+                    &mut self.outgoing_rng,
+                    self.conditions.now(),
+                    &conditions,
+                    reliable,
+                    &mut self.outgoing,
+                    addr,
+                    packet,
+                );
+                Ok(())
+            }
+        };
+    }
+
+    impl<S: ClientSocket> ClientSocket for SimulatedSocket<S> {
+        impl_simulated_socket!(ClientSocket);
+    }
+
+    impl<S: ServerSocket> ServerSocket for SimulatedSocket<S> {
+        impl_simulated_socket!(ServerSocket);
+
+        fn connection_denied(&mut self, addr: SocketAddr) {
+            self.inner.connection_denied(addr);
+        }
+
+        fn connection_accepted(&mut self, client_id: u64, addr: SocketAddr) {
+            self.inner.connection_accepted(client_id, addr);
+        }
+
+        fn disconnect(&mut self, addr: SocketAddr) {
+            self.inner.disconnect(addr);
+        }
+    }
+}
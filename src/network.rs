@@ -1,22 +1,226 @@
-use bevy_replicon::prelude::*;
-use serde::{Deserialize, Serialize};
+use std::any::type_name;
+
+use bevy_replicon::{bytes::Bytes, prelude::*};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use bevy::prelude::*;
 
+use crate::quantization::QuantizedTransform;
+
 pub mod prelude {
     pub use super::{
-        BoxCollider, CannonFiredEvent, NetworkEntity, NetworkPlugin, Player, PlayerDiedEvent,
-        PlayerFireEvent, PlayerInputEvent, PlayerJoinEvent, PlayerJoinedEvent, PlayerLeftEvent,
-        PlayerSpawnEvent, Shell, ShellImpactEvent, Throttle, PROTOCOL_ID,
+        BoxCollider, CannonFiredEvent, CreateRoomEvent, CurrentLevel, ListRoomsEvent, LobbyPlayer,
+        LobbyReadyEvent, LobbySelectEvent, LobbyStateEvent, MatchSettings, MatchSettingsEvent,
+        NetworkEntity, NetworkPlugin, NetworkTransform, Player, PlayerDiedEvent, PlayerFireEvent,
+        PlayerHitEvent, PlayerInputEvent, PlayerJoinEvent, PlayerJoinRejectedEvent,
+        PlayerJoinedEvent, PlayerLeftEvent, PlayerSpawnEvent, PlayerStats, ProtocolRegistrations,
+        RewoundHitboxesEvent, RoomCreatedEvent, RoomId, RoomInfo, RoomListEvent, RoomRejectedEvent,
+        RoomSettings, ServerMessageEvent, ServerShuttingDownEvent, ServerTick, ServerVersion,
+        Shell, ShellImpactEvent, TankClass, TankSkin, Team, Throttle, GAME_VERSION,
+        MAX_TIME_LIMIT_SECS, PROTOCOL_ID, TEAM_DEATHMATCH, TICK_RATE,
+    };
+    pub use bevy_replicon::prelude::{
+        client_connected, client_just_connected, client_just_disconnected,
     };
-    pub use bevy_replicon::prelude::{client_connected, client_just_connected};
 }
 
-pub const PROTOCOL_ID: u64 = 9;
+/// The version of the network protocol, bump it whenever the registrations in `NetworkPlugin` or
+/// the replicated types change
+pub const PROTOCOL_ID: u64 = 23;
+
+/// The rate of the fixed simulation tick of the server (in Hz)
+pub const TICK_RATE: f64 = 60.0;
+
+/// The longest match the host of a room can pick (in seconds)
+pub const MAX_TIME_LIMIT_SECS: u32 = 3600;
+
+/// The game mode where the players of a team cannot hurt each other and score together
+pub const TEAM_DEATHMATCH: &str = "team_deathmatch";
+
+/// The version of the game
+pub const GAME_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The ServerVersion is served on the `/info` HTTP endpoint, the client compares it with its own
+/// before opening the netcode socket
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerVersion {
+    pub protocol_id: u64,
+    pub game_version: String,
+}
+
+impl ServerVersion {
+    /// The version of this build
+    pub fn current() -> Self {
+        Self {
+            protocol_id: PROTOCOL_ID,
+            game_version: GAME_VERSION.to_string(),
+        }
+    }
+
+    /// Checks that a client of this build can play on the server with the given version
+    pub fn check(&self, server: &ServerVersion) -> Result<(), String> {
+        if self.protocol_id == server.protocol_id {
+            return Ok(());
+        }
+
+        Err(format!(
+            "The server is running version {} (protocol {}), you have {} (protocol {})",
+            server.game_version, server.protocol_id, self.game_version, self.protocol_id
+        ))
+    }
+}
 
 #[derive(Component, Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct NetworkEntity;
 
+/// The ServerTick is the number of the fixed simulation tick of the server. The server keeps it
+/// as a resource and replicates it on a single entity, the client copies the latest one it
+/// received into its own resource and stamps its inputs with it.
+#[derive(
+    Component,
+    Resource,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    Deref,
+)]
+pub struct ServerTick(pub u32);
+
+impl ServerTick {
+    /// The number of ticks from an earlier tick, zero if it is not earlier
+    pub fn since(self, earlier: ServerTick) -> u32 {
+        self.0.saturating_sub(earlier.0)
+    }
+
+    /// The number of ticks in a duration (in milliseconds), rounded to the nearest tick
+    pub fn ticks_in_millis(millis: u32) -> u32 {
+        (millis as f64 * TICK_RATE / 1000.0).round() as u32
+    }
+}
+
+/// The RoomId identifies one of the isolated matches hosted by a server, the server always has
+/// the default room
+#[derive(
+    Component,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+pub struct RoomId(pub u32);
+
+impl RoomId {
+    /// The room that is opened with the server, with the level and mode of its config
+    pub const DEFAULT: RoomId = RoomId(0);
+}
+
+/// The RoomSettings are chosen by the creator of a room
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomSettings {
+    pub name: String,
+    pub mode: String,
+    /// The level of the room, like `levels/World.glb`
+    pub map: String,
+    pub max_players: u32,
+}
+
+/// The RoomInfo describes a room in the room list
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomInfo {
+    pub id: RoomId,
+    pub settings: RoomSettings,
+    pub players: u32,
+}
+
+/// The team of a player, picked in the lobby. The server puts it on the tanks of a
+/// `TEAM_DEATHMATCH` match.
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Team {
+    #[default]
+    Red,
+    Blue,
+}
+
+impl Team {
+    pub const ALL: [Team; 2] = [Team::Red, Team::Blue];
+}
+
+/// The class of the tank of a player, picked in the lobby
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TankClass {
+    Light,
+    #[default]
+    Medium,
+    Heavy,
+}
+
+impl TankClass {
+    pub const ALL: [TankClass; 3] = [TankClass::Light, TankClass::Medium, TankClass::Heavy];
+
+    /// The health the tank spawns with
+    pub fn health(&self) -> f32 {
+        match self {
+            TankClass::Light => 75.0,
+            TankClass::Medium => 100.0,
+            TankClass::Heavy => 150.0,
+        }
+    }
+
+    /// The top speed of the tank
+    pub fn move_speed(&self) -> f32 {
+        match self {
+            TankClass::Light => 6.5,
+            TankClass::Medium => 5.0,
+            TankClass::Heavy => 4.0,
+        }
+    }
+
+    /// The time between two shots of the cannon (in seconds)
+    pub fn fire_rate_secs(&self) -> f32 {
+        match self {
+            TankClass::Light => 0.8,
+            TankClass::Medium => 1.0,
+            TankClass::Heavy => 1.4,
+        }
+    }
+}
+
+/// The MatchSettings of a room are chosen by its host in the lobby
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MatchSettings {
+    pub mode: String,
+    /// The level of the match, like `levels/World.glb`
+    pub map: String,
+    /// The length of a match (in seconds), zero has no limit
+    pub time_limit_secs: u32,
+}
+
+/// A player in the lobby of a room
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LobbyPlayer {
+    pub client_id: ClientId,
+    pub name: String,
+    pub team: Team,
+    pub color: Color,
+    pub class: TankClass,
+    pub ready: bool,
+}
+
 #[derive(Component, Clone, Debug, Serialize, Deserialize)]
 pub struct Player {
     pub client_id: ClientId,
@@ -24,6 +228,36 @@ pub struct Player {
     pub color: Color,
 }
 
+/// The CurrentLevel is the path of the level blueprint of a room, like `levels/World.glb`. The
+/// server replicates it on an entity of each room, the client copies the one of its room into
+/// its own resource and spawns that blueprint.
+#[derive(
+    Component, Resource, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, Deref,
+)]
+pub struct CurrentLevel(pub String);
+
+/// The TankSkin is the prototype texture of a tank, as an index in `GameAssets::prototype_textures`
+#[derive(
+    Resource, Component, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub struct TankSkin(pub u8);
+
+impl TankSkin {
+    /// The number of skins, one for each prototype texture
+    pub const COUNT: u8 = 7;
+}
+
+/// The PlayerStats are shown in the scoreboard, the server keeps one entity with them for each
+/// connected player
+#[derive(Component, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayerStats {
+    pub client_id: ClientId,
+    pub name: String,
+    pub score: u32,
+    /// The round trip time of the player (in milliseconds)
+    pub ping_ms: u32,
+}
+
 #[derive(Component, Clone, Debug, Serialize, Deserialize)]
 pub struct Throttle {
     pub value: f32,
@@ -32,25 +266,60 @@ pub struct Throttle {
 #[derive(Component, Clone, Debug, Serialize, Deserialize)]
 pub struct Shell;
 
-#[derive(Debug, Default, Deserialize, Event, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Event, Serialize)]
 pub struct CannonFiredEvent {
     pub position: Vec3,
     pub rotation: Quat,
+    /// The player whose tank fired
+    pub owner: Option<ClientId>,
+    pub tick: ServerTick,
 }
 
-#[derive(Debug, Default, Deserialize, Event, Serialize, Deref, DerefMut)]
-pub struct ShellImpactEvent(pub Vec3);
+#[derive(Debug, Clone, Default, Deserialize, Event, Serialize)]
+pub struct ShellImpactEvent {
+    pub position: Vec3,
+    pub tick: ServerTick,
+}
 
-#[derive(Debug, Default, Deserialize, Event, Serialize, Deref, DerefMut)]
-pub struct PlayerInputEvent(pub Vec2);
+/// The PlayerHitEvent is sent when a shell hits the tank of a player
+#[derive(Debug, Clone, Deserialize, Event, Serialize)]
+pub struct PlayerHitEvent {
+    pub client_id: ClientId,
+    pub position: Vec3,
+    pub tick: ServerTick,
+}
+
+/// The RewoundHitboxesEvent is sent to a client when it fires a lag compensated shell if the
+/// server has `TANKS_LAG_COMPENSATION_DEBUG` set, the scale of each hitbox is its size
+#[derive(Debug, Default, Deserialize, Event, Serialize)]
+pub struct RewoundHitboxesEvent {
+    /// The number of ticks the hitboxes were rewound by
+    pub rewind_ticks: u32,
+    pub hitboxes: Vec<Transform>,
+}
+
+/// The client inputs are stamped with the latest `ServerTick` the client received, the tick of
+/// the world it saw when it sent them
+#[derive(Debug, Default, Deserialize, Event, Serialize)]
+pub struct PlayerInputEvent {
+    pub axes: Vec2,
+    pub tick: ServerTick,
+}
 
 #[derive(Debug, Default, Deserialize, Event, Serialize)]
-pub struct PlayerFireEvent;
+pub struct PlayerFireEvent {
+    pub tick: ServerTick,
+}
 
 #[derive(Debug, Default, Deserialize, Event, Serialize)]
 pub struct PlayerJoinEvent {
     pub name: String,
     pub color: Color,
+    pub skin: TankSkin,
+    /// A random token that identifies the player across reconnects
+    pub session_token: u64,
+    /// The room to play in, None joins the default room
+    pub room: Option<RoomId>,
 }
 
 #[derive(Debug, Default, Deserialize, Event, Serialize)]
@@ -62,47 +331,294 @@ pub struct PlayerJoinedEvent {
     pub name: String,
 }
 
+/// The PlayerJoinRejectedEvent is sent to a client whose `PlayerJoinEvent` failed the validation
+#[derive(Debug, Deserialize, Event, Serialize)]
+pub struct PlayerJoinRejectedEvent {
+    pub reason: String,
+}
+
 #[derive(Debug, Deserialize, Event, Serialize)]
 pub struct PlayerLeftEvent {
     pub client_id: ClientId,
 }
 
-#[derive(Debug, Deserialize, Event, Serialize)]
+#[derive(Debug, Clone, Deserialize, Event, Serialize)]
 pub struct PlayerDiedEvent {
     pub client_id: ClientId,
     pub position: Vec3,
+    pub tick: ServerTick,
+}
+
+/// The ListRoomsEvent asks the server for a `RoomListEvent`
+#[derive(Debug, Default, Deserialize, Event, Serialize)]
+pub struct ListRoomsEvent;
+
+#[derive(Debug, Deserialize, Event, Serialize)]
+pub struct RoomListEvent {
+    pub rooms: Vec<RoomInfo>,
+}
+
+/// The CreateRoomEvent asks the server to open a room, the server answers with a
+/// `RoomCreatedEvent` or a `RoomRejectedEvent`. The creator plays in it by joining with its id.
+#[derive(Debug, Deserialize, Event, Serialize)]
+pub struct CreateRoomEvent {
+    pub settings: RoomSettings,
+}
+
+#[derive(Debug, Deserialize, Event, Serialize)]
+pub struct RoomCreatedEvent {
+    pub room: RoomInfo,
+}
+
+#[derive(Debug, Deserialize, Event, Serialize)]
+pub struct RoomRejectedEvent {
+    pub reason: String,
+}
+
+/// The LobbySelectEvent changes the team, color and class of the player in the lobby
+#[derive(Debug, Deserialize, Event, Serialize)]
+pub struct LobbySelectEvent {
+    pub team: Team,
+    pub color: Color,
+    pub class: TankClass,
+}
+
+/// The LobbyReadyEvent tells the server whether the player is ready, the match starts when all
+/// the players of the room are ready
+#[derive(Debug, Deserialize, Event, Serialize)]
+pub struct LobbyReadyEvent {
+    pub ready: bool,
+}
+
+/// The MatchSettingsEvent changes the settings of the next match, only the host can send it
+#[derive(Debug, Deserialize, Event, Serialize)]
+pub struct MatchSettingsEvent {
+    pub settings: MatchSettings,
+}
+
+/// The LobbyStateEvent is sent to the members of a room when its lobby changes
+#[derive(Debug, Clone, Deserialize, Event, Serialize)]
+pub struct LobbyStateEvent {
+    pub settings: MatchSettings,
+    /// The players in the order they joined, the first one is the host
+    pub players: Vec<LobbyPlayer>,
+    /// Whether the match is running, the players go back to the lobby when it ends
+    pub in_match: bool,
+    /// The maps of the rotation of the server, the host picks one of them
+    pub maps: Vec<String>,
+}
+
+/// The ServerMessageEvent is a chat message sent by the server administrator
+#[derive(Debug, Deserialize, Event, Serialize)]
+pub struct ServerMessageEvent {
+    pub message: String,
+}
+
+/// The ServerShuttingDownEvent is sent to all clients before the server stops
+#[derive(Debug, Deserialize, Event, Serialize)]
+pub struct ServerShuttingDownEvent {
+    pub reason: String,
+    /// The time until the server disconnects the clients (in seconds)
+    pub countdown: f32,
+}
+
+/// NetworkTransform replicates the `Transform` of the entities with a `NetworkEntity` as a
+/// `QuantizedTransform`
+#[derive(Debug, Clone, Copy)]
+pub struct NetworkTransform;
+
+impl GroupReplication for NetworkTransform {
+    fn register(world: &mut World, registry: &mut ReplicationRegistry) -> ReplicationRule {
+        let transform = registry.register_rule_fns(
+            world,
+            RuleFns::new(serialize_transform, deserialize_transform),
+        );
+        let entity = registry.register_rule_fns(world, RuleFns::<NetworkEntity>::default());
+
+        ReplicationRule::new(vec![transform, entity])
+    }
+}
+
+fn serialize_transform(
+    _ctx: &SerializeCtx,
+    transform: &Transform,
+    message: &mut Vec<u8>,
+) -> bincode::Result<()> {
+    QuantizedTransform::from_transform(transform).write(message);
+    Ok(())
+}
+
+fn deserialize_transform(_ctx: &mut WriteCtx, message: &mut Bytes) -> bincode::Result<Transform> {
+    QuantizedTransform::read(message)
+        .map(|transform| transform.to_transform())
+        .ok_or_else(|| bincode::ErrorKind::Custom("Invalid quantized transform".to_string()).into())
 }
 
 #[derive(Debug, Clone, Component, Reflect, Deserialize, Serialize)]
 #[reflect(Component)]
 pub struct BoxCollider(pub f32, pub f32, pub f32);
 
+/// The ProtocolRegistrations resource lists the events and components registered by
+/// `NetworkPlugin` in order, it is used to detect protocol changes
+#[derive(Resource, Debug, Clone, Default, Deref)]
+pub struct ProtocolRegistrations(Vec<String>);
+
+impl ProtocolRegistrations {
+    /// A stable FNV-1a hash of the registrations
+    pub fn fingerprint(&self) -> u64 {
+        self.0
+            .iter()
+            .flat_map(|registration| registration.bytes().chain([b'\n']))
+            .fold(0xcbf29ce484222325, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            })
+    }
+}
+
+fn add_client_event<E: Event + Serialize + DeserializeOwned>(app: &mut App, channel: ChannelKind) {
+    app.add_client_event::<E>(channel);
+    record::<E>(app, format!("client_event {:?}", channel));
+}
+
+fn add_server_event<E: Event + Serialize + DeserializeOwned>(app: &mut App, channel: ChannelKind) {
+    app.add_server_event::<E>(channel);
+    record::<E>(app, format!("server_event {:?}", channel));
+}
+
+fn replicate<C: Component + Serialize + DeserializeOwned>(app: &mut App) {
+    app.replicate::<C>();
+    record::<C>(app, "component".to_string());
+}
+
+fn record<T>(app: &mut App, kind: String) {
+    app.world_mut()
+        .get_resource_or_insert_with(ProtocolRegistrations::default)
+        .0
+        .push(format!("{} {}", kind, type_name::<T>()));
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkPlugin;
 
 impl Plugin for NetworkPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(RepliconPlugins);
+        // The server hides the entities that are not relevant to a client, see `InterestPlugin`
+        app.add_plugins(
+            RepliconPlugins
+                .build()
+                .set(bevy_replicon::server::ServerPlugin {
+                    visibility_policy: VisibilityPolicy::Blacklist,
+                    ..default()
+                }),
+        );
 
-        app.add_client_event::<PlayerInputEvent>(ChannelKind::Ordered);
-        app.add_client_event::<PlayerFireEvent>(ChannelKind::Ordered);
-        app.add_client_event::<PlayerJoinEvent>(ChannelKind::Ordered);
-        app.add_client_event::<PlayerSpawnEvent>(ChannelKind::Ordered);
+        add_client_event::<PlayerInputEvent>(app, ChannelKind::Ordered);
+        add_client_event::<PlayerFireEvent>(app, ChannelKind::Ordered);
+        add_client_event::<PlayerJoinEvent>(app, ChannelKind::Ordered);
+        add_client_event::<PlayerSpawnEvent>(app, ChannelKind::Ordered);
+        add_client_event::<ListRoomsEvent>(app, ChannelKind::Ordered);
+        add_client_event::<CreateRoomEvent>(app, ChannelKind::Ordered);
+        add_client_event::<LobbySelectEvent>(app, ChannelKind::Ordered);
+        add_client_event::<LobbyReadyEvent>(app, ChannelKind::Ordered);
+        add_client_event::<MatchSettingsEvent>(app, ChannelKind::Ordered);
 
-        app.add_server_event::<PlayerJoinedEvent>(ChannelKind::Ordered);
-        app.add_server_event::<PlayerDiedEvent>(ChannelKind::Ordered);
-        app.add_server_event::<PlayerLeftEvent>(ChannelKind::Ordered);
+        add_server_event::<PlayerJoinedEvent>(app, ChannelKind::Ordered);
+        add_server_event::<PlayerDiedEvent>(app, ChannelKind::Ordered);
+        add_server_event::<PlayerLeftEvent>(app, ChannelKind::Ordered);
+        add_server_event::<ServerMessageEvent>(app, ChannelKind::Ordered);
+        add_server_event::<ServerShuttingDownEvent>(app, ChannelKind::Ordered);
+        add_server_event::<PlayerJoinRejectedEvent>(app, ChannelKind::Ordered);
+        add_server_event::<RoomListEvent>(app, ChannelKind::Ordered);
+        add_server_event::<RoomCreatedEvent>(app, ChannelKind::Ordered);
+        add_server_event::<RoomRejectedEvent>(app, ChannelKind::Ordered);
+        add_server_event::<LobbyStateEvent>(app, ChannelKind::Ordered);
 
-        app.add_server_event::<CannonFiredEvent>(ChannelKind::Unreliable);
-        app.add_server_event::<ShellImpactEvent>(ChannelKind::Unreliable);
+        add_server_event::<CannonFiredEvent>(app, ChannelKind::Unreliable);
+        add_server_event::<ShellImpactEvent>(app, ChannelKind::Unreliable);
+        add_server_event::<PlayerHitEvent>(app, ChannelKind::Unreliable);
+        add_server_event::<RewoundHitboxesEvent>(app, ChannelKind::Unreliable);
 
-        app.replicate::<Name>();
-        app.replicate::<NetworkEntity>();
-        app.replicate::<Player>();
-        app.replicate::<Shell>();
-        app.replicate::<Throttle>();
-        app.replicate_group::<(Transform, NetworkEntity)>(); // NetworkTransform
+        replicate::<Name>(app);
+        replicate::<NetworkEntity>(app);
+        replicate::<Player>(app);
+        replicate::<TankSkin>(app);
+        replicate::<Shell>(app);
+        replicate::<Throttle>(app);
+        replicate::<PlayerStats>(app);
+        replicate::<ServerTick>(app);
+        replicate::<CurrentLevel>(app);
+        app.replicate_group::<NetworkTransform>();
+        record::<NetworkTransform>(app, "group".to_string());
 
         app.register_type::<BoxCollider>();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The protocol ids with the fingerprint of their registrations. When this test fails, bump
+    /// `PROTOCOL_ID` and append the new pair. Changing the fields of a replicated type is not
+    /// detected, it needs a bump as well.
+    const PROTOCOL_HISTORY: &[(u64, u64)] = &[
+        (13, 0x41dfc21692f784bd),
+        (14, 0x311d5675ea34e0df),
+        (15, 0xe0cc39b8225eb6a8),
+        (16, 0x6e8b8e5262fa9def),
+        (17, 0x20a08ba93c041079),
+        (18, 0xfe1c18b9ba496733),
+        (19, 0x6e559e6a7c0ab4fd),
+        (20, 0xc56fae41d1a8bd07),
+        (21, 0xb7d9cb5470f4b2ce),
+        (22, 0x2f6f22019f801579),
+        (23, 0x318624416d397737),
+    ];
+
+    #[test]
+    fn protocol_id_matches_registrations() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, NetworkPlugin));
+
+        let fingerprint = app
+            .world()
+            .resource::<ProtocolRegistrations>()
+            .fingerprint();
+        let (protocol_id, expected) = *PROTOCOL_HISTORY.last().unwrap();
+
+        assert_eq!(
+            protocol_id, PROTOCOL_ID,
+            "PROTOCOL_ID is not the last entry of PROTOCOL_HISTORY"
+        );
+        assert_eq!(
+            expected,
+            fingerprint,
+            "The NetworkPlugin registrations changed, bump PROTOCOL_ID and append \
+             ({}, {:#018x}) to PROTOCOL_HISTORY",
+            PROTOCOL_ID + 1,
+            fingerprint
+        );
+    }
+
+    #[test]
+    fn protocol_history_is_increasing() {
+        for pair in PROTOCOL_HISTORY.windows(2) {
+            assert!(pair[0].0 < pair[1].0);
+            assert_ne!(pair[0].1, pair[1].1);
+        }
+    }
+
+    #[test]
+    fn version_mismatch_is_reported() {
+        let client = ServerVersion::current();
+        let server = ServerVersion {
+            protocol_id: PROTOCOL_ID + 1,
+            game_version: "9.9.9".to_string(),
+        };
+
+        assert!(client.check(&client).is_ok());
+        let error = client.check(&server).unwrap_err();
+        assert!(error.contains("9.9.9"));
+        assert!(error.contains(GAME_VERSION));
+    }
+}
@@ -0,0 +1,257 @@
+//! Compact encoding of the replicated transforms
+//!
+//! A full `Transform` takes 40 bytes with bincode. Tanks and shells never scale and tanks mostly
+//! only yaw, so the position is quantized to 16 bits per axis inside the map bounds and the
+//! rotation is sent either as a 16 bit yaw or as the smallest three components of the quaternion.
+
+use std::{
+    f32::consts::{SQRT_2, TAU},
+    mem::size_of,
+};
+
+use bevy::prelude::*;
+use bevy_replicon::bytes::Buf;
+
+pub mod prelude {
+    pub use super::{in_map_bounds, QuantizedTransform, MAP_BOUNDS_MAX, MAP_BOUNDS_MIN};
+}
+
+/// The corner of the box covered by the quantized positions with the smallest coordinates,
+/// positions outside of the box are clamped
+pub const MAP_BOUNDS_MIN: Vec3 = Vec3::new(-256.0, -32.0, -256.0);
+
+/// The corner of the box covered by the quantized positions with the largest coordinates
+pub const MAP_BOUNDS_MAX: Vec3 = Vec3::new(256.0, 96.0, 256.0);
+
+/// Whether a position can be replicated without being clamped, every map has to fit inside
+pub fn in_map_bounds(position: Vec3) -> bool {
+    position.cmpge(MAP_BOUNDS_MIN).all() && position.cmple(MAP_BOUNDS_MAX).all()
+}
+
+/// Rotations with smaller x and z components than this are sent as a yaw
+const YAW_EPSILON: f32 = 1e-4;
+
+/// The tag of a yaw rotation, the smallest three rotations are tagged with the index of the
+/// dropped component plus one
+const YAW_TAG: u8 = 0;
+
+/// The encoded size of the position
+const POSITION_LEN: usize = 3 * size_of::<u16>();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum QuantizedRotation {
+    /// The angle around the Y axis, the full turn is split in 2^16 steps
+    Yaw(u16),
+    /// The quaternion without its largest component, which is recomputed from the other three
+    SmallestThree { largest: u8, components: [i16; 3] },
+}
+
+/// The QuantizedTransform is the compact form of a `Transform` without the scale, it is 9 bytes
+/// for a yaw and 13 bytes for any other rotation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuantizedTransform {
+    position: [u16; 3],
+    rotation: QuantizedRotation,
+}
+
+impl QuantizedTransform {
+    /// The size of the largest encoding
+    pub const MAX_LEN: usize = POSITION_LEN + 1 + 3 * size_of::<i16>();
+
+    pub fn from_transform(transform: &Transform) -> Self {
+        let normalized =
+            (transform.translation - MAP_BOUNDS_MIN) / (MAP_BOUNDS_MAX - MAP_BOUNDS_MIN);
+        let position = normalized
+            .clamp(Vec3::ZERO, Vec3::ONE)
+            .to_array()
+            .map(|value| (value * u16::MAX as f32).round() as u16);
+
+        Self {
+            position,
+            rotation: quantize_rotation(transform.rotation),
+        }
+    }
+
+    /// The transform with the quantized position and rotation and a scale of one
+    pub fn to_transform(&self) -> Transform {
+        let normalized =
+            Vec3::from_array(self.position.map(|value| value as f32 / u16::MAX as f32));
+        let translation = MAP_BOUNDS_MIN + normalized * (MAP_BOUNDS_MAX - MAP_BOUNDS_MIN);
+
+        Transform::from_translation(translation).with_rotation(dequantize_rotation(self.rotation))
+    }
+
+    /// The size of the encoding in bytes
+    pub fn encoded_len(&self) -> usize {
+        match self.rotation {
+            QuantizedRotation::Yaw(_) => POSITION_LEN + 1 + size_of::<u16>(),
+            QuantizedRotation::SmallestThree { .. } => Self::MAX_LEN,
+        }
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        for value in self.position {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+
+        match self.rotation {
+            QuantizedRotation::Yaw(yaw) => {
+                out.push(YAW_TAG);
+                out.extend_from_slice(&yaw.to_le_bytes());
+            }
+            QuantizedRotation::SmallestThree {
+                largest,
+                components,
+            } => {
+                out.push(largest + 1);
+                for value in components {
+                    out.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+    }
+
+    /// Reads a transform written by `write`, returns None if the message is too short or the
+    /// rotation tag is invalid
+    pub fn read(message: &mut impl Buf) -> Option<Self> {
+        if message.remaining() < POSITION_LEN + 1 {
+            return None;
+        }
+
+        let position = [
+            message.get_u16_le(),
+            message.get_u16_le(),
+            message.get_u16_le(),
+        ];
+        let rotation = match message.get_u8() {
+            YAW_TAG if message.remaining() >= size_of::<u16>() => {
+                QuantizedRotation::Yaw(message.get_u16_le())
+            }
+            tag @ 1..=4 if message.remaining() >= 3 * size_of::<i16>() => {
+                QuantizedRotation::SmallestThree {
+                    largest: tag - 1,
+                    components: [
+                        message.get_i16_le(),
+                        message.get_i16_le(),
+                        message.get_i16_le(),
+                    ],
+                }
+            }
+            _ => return None,
+        };
+
+        Some(Self { position, rotation })
+    }
+}
+
+fn quantize_rotation(rotation: Quat) -> QuantizedRotation {
+    let rotation = rotation.normalize();
+
+    if rotation.x.abs() < YAW_EPSILON && rotation.z.abs() < YAW_EPSILON {
+        let yaw = (2.0 * rotation.y.atan2(rotation.w)).rem_euclid(TAU);
+        let steps = (yaw / TAU * 65536.0).round() as u32;
+        return QuantizedRotation::Yaw(steps as u16);
+    }
+
+    let values = rotation.to_array();
+    let largest = (0..4)
+        .max_by(|&a, &b| values[a].abs().total_cmp(&values[b].abs()))
+        .unwrap_or_default();
+    // q and -q are the same rotation, the dropped component is always made positive
+    let sign = values[largest].signum();
+    let mut components = [0; 3];
+    for (component, index) in components
+        .iter_mut()
+        .zip((0..4).filter(|&index| index != largest))
+    {
+        // The other components are within [-1/sqrt(2), 1/sqrt(2)]
+        let value = (values[index] * sign * SQRT_2).clamp(-1.0, 1.0);
+        *component = (value * i16::MAX as f32).round() as i16;
+    }
+
+    QuantizedRotation::SmallestThree {
+        largest: largest as u8,
+        components,
+    }
+}
+
+fn dequantize_rotation(rotation: QuantizedRotation) -> Quat {
+    match rotation {
+        QuantizedRotation::Yaw(steps) => Quat::from_rotation_y(steps as f32 / 65536.0 * TAU),
+        QuantizedRotation::SmallestThree {
+            largest,
+            components,
+        } => {
+            let others = components.map(|value| value as f32 / i16::MAX as f32 / SQRT_2);
+            let dropped = (1.0 - others.iter().map(|value| value * value).sum::<f32>())
+                .max(0.0)
+                .sqrt();
+
+            let mut values = [0.0; 4];
+            let mut others = others.into_iter();
+            for (index, value) in values.iter_mut().enumerate() {
+                *value = match index == largest as usize {
+                    true => dropped,
+                    false => others.next().unwrap_or_default(),
+                };
+            }
+
+            Quat::from_array(values).normalize()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(transform: &Transform) -> (Transform, usize) {
+        let quantized = QuantizedTransform::from_transform(transform);
+        let mut message = Vec::new();
+        quantized.write(&mut message);
+        assert_eq!(message.len(), quantized.encoded_len());
+
+        let read = QuantizedTransform::read(&mut message.as_slice()).unwrap();
+        assert_eq!(read, quantized);
+        (read.to_transform(), message.len())
+    }
+
+    #[test]
+    fn yaw_is_nine_bytes() {
+        let transform =
+            Transform::from_xyz(12.3, 0.5, -40.1).with_rotation(Quat::from_rotation_y(2.5));
+        let (decoded, len) = round_trip(&transform);
+
+        assert_eq!(len, 9);
+        assert!(decoded.translation.distance(transform.translation) < 0.01);
+        assert!(decoded.rotation.angle_between(transform.rotation) < 0.001);
+        assert_eq!(decoded.scale, Vec3::ONE);
+    }
+
+    #[test]
+    fn tilted_rotation_uses_smallest_three() {
+        let rotation = Quat::from_euler(EulerRot::YXZ, -2.0, 0.3, -0.1);
+        let transform = Transform::from_xyz(-100.0, 20.0, 200.0).with_rotation(rotation);
+        let (decoded, len) = round_trip(&transform);
+
+        assert_eq!(len, QuantizedTransform::MAX_LEN);
+        assert!(decoded.translation.distance(transform.translation) < 0.01);
+        assert!(decoded.rotation.angle_between(transform.rotation) < 0.001);
+    }
+
+    #[test]
+    fn positions_outside_the_map_are_clamped() {
+        let (decoded, _) = round_trip(&Transform::from_xyz(1000.0, -1000.0, 0.0));
+
+        assert!(decoded.translation.distance(Vec3::new(256.0, -32.0, 0.0)) < 0.01);
+    }
+
+    #[test]
+    fn truncated_message_is_rejected() {
+        let mut message = Vec::new();
+        QuantizedTransform::from_transform(&Transform::default()).write(&mut message);
+
+        assert!(QuantizedTransform::read(&mut &message[..message.len() - 1]).is_none());
+        assert!(QuantizedTransform::read(&mut &[0; 6][..]).is_none());
+    }
+}
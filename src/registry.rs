@@ -0,0 +1,47 @@
+//! Types shared by the master server, the game servers and the server browser
+
+use serde::{Deserialize, Serialize};
+
+pub mod prelude {
+    pub use super::{
+        ServerAnnouncement, ServerListing, DEFAULT_MASTER_PORT, HEARTBEAT_INTERVAL_SECS,
+        LISTING_EXPIRY_SECS,
+    };
+}
+
+/// The default port of the master server
+pub const DEFAULT_MASTER_PORT: u16 = 4000;
+
+/// How often the game servers announce themselves to the master server
+pub const HEARTBEAT_INTERVAL_SECS: u64 = 10;
+
+/// How long the master server keeps a game server that stopped sending heartbeats
+pub const LISTING_EXPIRY_SECS: u64 = 30;
+
+/// The ServerAnnouncement is sent by the game servers to the master server with each heartbeat
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ServerAnnouncement {
+    /// The address that the clients should use, defaults to the address of the sender
+    pub address: Option<String>,
+    pub name: String,
+    pub map: String,
+    pub mode: String,
+    pub players: u32,
+    pub max_players: u32,
+    pub http_port: u16,
+    pub native_port: u16,
+    pub wt_port: u16,
+    pub ws_port: u16,
+}
+
+/// The ServerListing is the entry that the master server returns for each game server
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ServerListing {
+    /// The address of the game server in the form `host:http_port`
+    pub address: String,
+    pub name: String,
+    pub map: String,
+    pub mode: String,
+    pub players: u32,
+    pub max_players: u32,
+}
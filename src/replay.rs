@@ -0,0 +1,135 @@
+//! The format of the match replays recorded by the server and played back by the client
+
+use std::io::{Read, Write};
+
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+
+pub mod prelude {
+    pub use super::{
+        Replay, ReplayEntity, ReplayEvent, ReplayFrame, ReplayHeader, ReplayWriter,
+        REPLAY_EXTENSION,
+    };
+}
+
+/// The extension of the replay files
+pub const REPLAY_EXTENSION: &str = "replay";
+
+/// The ReplayHeader is written once at the start of a replay file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayHeader {
+    pub protocol_id: u64,
+    pub game_version: String,
+    /// The level that was loaded by the server
+    pub level: String,
+    /// The time at which the recording started (in seconds since the Unix epoch)
+    pub started_at: u64,
+}
+
+/// The replicated state of an entity, it replaces the previous state with the same id
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ReplayEntity {
+    Player {
+        player: Player,
+        skin: TankSkin,
+        transform: Transform,
+        throttle: f32,
+    },
+    Shell {
+        transform: Transform,
+    },
+    Stats(PlayerStats),
+}
+
+/// A server event that was sent to the clients
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ReplayEvent {
+    CannonFired { position: Vec3, rotation: Quat },
+    ShellImpact(Vec3),
+    PlayerJoined { client_id: ClientId, name: String },
+    PlayerLeft { client_id: ClientId },
+    PlayerDied { client_id: ClientId, position: Vec3 },
+}
+
+/// The ReplayFrame holds the changes of one server tick. The entities are identified by the bits
+/// of their entity on the server.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReplayFrame {
+    /// The time since the start of the recording (in seconds)
+    pub time: f32,
+    /// The server tick of the last simulated changes in the frame
+    pub tick: ServerTick,
+    /// The entities that were spawned or changed
+    pub entities: Vec<(u64, ReplayEntity)>,
+    /// The new transforms of the entities that only moved
+    pub transforms: Vec<(u64, Transform)>,
+    pub despawned: Vec<u64>,
+    pub events: Vec<ReplayEvent>,
+}
+
+impl ReplayFrame {
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+            && self.transforms.is_empty()
+            && self.despawned.is_empty()
+            && self.events.is_empty()
+    }
+}
+
+/// The ReplayWriter writes the header and then streams the frames, so a replay can be read back
+/// even if the server stopped before closing it
+pub struct ReplayWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> ReplayWriter<W> {
+    pub fn new(mut writer: W, header: &ReplayHeader) -> bincode::Result<Self> {
+        bincode::serialize_into(&mut writer, header)?;
+        Ok(Self { writer })
+    }
+
+    pub fn write_frame(&mut self, frame: &ReplayFrame) -> bincode::Result<()> {
+        bincode::serialize_into(&mut self.writer, frame)
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// A replay loaded in memory
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub header: ReplayHeader,
+    pub frames: Vec<ReplayFrame>,
+}
+
+impl Replay {
+    /// Reads a replay recorded with the same protocol, a truncated last frame is ignored
+    pub fn read(mut reader: impl Read) -> Result<Self, String> {
+        let header = bincode::deserialize_from::<_, ReplayHeader>(&mut reader)
+            .map_err(|e| format!("Invalid replay: {}", e))?;
+
+        if header.protocol_id != PROTOCOL_ID {
+            return Err(format!(
+                "The replay was recorded with version {} (protocol {}), you have {} (protocol {})",
+                header.game_version, header.protocol_id, GAME_VERSION, PROTOCOL_ID
+            ));
+        }
+
+        let mut frames = Vec::new();
+        while let Ok(frame) = bincode::deserialize_from::<_, ReplayFrame>(&mut reader) {
+            frames.push(frame);
+        }
+
+        Ok(Self { header, frames })
+    }
+
+    /// The length of the replay (in seconds)
+    pub fn duration(&self) -> f32 {
+        self.frames.last().map(|frame| frame.time).unwrap_or(0.0)
+    }
+}
@@ -3,6 +3,8 @@ use bevy_kira_audio::prelude::*;
 use serde::{Deserialize, Serialize};
 
 use crate::prelude::*;
+// NOTE: explicit import so that it takes precedence over the kira prelude
+use crate::prelude::Volume;
 
 pub mod prelude {
     pub use super::AudioEffectsPlugin;
@@ -17,6 +19,13 @@ pub struct AudioEffectsPlugin;
 #[derive(Resource, Component, Default, Clone)]
 struct ExplosionChannel;
 
+/// The volume setting that scales the sounds of an emitter
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+enum SoundVolume {
+    Effects,
+    Engine,
+}
+
 impl Plugin for AudioEffectsPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins((AudioPlugin, SpatialAudioPlugin));
@@ -33,6 +42,9 @@ impl Plugin for AudioEffectsPlugin {
             )
                 .run_if(in_state(GameStates::Playing)),
         );
+        // The spatial audio sets the volume of the emitters every frame, so the settings are
+        // applied on top of it once it ran
+        app.add_systems(Last, apply_spatial_volume);
 
         app.add_audio_channel::<ExplosionChannel>();
     }
@@ -54,6 +66,7 @@ fn play_cannon_fired(
                 instances: vec![sound],
             },
             SpatialRadius { radius: 50.0 },
+            SoundVolume::Effects,
             StateScoped(GameStates::Playing),
         ));
     }
@@ -70,11 +83,12 @@ fn play_shell_impact(
 
         commands.spawn((
             Name::new("ShellImpactSound"),
-            Transform::from_translation(**event),
+            Transform::from_translation(event.position),
             SpatialAudioEmitter {
                 instances: vec![sound],
             },
             SpatialRadius { radius: 50.0 },
+            SoundVolume::Effects,
             StateScoped(GameStates::Playing),
         ));
     }
@@ -96,6 +110,7 @@ fn play_player_died(
                 instances: vec![sound],
             },
             SpatialRadius { radius: 50.0 },
+            SoundVolume::Effects,
             StateScoped(GameStates::Playing),
         ));
     }
@@ -108,8 +123,8 @@ fn play_engine_sound(
     q_player: Query<(Entity, &Player), Without<EngineSound>>,
 ) {
     for (entity, Player { client_id, .. }) in q_player.iter() {
-        let sound = audio
-            .create_channel(&client_id.get().to_string())
+        let channel = audio.create_channel(&client_id.get().to_string());
+        let sound = channel
             .play(game_assets.tank_engine.clone())
             .looped()
             .handle();
@@ -121,6 +136,7 @@ fn play_engine_sound(
                 instances: vec![sound],
             },
             SpatialRadius { radius: 25.0 },
+            SoundVolume::Engine,
         ));
     }
 }
@@ -138,6 +154,40 @@ fn handle_engine_sound(
     }
 }
 
+// Same attenuation as the spatial audio, scaled by the volume settings
+fn apply_spatial_volume(
+    volume: Res<Volume>,
+    effects_volume: Res<EffectsVolume>,
+    engine_volume: Res<EngineVolume>,
+    q_receiver: Query<&GlobalTransform, With<SpatialAudioReceiver>>,
+    q_emitter: Query<(
+        &GlobalTransform,
+        &SpatialAudioEmitter,
+        &SpatialRadius,
+        &SoundVolume,
+    )>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+) {
+    let Ok(receiver) = q_receiver.get_single() else {
+        return;
+    };
+
+    for (transform, emitter, radius, sound_volume) in q_emitter.iter() {
+        let distance = transform.translation().distance(receiver.translation());
+        let attenuation = (1.0 - distance / radius.radius).clamp(0.0, 1.0).powi(2) as f64;
+        let amplitude = match sound_volume {
+            SoundVolume::Effects => effects_volume.amplitude(*volume),
+            SoundVolume::Engine => engine_volume.amplitude(*volume),
+        };
+
+        for handle in emitter.instances.iter() {
+            if let Some(instance) = audio_instances.get_mut(handle) {
+                instance.set_volume(attenuation * amplitude, AudioTween::default());
+            }
+        }
+    }
+}
+
 fn destroy_audio(mut commands: Commands, q_audio: Query<(Entity, &SpatialAudioEmitter)>) {
     for (entity, audio) in q_audio.iter() {
         if audio.instances.is_empty() {
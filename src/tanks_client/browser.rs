@@ -0,0 +1,179 @@
+//! Server browser backed by the master server
+
+use std::time::Duration;
+
+use bevy::{
+    prelude::*,
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
+    utils::Instant,
+};
+
+use crate::prelude::*;
+use crate::tanks_client::protocol::http_get;
+
+pub mod prelude {
+    pub use super::{
+        BrowsedServer, RefreshServerListEvent, ServerBrowserPlugin, ServerList, ServerListStatus,
+    };
+}
+
+/// A game server returned by the master server
+#[derive(Debug, Clone)]
+pub struct BrowsedServer {
+    pub listing: ServerListing,
+    /// The round trip time of an HTTP request to the server, `None` until it is measured
+    pub latency: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ServerListStatus {
+    #[default]
+    Idle,
+    Loading,
+    Ready,
+    Failed(String),
+}
+
+/// The ServerList holds the game servers of the last refresh, sorted by latency
+#[derive(Resource, Debug, Clone, Default)]
+pub struct ServerList {
+    pub servers: Vec<BrowsedServer>,
+    pub status: ServerListStatus,
+}
+
+/// The RefreshServerListEvent is an event that is sent to fetch the server list from the master
+/// server in `ClientInfo::master_address`
+#[derive(Debug, Clone, Event)]
+pub struct RefreshServerListEvent;
+
+#[derive(Resource, Debug)]
+struct ListServersTask(Task<Result<Vec<ServerListing>, String>>);
+
+#[derive(Resource, Debug, Default)]
+struct PingTasks(Vec<(String, Task<Result<Duration, String>>)>);
+
+/// This plugin fetches the list of game servers from the master server and pings each of them
+#[derive(Debug, Clone)]
+pub struct ServerBrowserPlugin;
+
+impl Plugin for ServerBrowserPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ServerList>();
+        app.init_resource::<PingTasks>();
+        app.add_event::<RefreshServerListEvent>();
+
+        app.add_systems(
+            Update,
+            (
+                refresh_server_list,
+                handle_list_servers_task.run_if(resource_exists::<ListServersTask>),
+                handle_ping_tasks,
+            )
+                .chain(),
+        );
+    }
+}
+
+fn refresh_server_list(
+    mut commands: Commands,
+    mut events: EventReader<RefreshServerListEvent>,
+    client_info: Res<ClientInfo>,
+    mut server_list: ResMut<ServerList>,
+    mut ping_tasks: ResMut<PingTasks>,
+) {
+    if events.read().last().is_none() {
+        return;
+    }
+
+    let url = format!("http://{}/servers", client_info.master_address);
+    info!("Fetching the server list from {}", url);
+
+    let task = AsyncComputeTaskPool::get().spawn(async move {
+        let body = http_get(url).await?;
+        serde_json::from_str::<Vec<ServerListing>>(&body).map_err(|e| e.to_string())
+    });
+
+    *server_list = ServerList {
+        servers: Vec::new(),
+        status: ServerListStatus::Loading,
+    };
+    ping_tasks.0.clear();
+    commands.insert_resource(ListServersTask(task));
+}
+
+fn handle_list_servers_task(
+    mut commands: Commands,
+    mut task: ResMut<ListServersTask>,
+    mut server_list: ResMut<ServerList>,
+    mut ping_tasks: ResMut<PingTasks>,
+) {
+    let Some(result) = block_on(future::poll_once(&mut task.0)) else {
+        return;
+    };
+    commands.remove_resource::<ListServersTask>();
+
+    let listings = match result {
+        Ok(listings) => listings,
+        Err(error) => {
+            warn!("Failed to fetch the server list: {}", error);
+            server_list.status = ServerListStatus::Failed(error);
+            return;
+        }
+    };
+
+    let thread_pool = AsyncComputeTaskPool::get();
+    for listing in listings.iter() {
+        let url = format!("http://{}/wasm", listing.address);
+        let task = thread_pool.spawn(async move {
+            let start = Instant::now();
+            http_get(url).await?;
+            Ok(start.elapsed())
+        });
+        ping_tasks.0.push((listing.address.clone(), task));
+    }
+
+    server_list.servers = listings
+        .into_iter()
+        .map(|listing| BrowsedServer {
+            listing,
+            latency: None,
+        })
+        .collect();
+    server_list.status = ServerListStatus::Ready;
+}
+
+fn handle_ping_tasks(mut ping_tasks: ResMut<PingTasks>, mut server_list: ResMut<ServerList>) {
+    if ping_tasks.0.is_empty() {
+        return;
+    }
+
+    let mut updated = false;
+    ping_tasks.0.retain_mut(|(address, task)| {
+        let Some(result) = block_on(future::poll_once(task)) else {
+            return true;
+        };
+
+        match result {
+            Ok(latency) => {
+                if let Some(server) = server_list
+                    .servers
+                    .iter_mut()
+                    .find(|server| server.listing.address == *address)
+                {
+                    server.latency = Some(latency);
+                    updated = true;
+                }
+            }
+            Err(error) => debug!("Failed to ping {}: {}", address, error),
+        }
+
+        false
+    });
+
+    // Servers that did not answer are listed last
+    if updated {
+        server_list
+            .servers
+            .sort_by_key(|server| server.latency.unwrap_or(Duration::MAX));
+    }
+}
@@ -1,13 +1,23 @@
-use bevy::prelude::*;
+use bevy::{
+    prelude::*,
+    render::camera::RenderTarget,
+    window::{PrimaryWindow, WindowResized},
+};
 use bevy_kira_audio::prelude::*;
+use leafwing_input_manager::prelude::*;
 
 use crate::prelude::*;
+use crate::tanks_client::renderer::render_target_image;
 use utils::prelude::*;
 
 pub mod prelude {
     pub use super::TankCameraPlugin;
 }
 
+// Tag component used to mark the UI camera and the image that show the scaled game view
+#[derive(Component)]
+struct ScaledView;
+
 pub struct TankCameraPlugin;
 
 impl Plugin for TankCameraPlugin {
@@ -24,15 +34,70 @@ impl Plugin for TankCameraPlugin {
             SmoothTransformSet.run_if(in_state(GameStates::Playing)),
         );
 
-        app.add_systems(OnEnter(GameStates::Playing), spawn_camera);
+        app.add_systems(
+            OnEnter(GameStates::Playing),
+            (spawn_camera, apply_render_scale).chain(),
+        );
         app.add_systems(
             Update,
             update_camera_target.run_if(in_state(GameStates::Playing)),
         );
+        app.add_systems(
+            Update,
+            update_input_map
+                .run_if(in_state(GameStates::Playing))
+                .run_if(resource_changed::<Keybindings>),
+        );
+        app.add_systems(
+            Update,
+            apply_display_quality
+                .run_if(in_state(GameStates::Playing))
+                .run_if(resource_changed::<DisplayQuality>),
+        );
+        app.add_systems(
+            Update,
+            apply_render_scale
+                .run_if(in_state(GameStates::Playing))
+                .run_if(resource_changed::<RenderScale>.or(on_event::<WindowResized>)),
+        );
+    }
+}
+
+fn camera_input_map(keybindings: &Keybindings) -> InputMap<OrbiterTransformAction> {
+    let mut input_map =
+        InputMap::default().with_dual_axis(OrbiterTransformAction::Orbit, GamepadStick::RIGHT);
+
+    if let Some(binding) = keybindings.get(ControlAction::CameraOrbit) {
+        insert_dual_axis_binding(&mut input_map, OrbiterTransformAction::Pan, binding);
+    }
+    if let Some(binding) = keybindings.get(ControlAction::Zoom) {
+        insert_axis_binding(&mut input_map, OrbiterTransformAction::Zoom, binding);
+    }
+
+    for (control, action) in [
+        (ControlAction::CameraLeft, OrbiterTransformAction::OrbitLeft),
+        (
+            ControlAction::CameraRight,
+            OrbiterTransformAction::OrbitRight,
+        ),
+        (ControlAction::CameraUp, OrbiterTransformAction::OrbitUp),
+        (ControlAction::CameraDown, OrbiterTransformAction::OrbitDown),
+        (ControlAction::ZoomIn, OrbiterTransformAction::ZoomIn),
+        (ControlAction::ZoomOut, OrbiterTransformAction::ZoomOut),
+    ] {
+        if let Some(binding) = keybindings.get(control) {
+            insert_binding(&mut input_map, action, binding);
+        }
     }
+
+    input_map
 }
 
-fn spawn_camera(mut commands: Commands) {
+fn spawn_camera(
+    mut commands: Commands,
+    display_quality: Res<DisplayQuality>,
+    keybindings: Res<Keybindings>,
+) {
     commands
         .spawn((
             Name::new("CameraRoot"),
@@ -44,16 +109,89 @@ fn spawn_camera(mut commands: Commands) {
         .with_child((
             Name::new("Camera3d"),
             OrbiterTransform::default(),
+            InputManagerBundle::with_map(camera_input_map(&keybindings)),
             Camera {
                 clear_color: Color::BLACK.into(),
                 ..default()
             },
             Camera3d::default(),
+            display_quality.msaa(),
             Transform::from_xyz(15.0, 15.0, 15.0).looking_at(Vec3::ZERO, Vec3::Y),
             SpatialAudioReceiver,
         ));
 }
 
+fn update_input_map(
+    keybindings: Res<Keybindings>,
+    mut q_camera: Query<&mut InputMap<OrbiterTransformAction>>,
+) {
+    for mut input_map in q_camera.iter_mut() {
+        *input_map = camera_input_map(&keybindings);
+    }
+}
+
+fn apply_display_quality(
+    display_quality: Res<DisplayQuality>,
+    mut q_camera: Query<&mut Msaa, With<Camera3d>>,
+) {
+    for mut msaa in q_camera.iter_mut() {
+        *msaa = display_quality.msaa();
+    }
+}
+
+// Below 100% the game camera renders to a smaller image, which a UI camera stretches over the
+// window behind the rest of the UI
+fn apply_render_scale(
+    mut commands: Commands,
+    render_scale: Res<RenderScale>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    mut images: ResMut<Assets<Image>>,
+    mut q_camera: Query<&mut Camera, With<Camera3d>>,
+    q_scaled: Query<Entity, With<ScaledView>>,
+) {
+    for entity in q_scaled.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let target = match render_scale.is_native() {
+        true => RenderTarget::default(),
+        false => {
+            let size = (window.physical_size().as_vec2() * render_scale.factor()).as_uvec2();
+            let image = images.add(render_target_image(size));
+
+            commands.spawn((
+                Name::new("CameraUI"),
+                ScaledView,
+                Camera2d,
+                Camera {
+                    order: 1,
+                    ..default()
+                },
+                StateScoped(GameStates::Playing),
+            ));
+            commands.spawn((
+                Name::new("ScaledView"),
+                ScaledView,
+                ImageNode::new(image.clone()),
+                Node {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    ..default()
+                },
+                GlobalZIndex(i32::MIN),
+                StateScoped(GameStates::Playing),
+            ));
+
+            RenderTarget::Image(image)
+        }
+    };
+
+    for mut camera in q_camera.iter_mut() {
+        camera.target = target.clone();
+    }
+}
+
 fn update_camera_target(
     // TODO: somehow get local player without all the network stuff
     q_player: Query<(&Player, &Transform)>,
@@ -9,7 +9,14 @@ use crate::prelude::*;
 use ::utils::prelude::*;
 
 pub mod prelude {
-    pub use super::ClientPlugin;
+    pub use super::{ClientPlugin, ServerShutdown};
+}
+
+/// The ServerShutdown resource is inserted when the server announces that it is shutting down
+#[derive(Resource, Debug, Clone)]
+pub struct ServerShutdown {
+    pub reason: String,
+    pub countdown: Timer,
 }
 
 pub struct ClientPlugin;
@@ -35,13 +42,23 @@ impl Plugin for ClientPlugin {
                     ..default()
                 }),
         );
+        app.add_plugins(SettingsPlugin);
         app.add_plugins(ClientProtocolPlugin);
         app.add_plugins(RendererPlugin);
+        app.add_plugins(ClientLevelPlugin);
         app.add_plugins(MainMenuPlugin);
+        app.add_plugins(ServerBrowserPlugin);
         app.add_plugins(TankCameraPlugin);
         app.add_plugins(TankInputPlugin);
+        app.add_plugins(TouchControlsPlugin);
+        app.add_plugins(RoomMenuPlugin);
+        app.add_plugins(LobbyMenuPlugin);
         app.add_plugins(GameGuiPlugin);
+        app.add_plugins(NetStatsPlugin);
         app.add_plugins(AudioEffectsPlugin);
+        app.add_plugins(GamepadRumblePlugin);
+        app.add_plugins(ReconnectPlugin);
+        app.add_plugins(ReplayPlaybackPlugin);
         app.add_plugins(DespawnAfterPlugin);
 
         // FIXME: For now we disable particle effects on wasm because it's not working
@@ -52,7 +69,9 @@ impl Plugin for ClientPlugin {
         app.add_plugins(DebugPlugin);
 
         app.init_state::<GameStates>();
+        app.add_computed_state::<InGame>();
         app.enable_state_scoped_entities::<GameStates>();
+        app.enable_state_scoped_entities::<InGame>();
         app.add_loading_state(
             LoadingState::new(GameStates::AssetLoading)
                 .continue_to_state(GameStates::MainMenu)
@@ -71,12 +90,36 @@ impl Plugin for ClientPlugin {
                 .run_if(in_state(GameStates::Connecting))
                 .run_if(client_just_connected),
         );
-        app.add_systems(OnEnter(GameStates::Playing), (setup_game, hide_cursor));
         app.add_systems(
             Update,
-            (handle_player_died, handle_state_scoped).run_if(in_state(GameStates::Playing)),
+            (
+                handle_connecting_cancel,
+                handle_connecting_failed.run_if(resource_added::<ConnectionError>),
+            )
+                .run_if(in_state(GameStates::Connecting)),
+        );
+        app.add_systems(OnEnter(InGame), setup_game);
+        app.add_systems(OnEnter(GameStates::Playing), hide_cursor);
+        app.add_systems(
+            Update,
+            (
+                handle_player_died,
+                handle_player_join_rejected,
+                handle_state_scoped,
+                handle_server_shutting_down,
+                update_server_shutdown.run_if(resource_exists::<ServerShutdown>),
+            )
+                .run_if(in_state(InGame)),
+        );
+        app.add_systems(
+            Update,
+            handle_server_disconnected
+                .run_if(in_state(InGame))
+                .run_if(resource_exists::<ServerShutdown>)
+                .run_if(client_just_disconnected),
         );
         app.add_systems(OnExit(GameStates::Playing), show_cursor);
+        app.add_systems(OnExit(InGame), remove_server_shutdown);
     }
 }
 
@@ -142,7 +185,14 @@ fn handle_play_button_pressed(
     }
 }
 
-fn spawn_connecting_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+#[derive(Component, Clone, Copy, Debug)]
+struct ConnectingCancelButton;
+
+fn spawn_connecting_ui(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    client_info: Res<ClientInfo>,
+) {
     let icon = asset_server.load("branding/icon.png");
 
     commands.spawn((
@@ -172,16 +222,98 @@ fn spawn_connecting_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
                 },
             ));
         });
+
+    commands
+        .spawn((
+            Node {
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::End,
+                flex_direction: FlexDirection::Column,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                padding: UiRect::bottom(Val::Px(50.0)),
+                position_type: PositionType::Absolute,
+                ..default()
+            },
+            StateScoped(GameStates::Connecting),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(format!("Connecting to {}...", client_info.address)),
+                TextFont {
+                    font_size: 24.0,
+                    ..default()
+                },
+            ));
+            parent
+                .spawn((
+                    ConnectingCancelButton,
+                    Button,
+                    Node {
+                        width: Val::Px(200.0),
+                        height: Val::Px(65.0),
+                        margin: UiRect::all(Val::Px(20.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgb(0.15, 0.15, 0.15)),
+                ))
+                .with_child((
+                    Text::new("Cancel"),
+                    TextFont {
+                        font_size: 33.0,
+                        ..default()
+                    },
+                ));
+        });
 }
 
 fn handle_connecting_done(mut next_state: ResMut<NextState<GameStates>>) {
-    next_state.set(GameStates::Playing);
+    next_state.set(GameStates::Rooms);
 }
 
-fn setup_game(client_info: Res<ClientInfo>, mut join: EventWriter<PlayerJoinEvent>) {
+fn handle_connecting_cancel(
+    q_button: Query<&Interaction, (Changed<Interaction>, With<ConnectingCancelButton>)>,
+    keys: Res<ButtonInput<KeyCode>>,
+    q_gamepad: Query<&Gamepad>,
+    mut next_state: ResMut<NextState<GameStates>>,
+) {
+    let pressed = q_button
+        .iter()
+        .any(|interaction| *interaction == Interaction::Pressed);
+    let back = keys.just_pressed(KeyCode::Escape)
+        || q_gamepad
+            .iter()
+            .any(|gamepad| gamepad.just_pressed(GamepadButton::East));
+
+    if pressed || back {
+        info!("Connection cancelled");
+        next_state.set(GameStates::MainMenu);
+    }
+}
+
+fn handle_connecting_failed(
+    error: Res<ConnectionError>,
+    mut next_state: ResMut<NextState<GameStates>>,
+) {
+    warn!("{}", **error);
+    next_state.set(GameStates::MainMenu);
+}
+
+fn setup_game(
+    client_info: Res<ClientInfo>,
+    session_token: Res<SessionToken>,
+    tank_color: Res<TankColor>,
+    tank_skin: Res<TankSkin>,
+    mut join: EventWriter<PlayerJoinEvent>,
+) {
     join.send(PlayerJoinEvent {
         name: client_info.name.clone(),
-        color: Color::srgb(0.0, 0.0, 1.0),
+        color: tank_color.0,
+        skin: *tank_skin,
+        session_token: **session_token,
+        room: client_info.room,
     });
 }
 
@@ -197,13 +329,58 @@ fn handle_player_died(
     }
 }
 
+// The server refuses the join when the name is taken or invalid
+fn handle_player_join_rejected(
+    mut commands: Commands,
+    mut rejected: EventReader<PlayerJoinRejectedEvent>,
+    mut next_state: ResMut<NextState<GameStates>>,
+) {
+    if let Some(event) = rejected.read().last() {
+        warn!("The server rejected the player: {}", event.reason);
+        commands.insert_resource(ConnectionError(event.reason.clone()));
+        next_state.set(GameStates::MainMenu);
+    }
+}
+
 fn handle_state_scoped(
     mut commands: Commands,
-    q_entity: Query<Entity, (With<NetworkEntity>, Without<StateScoped<GameStates>>)>,
+    q_entity: Query<Entity, (With<NetworkEntity>, Without<StateScoped<InGame>>)>,
 ) {
     for entity in q_entity.iter() {
-        commands
-            .entity(entity)
-            .insert((StateScoped(GameStates::Playing),));
+        commands.entity(entity).insert((StateScoped(InGame),));
     }
 }
+
+fn handle_server_shutting_down(
+    mut commands: Commands,
+    mut shutting_down: EventReader<ServerShuttingDownEvent>,
+) {
+    for event in shutting_down.read() {
+        warn!("{} in {} seconds", event.reason, event.countdown);
+
+        commands.insert_resource(ServerShutdown {
+            reason: event.reason.clone(),
+            countdown: Timer::from_seconds(event.countdown, TimerMode::Once),
+        });
+    }
+}
+
+// Leave before the server drops the connection
+fn update_server_shutdown(
+    time: Res<Time>,
+    mut shutdown: ResMut<ServerShutdown>,
+    mut next_state: ResMut<NextState<GameStates>>,
+) {
+    if shutdown.countdown.tick(time.delta()).just_finished() {
+        next_state.set(GameStates::MainMenu);
+    }
+}
+
+fn handle_server_disconnected(mut next_state: ResMut<NextState<GameStates>>) {
+    warn!("Disconnected from the server");
+    next_state.set(GameStates::MainMenu);
+}
+
+fn remove_server_shutdown(mut commands: Commands) {
+    commands.remove_resource::<ServerShutdown>();
+}
@@ -1,8 +1,12 @@
+use std::time::Duration;
+
 use bevy::prelude::*;
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use bevy_rapier3d::prelude::*;
 use iyes_perf_ui::prelude::*;
 
+use crate::prelude::*;
+
 pub mod prelude {
     pub use super::{DebugPlugin, DebugSet};
 }
@@ -10,6 +14,16 @@ pub mod prelude {
 #[derive(Debug, Resource, Default, Clone, Deref, DerefMut)]
 struct ShowAxes(pub bool);
 
+/// How long the rewound hitboxes of a shot are drawn
+const REWOUND_HITBOXES_DURATION: Duration = Duration::from_secs(1);
+
+/// The hitboxes the server used for the last lag compensated shot of the local player
+#[derive(Debug, Resource, Default)]
+struct RewoundHitboxes {
+    hitboxes: Vec<Transform>,
+    timer: Timer,
+}
+
 /// System set for the debug plugin
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct DebugSet;
@@ -25,8 +39,9 @@ impl Plugin for DebugPlugin {
             .add_plugins(bevy::diagnostic::EntityCountDiagnosticsPlugin)
             .add_plugins(bevy::diagnostic::SystemInformationDiagnosticsPlugin)
             .init_resource::<ShowAxes>()
+            .init_resource::<RewoundHitboxes>()
             .add_plugins(PerfUiPlugin)
-            // Bevy egui inspector
+            // Bevy egui inspector, the `NetworkConditions` resource can be edited from it
             .add_plugins(WorldInspectorPlugin::new())
             // we want to show Rapier debug information:
             .add_plugins(RapierDebugRenderPlugin::default())
@@ -35,6 +50,10 @@ impl Plugin for DebugPlugin {
             // frame as we spawn the entities. Otherwise, Bevy UI will complain.
             .add_systems(Update, toggle.before(iyes_perf_ui::PerfUiSet::Setup))
             .add_systems(Update, draw_axes)
+            .add_systems(
+                Update,
+                (receive_rewound_hitboxes, draw_rewound_hitboxes).chain(),
+            )
             .add_systems(Startup, setup);
     }
 }
@@ -77,3 +96,35 @@ fn draw_axes(mut gizmos: Gizmos, query: Query<&Transform>, show_axes: Res<ShowAx
         gizmos.axes(transform, length);
     }
 }
+
+// The server sends the rewound hitboxes when it runs with `TANKS_LAG_COMPENSATION_DEBUG`
+fn receive_rewound_hitboxes(
+    mut events: EventReader<RewoundHitboxesEvent>,
+    mut rewound: ResMut<RewoundHitboxes>,
+) {
+    let Some(event) = events.read().last() else {
+        return;
+    };
+
+    debug!(
+        "Shot rewound by {} ticks against {} hitboxes",
+        event.rewind_ticks,
+        event.hitboxes.len()
+    );
+    rewound.hitboxes = event.hitboxes.clone();
+    rewound.timer = Timer::new(REWOUND_HITBOXES_DURATION, TimerMode::Once);
+}
+
+fn draw_rewound_hitboxes(
+    mut gizmos: Gizmos,
+    time: Res<Time>,
+    mut rewound: ResMut<RewoundHitboxes>,
+) {
+    if rewound.timer.tick(time.delta()).finished() {
+        return;
+    }
+
+    for &hitbox in rewound.hitboxes.iter() {
+        gizmos.cuboid(hitbox, Color::srgb(1.0, 0.2, 0.2));
+    }
+}
@@ -20,8 +20,15 @@ impl Plugin for GameGuiPlugin {
         app.add_systems(OnEnter(GameStates::Playing), setup_gui);
         app.add_systems(
             Update,
-            (handle_player_joined, handle_player_died, handle_player_left)
-                .run_if(in_state(GameStates::Playing)),
+            (
+                handle_player_joined,
+                handle_player_died,
+                handle_player_left,
+                handle_server_message,
+                update_shutdown_banner,
+                update_scoreboard,
+            )
+                .run_if(in_state(InGame)),
         );
     }
 }
@@ -40,6 +47,15 @@ struct GuiChat;
 #[derive(Component, Clone, Copy, Debug)]
 struct GuiChatEntry;
 
+#[derive(Component, Clone, Copy, Debug)]
+struct GuiShutdownBanner;
+
+#[derive(Component, Clone, Copy, Debug)]
+struct GuiScoreboard;
+
+/// The key that shows the scoreboard while it is held
+const SCOREBOARD_KEY: KeyCode = KeyCode::Tab;
+
 fn setup_gui(
     mut commands: Commands,
     mut player_info_map: ResMut<PlayerInfoMap>,
@@ -78,12 +94,50 @@ fn setup_gui(
                 ..default()
             },
         ));
+
+    commands.spawn((
+        Name::new("GuiShutdownBanner"),
+        GuiShutdownBanner,
+        Text::new(""),
+        TextFont {
+            font_size: 33.0,
+            ..default()
+        },
+        TextColor(Color::srgb(0.95, 0.35, 0.35)),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(20.0),
+            width: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        TextLayout::new_with_justify(JustifyText::Center),
+        StateScoped(GameStates::Playing),
+    ));
+
+    commands.spawn((
+        Name::new("GuiScoreboard"),
+        GuiScoreboard,
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(20.0),
+            left: Val::Percent(30.0),
+            width: Val::Percent(40.0),
+            flex_direction: FlexDirection::Column,
+            padding: UiRect::all(Val::Px(10.0)),
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+        Visibility::Hidden,
+        StateScoped(GameStates::Playing),
+    ));
 }
 
 fn handle_player_joined(
     mut commands: Commands,
     mut joined: EventReader<PlayerJoinedEvent>,
     mut player_info_map: ResMut<PlayerInfoMap>,
+    local_player: Option<Res<LocalPlayer>>,
     q_chat: Query<Entity, With<GuiChat>>,
 ) {
     for event in joined.read() {
@@ -91,7 +145,11 @@ fn handle_player_joined(
             continue;
         }
 
-        if let Ok(entity) = q_chat.get_single() {
+        // The server tells the whole room about a join, the player knows it joined
+        let is_local = local_player
+            .as_deref()
+            .is_some_and(|local| **local == event.client_id);
+        if let Some(entity) = q_chat.get_single().ok().filter(|_| !is_local) {
             let child = commands
                 .spawn((
                     Name::new("GuiChatEntry"),
@@ -160,3 +218,135 @@ fn handle_player_left(
         }
     }
 }
+
+fn handle_server_message(
+    mut commands: Commands,
+    mut messages: EventReader<ServerMessageEvent>,
+    q_chat: Query<Entity, With<GuiChat>>,
+) {
+    for event in messages.read() {
+        if let Ok(entity) = q_chat.get_single() {
+            let child = commands
+                .spawn((
+                    Name::new("GuiChatEntry"),
+                    GuiChatEntry,
+                    Text::new(format!("[Server] {}", event.message)),
+                    DespawnAfter::new(10.0),
+                ))
+                .id();
+
+            commands.entity(entity).add_child(child);
+        }
+    }
+}
+
+fn update_shutdown_banner(
+    shutdown: Option<Res<ServerShutdown>>,
+    mut q_banner: Query<&mut Text, With<GuiShutdownBanner>>,
+) {
+    let banner = match shutdown {
+        Some(shutdown) => format!(
+            "{} in {:.0} seconds",
+            shutdown.reason,
+            shutdown.countdown.remaining_secs().ceil()
+        ),
+        None => String::new(),
+    };
+
+    for mut text in q_banner.iter_mut() {
+        if text.0 != banner {
+            text.0 = banner.clone();
+        }
+    }
+}
+
+fn update_scoreboard(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    q_stats: Query<&PlayerStats>,
+    q_changed: Query<(), Changed<PlayerStats>>,
+    mut removed: RemovedComponents<PlayerStats>,
+    lobby: Option<Res<Lobby>>,
+    mut q_scoreboard: Query<(Entity, &mut Visibility), With<GuiScoreboard>>,
+) {
+    let Ok((entity, mut visibility)) = q_scoreboard.get_single_mut() else {
+        return;
+    };
+
+    let shown = keys.pressed(SCOREBOARD_KEY);
+    visibility.set_if_neq(match shown {
+        true => Visibility::Inherited,
+        false => Visibility::Hidden,
+    });
+
+    let changed = !q_changed.is_empty()
+        || removed.read().count() > 0
+        || lobby.as_ref().is_some_and(|lobby| lobby.is_changed());
+    if !shown || !(changed || keys.just_pressed(SCOREBOARD_KEY)) {
+        return;
+    }
+
+    let mut players = q_stats.iter().collect::<Vec<_>>();
+    players.sort_by(|a, b| b.score.cmp(&a.score).then(a.name.cmp(&b.name)));
+
+    // The score of a team is the sum of the scores of its players
+    let teams = lobby
+        .filter(|lobby| lobby.settings.mode == TEAM_DEATHMATCH)
+        .map(|lobby| {
+            Team::ALL
+                .iter()
+                .map(|team| {
+                    let score = players
+                        .iter()
+                        .filter(|stats| {
+                            lobby
+                                .player(stats.client_id)
+                                .is_some_and(|player| player.team == *team)
+                        })
+                        .map(|stats| stats.score)
+                        .sum::<u32>();
+                    format!("{:?} {}", team, score)
+                })
+                .collect::<Vec<_>>()
+                .join(" - ")
+        });
+
+    commands.entity(entity).despawn_descendants();
+    commands.entity(entity).with_children(|parent| {
+        if let Some(teams) = teams {
+            parent.spawn(Text::new(teams));
+        }
+        let header = (
+            "Player".to_string(),
+            "Score".to_string(),
+            "Ping".to_string(),
+        );
+        let rows = players.iter().map(|stats| {
+            (
+                stats.name.clone(),
+                stats.score.to_string(),
+                format!("{} ms", stats.ping_ms),
+            )
+        });
+
+        for (name, score, ping) in std::iter::once(header).chain(rows) {
+            parent
+                .spawn(Node {
+                    width: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Row,
+                    ..default()
+                })
+                .with_children(|row| {
+                    for (text, width) in [(name, 60.0), (score, 20.0), (ping, 20.0)] {
+                        row.spawn((
+                            Text::new(text),
+                            Node {
+                                width: Val::Percent(width),
+                                ..default()
+                            },
+                        ));
+                    }
+                });
+        }
+    });
+}
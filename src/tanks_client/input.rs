@@ -1,32 +1,58 @@
+use std::time::Duration;
+
 use bevy::prelude::*;
 use leafwing_input_manager::prelude::*;
 
 use crate::prelude::*;
 
 pub mod prelude {
-    pub use super::TankInputPlugin;
+    pub use super::{PlayerInputAction, TankInputPlugin, TankInputSet};
 }
 
 #[derive(Actionlike, Clone, Debug, Copy, PartialEq, Eq, Hash, Reflect)]
-enum PlayerInputAction {
+pub enum PlayerInputAction {
     #[actionlike(DualAxis)]
     Move,
+    Forward,
+    Backward,
+    Left,
+    Right,
     Fire,
     Leave,
 }
 
 impl PlayerInputAction {
-    fn default_input_map() -> InputMap<Self> {
-        InputMap::default()
-            .with_dual_axis(PlayerInputAction::Move, VirtualDPad::wasd())
-            .with(Self::Fire, KeyCode::Space)
-            .with(Self::Leave, KeyCode::Escape)
+    fn input_map(keybindings: &Keybindings) -> InputMap<Self> {
+        // The gamepad bindings are not rebindable
+        let mut input_map = InputMap::default()
+            .with_dual_axis(Self::Move, GamepadStick::LEFT)
+            .with(Self::Fire, GamepadButton::RightTrigger2)
+            .with(Self::Fire, GamepadButton::LeftTrigger2)
+            .with(Self::Leave, GamepadButton::Start);
+
+        for (control, action) in [
+            (ControlAction::MoveForward, Self::Forward),
+            (ControlAction::MoveBackward, Self::Backward),
+            (ControlAction::SteerLeft, Self::Left),
+            (ControlAction::SteerRight, Self::Right),
+            (ControlAction::Fire, Self::Fire),
+            (ControlAction::Leave, Self::Leave),
+        ] {
+            if let Some(binding) = keybindings.get(control) {
+                insert_binding(&mut input_map, action, binding);
+            }
+        }
+
+        input_map
     }
 }
 
 #[derive(Component, Clone, Debug, Copy, Deref, DerefMut, Default)]
 struct PlayerInputMove(Vec2);
 
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TankInputSet;
+
 pub struct TankInputPlugin;
 
 impl Plugin for TankInputPlugin {
@@ -36,46 +62,85 @@ impl Plugin for TankInputPlugin {
         app.add_systems(
             Update,
             (update_player_input)
+                .in_set(TankInputSet)
                 .run_if(in_state(GameStates::Playing))
                 .run_if(resource_exists::<LocalPlayerEntity>),
         );
         app.add_systems(
             Update,
             (update_player_spawn)
+                .in_set(TankInputSet)
                 .run_if(in_state(GameStates::Playing))
                 .run_if(not(resource_exists::<LocalPlayerEntity>)),
         );
         app.add_systems(
             Update,
-            (update_player_leave).run_if(in_state(GameStates::Playing)),
+            (update_player_leave)
+                .in_set(TankInputSet)
+                .run_if(in_state(GameStates::Playing)),
+        );
+        app.add_systems(
+            Update,
+            (update_input_map)
+                .run_if(in_state(GameStates::Playing))
+                .run_if(resource_changed::<Keybindings>),
         );
     }
 }
 
-fn setup_input(mut commands: Commands) {
+fn setup_input(mut commands: Commands, keybindings: Res<Keybindings>) {
     commands.spawn((
         Name::new("PlayerInput"),
         PlayerInputMove::default(),
-        InputManagerBundle::with_map(PlayerInputAction::default_input_map()),
+        InputManagerBundle::with_map(PlayerInputAction::input_map(&keybindings)),
         StateScoped(GameStates::Playing),
     ));
 }
 
+fn update_input_map(
+    keybindings: Res<Keybindings>,
+    mut q_input: Query<&mut InputMap<PlayerInputAction>>,
+) {
+    for mut input_map in q_input.iter_mut() {
+        *input_map = PlayerInputAction::input_map(&keybindings);
+    }
+}
+
+// The movement is sent at most once per server tick, the changes in between are coalesced into
+// the latest axes
 fn update_player_input(
     mut input: EventWriter<PlayerInputEvent>,
     mut fire: EventWriter<PlayerFireEvent>,
     mut q_input: Query<(&mut PlayerInputMove, &ActionState<PlayerInputAction>)>,
+    tick: Res<ServerTick>,
+    time: Res<Time<Real>>,
+    mut last_sent: Local<Option<Duration>>,
 ) {
+    let interval = Duration::from_secs_f64(1.0 / TICK_RATE);
+    let can_send = last_sent.is_none_or(|sent| time.elapsed() >= sent + interval);
+
     for (mut prev, action) in q_input.iter_mut() {
-        let movement = action.clamped_axis_pair(&PlayerInputAction::Move);
+        let button_value = |a: PlayerInputAction, b: PlayerInputAction| {
+            action.pressed(&a) as i32 as f32 - action.pressed(&b) as i32 as f32
+        };
+
+        let movement = (Vec2::new(
+            button_value(PlayerInputAction::Right, PlayerInputAction::Left),
+            button_value(PlayerInputAction::Forward, PlayerInputAction::Backward),
+        ) + action.clamped_axis_pair(&PlayerInputAction::Move))
+        .clamp(Vec2::NEG_ONE, Vec2::ONE);
 
-        if movement.x != prev.x || movement.y != prev.y {
+        if can_send && (movement.x != prev.x || movement.y != prev.y) {
             **prev = movement;
-            input.send(PlayerInputEvent(movement));
+            *last_sent = Some(time.elapsed());
+            input.send(PlayerInputEvent {
+                axes: movement,
+                tick: *tick,
+            });
         }
 
         if action.just_pressed(&PlayerInputAction::Fire) {
-            fire.send(PlayerFireEvent);
+            fire.send(PlayerFireEvent { tick: *tick });
         }
     }
 }
@@ -0,0 +1,264 @@
+//! Rebindable controls for the client
+
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub mod prelude {
+    pub use super::{
+        insert_axis_binding, insert_binding, insert_dual_axis_binding, ControlAction, InputBinding,
+        Keybindings,
+    };
+}
+
+/// A single input that can be bound to a control action
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum InputBinding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    WheelUp,
+    WheelDown,
+    MouseMove,
+    Wheel,
+}
+
+impl InputBinding {
+    /// A short human readable name of the binding
+    pub fn label(&self) -> String {
+        match self {
+            InputBinding::Key(key) => {
+                let name = format!("{:?}", key);
+                name.strip_prefix("Key")
+                    .or_else(|| name.strip_prefix("Digit"))
+                    .unwrap_or(&name)
+                    .to_string()
+            }
+            InputBinding::Mouse(button) => format!("Mouse {:?}", button),
+            InputBinding::WheelUp => "Wheel Up".to_string(),
+            InputBinding::WheelDown => "Wheel Down".to_string(),
+            InputBinding::MouseMove => "Mouse Move".to_string(),
+            InputBinding::Wheel => "Mouse Wheel".to_string(),
+        }
+    }
+
+    /// Whether both bindings react to the same input
+    pub fn overlaps(&self, other: &InputBinding) -> bool {
+        match (self, other) {
+            (InputBinding::Wheel, InputBinding::WheelUp | InputBinding::WheelDown)
+            | (InputBinding::WheelUp | InputBinding::WheelDown, InputBinding::Wheel) => true,
+            _ => self == other,
+        }
+    }
+}
+
+/// All the actions that can be rebound from the controls menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Component)]
+pub enum ControlAction {
+    MoveForward,
+    MoveBackward,
+    SteerLeft,
+    SteerRight,
+    Fire,
+    Leave,
+    CameraLeft,
+    CameraRight,
+    CameraUp,
+    CameraDown,
+    CameraOrbit,
+    Zoom,
+    ZoomIn,
+    ZoomOut,
+}
+
+impl ControlAction {
+    /// The actions that control the tank
+    pub const TANK: [ControlAction; 6] = [
+        ControlAction::MoveForward,
+        ControlAction::MoveBackward,
+        ControlAction::SteerLeft,
+        ControlAction::SteerRight,
+        ControlAction::Fire,
+        ControlAction::Leave,
+    ];
+
+    /// The actions that control the camera
+    pub const CAMERA: [ControlAction; 8] = [
+        ControlAction::CameraLeft,
+        ControlAction::CameraRight,
+        ControlAction::CameraUp,
+        ControlAction::CameraDown,
+        ControlAction::CameraOrbit,
+        ControlAction::Zoom,
+        ControlAction::ZoomIn,
+        ControlAction::ZoomOut,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ControlAction::MoveForward => "Forward",
+            ControlAction::MoveBackward => "Backward",
+            ControlAction::SteerLeft => "Steer Left",
+            ControlAction::SteerRight => "Steer Right",
+            ControlAction::Fire => "Fire",
+            ControlAction::Leave => "Leave",
+            ControlAction::CameraLeft => "Orbit Left",
+            ControlAction::CameraRight => "Orbit Right",
+            ControlAction::CameraUp => "Orbit Up",
+            ControlAction::CameraDown => "Orbit Down",
+            ControlAction::CameraOrbit => "Orbit",
+            ControlAction::Zoom => "Zoom",
+            ControlAction::ZoomIn => "Zoom In",
+            ControlAction::ZoomOut => "Zoom Out",
+        }
+    }
+
+    /// The mouse axis of the actions that are driven by an axis. A key or a mouse button bound to
+    /// them has to be held while the axis is moved.
+    pub fn axis(&self) -> Option<InputBinding> {
+        match self {
+            ControlAction::CameraOrbit => Some(InputBinding::MouseMove),
+            ControlAction::Zoom => Some(InputBinding::Wheel),
+            _ => None,
+        }
+    }
+
+    /// Whether the binding can be used for this action
+    pub fn accepts(&self, binding: InputBinding) -> bool {
+        match binding {
+            InputBinding::Key(_) | InputBinding::Mouse(_) => true,
+            InputBinding::WheelUp | InputBinding::WheelDown => self.axis().is_none(),
+            InputBinding::MouseMove | InputBinding::Wheel => self.axis() == Some(binding),
+        }
+    }
+}
+
+/// The current bindings of the control actions
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Keybindings(pub HashMap<ControlAction, InputBinding>);
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        Self(HashMap::from([
+            (ControlAction::MoveForward, InputBinding::Key(KeyCode::KeyW)),
+            (
+                ControlAction::MoveBackward,
+                InputBinding::Key(KeyCode::KeyS),
+            ),
+            (ControlAction::SteerLeft, InputBinding::Key(KeyCode::KeyA)),
+            (ControlAction::SteerRight, InputBinding::Key(KeyCode::KeyD)),
+            (ControlAction::Fire, InputBinding::Key(KeyCode::Space)),
+            (ControlAction::Leave, InputBinding::Key(KeyCode::Escape)),
+            (
+                ControlAction::CameraLeft,
+                InputBinding::Key(KeyCode::ArrowLeft),
+            ),
+            (
+                ControlAction::CameraRight,
+                InputBinding::Key(KeyCode::ArrowRight),
+            ),
+            (ControlAction::CameraUp, InputBinding::Key(KeyCode::ArrowUp)),
+            (
+                ControlAction::CameraDown,
+                InputBinding::Key(KeyCode::ArrowDown),
+            ),
+            (ControlAction::CameraOrbit, InputBinding::MouseMove),
+            (ControlAction::Zoom, InputBinding::Wheel),
+            (ControlAction::ZoomIn, InputBinding::Key(KeyCode::Equal)),
+            (ControlAction::ZoomOut, InputBinding::Key(KeyCode::Minus)),
+        ]))
+    }
+}
+
+impl Keybindings {
+    pub fn get(&self, action: ControlAction) -> Option<InputBinding> {
+        self.0.get(&action).copied()
+    }
+
+    pub fn set(&mut self, action: ControlAction, binding: InputBinding) {
+        self.0.insert(action, binding);
+    }
+
+    /// Replaces the missing bindings and the ones an action can't use with the defaults, for
+    /// example after a new action was added
+    pub fn sanitized(mut self) -> Self {
+        for (action, binding) in Keybindings::default().0 {
+            match self.get(action) {
+                Some(current) if action.accepts(current) => {}
+                _ => self.set(action, binding),
+            }
+        }
+
+        self
+    }
+
+    /// The actions that share their binding with at least one other action
+    pub fn conflicts(&self) -> HashSet<ControlAction> {
+        let mut conflicts = HashSet::new();
+
+        for (action, binding) in self.0.iter() {
+            for (other, other_binding) in self.0.iter() {
+                if action != other && binding.overlaps(other_binding) {
+                    conflicts.insert(*action);
+                }
+            }
+        }
+
+        conflicts
+    }
+}
+
+/// Adds the given binding to the input map of an action
+pub fn insert_binding<A: Actionlike>(map: &mut InputMap<A>, action: A, binding: InputBinding) {
+    match binding {
+        InputBinding::Key(key) => {
+            map.insert(action, key);
+        }
+        InputBinding::Mouse(button) => {
+            map.insert(action, button);
+        }
+        InputBinding::WheelUp => {
+            map.insert(action, MouseScrollDirection::UP);
+        }
+        InputBinding::WheelDown => {
+            map.insert(action, MouseScrollDirection::DOWN);
+        }
+        // The mouse axes only drive the axis actions
+        InputBinding::MouseMove | InputBinding::Wheel => {}
+    }
+}
+
+/// Adds the given binding to the input map of an axis action, a button is held while scrolling
+pub fn insert_axis_binding<A: Actionlike>(map: &mut InputMap<A>, action: A, binding: InputBinding) {
+    let chord = match binding {
+        InputBinding::Wheel => {
+            map.insert_axis(action, MouseScrollAxis::Y);
+            return;
+        }
+        InputBinding::Key(key) => InputChord::from_single(key),
+        InputBinding::Mouse(button) => InputChord::from_single(button),
+        _ => return,
+    };
+    map.insert_axis(action, chord.with_axis(MouseScrollAxis::Y));
+}
+
+/// Adds the given binding to the input map of a dual axis action, a button is held while the
+/// mouse moves
+pub fn insert_dual_axis_binding<A: Actionlike>(
+    map: &mut InputMap<A>,
+    action: A,
+    binding: InputBinding,
+) {
+    let chord = match binding {
+        InputBinding::MouseMove => {
+            map.insert_dual_axis(action, MouseMove::default());
+            return;
+        }
+        InputBinding::Key(key) => InputChord::from_single(key),
+        InputBinding::Mouse(button) => InputChord::from_single(button),
+        _ => return,
+    };
+    map.insert_dual_axis(action, chord.with_dual_axis(MouseMove::default()));
+}
@@ -0,0 +1,111 @@
+//! The level of the room, it is replaced when the server changes the map
+
+use std::path::Path;
+
+use bevy::prelude::*;
+use blenvy::*;
+
+use crate::prelude::*;
+
+pub mod prelude {
+    pub use super::{ClientLevelPlugin, ClientLevelSet};
+}
+
+/// The ClientLevel marks the level the client spawned with the path of its blueprint
+#[derive(Component, Clone, Debug)]
+struct ClientLevel(String);
+
+// Tag component used to mark the screen that covers the level while it loads
+#[derive(Component)]
+struct LoadingScreen;
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClientLevelSet;
+
+/// This plugin spawns the blueprint of the `CurrentLevel` and replaces it when the server changes
+/// the map, without a reconnect. A loading screen is shown while the blueprint streams in.
+#[derive(Debug, Clone)]
+pub struct ClientLevelPlugin;
+
+impl Plugin for ClientLevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CurrentLevel>();
+
+        app.add_systems(
+            Update,
+            (spawn_current_level, update_loading_screen)
+                .chain()
+                .in_set(ClientLevelSet)
+                .run_if(in_state(GameStates::Playing)),
+        );
+    }
+}
+
+fn spawn_current_level(
+    mut commands: Commands,
+    current_level: Res<CurrentLevel>,
+    q_level: Query<(Entity, &ClientLevel)>,
+) {
+    // The level is not known until the server replicated it
+    if current_level.is_empty() || q_level.iter().any(|(_, level)| level.0 == **current_level) {
+        return;
+    }
+
+    for (entity, _) in q_level.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    info!("Loading the level {}", **current_level);
+    commands.spawn((
+        Name::new("Level"),
+        BlueprintInfo::from_path(current_level.as_str()), // all we need is a Blueprint info...
+        SpawnBlueprint, // and spawnblueprint to tell blenvy to spawn the blueprint now
+        HideUntilReady, // only reveal the level once it is ready
+        GameWorldTag,
+        ClientLevel(current_level.0.clone()),
+        StateScoped(GameStates::Playing),
+    ));
+}
+
+fn update_loading_screen(
+    mut commands: Commands,
+    q_loading: Query<&ClientLevel, Without<BlueprintInstanceReady>>,
+    q_screen: Query<Entity, With<LoadingScreen>>,
+) {
+    match (q_loading.iter().next(), q_screen.is_empty()) {
+        (Some(ClientLevel(path)), true) => {
+            let name = Path::new(path)
+                .file_stem()
+                .and_then(|name| name.to_str())
+                .unwrap_or("level");
+
+            commands.spawn((
+                Name::new("LoadingScreen"),
+                LoadingScreen,
+                Text::new(format!("Loading {}...", name)),
+                TextFont {
+                    font_size: 33.0,
+                    ..default()
+                },
+                Node {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    padding: UiRect::top(Val::Percent(40.0)),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                TextLayout::new_with_justify(JustifyText::Center),
+                BackgroundColor(Color::BLACK),
+                GlobalZIndex(1),
+                StateScoped(GameStates::Playing),
+            ));
+        }
+        (None, false) => {
+            for entity in q_screen.iter() {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,428 @@
+//! The lobby screen, the players of a room pick their team, color and class before the match
+
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+
+use crate::prelude::*;
+use crate::tanks_client::main_menu::{handle_button_interact, NORMAL_BUTTON, TEXT_COLOR};
+
+pub mod prelude {
+    pub use super::{Lobby, LobbyMenuPlugin, LobbyMenuSet};
+}
+
+/// The game modes the host can pick
+const LOBBY_MODES: [&str; 2] = ["deathmatch", TEAM_DEATHMATCH];
+
+/// How much each press changes the time limit (in seconds)
+const TIME_LIMIT_STEP_SECS: u32 = 60;
+
+/// The Lobby resource holds the latest lobby of the room, it is inserted once the server
+/// accepted the join
+#[derive(Resource, Debug, Clone, Deref)]
+pub struct Lobby(pub LobbyStateEvent);
+
+impl Lobby {
+    /// The player that picks the settings of the match
+    pub fn host(&self) -> Option<ClientId> {
+        self.players.first().map(|player| player.client_id)
+    }
+
+    pub fn player(&self, client_id: ClientId) -> Option<&LobbyPlayer> {
+        self.players
+            .iter()
+            .find(|player| player.client_id == client_id)
+    }
+}
+
+// All the actions of the lobby buttons
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+enum LobbyButton {
+    Team,
+    Color,
+    Class,
+    Ready,
+    Mode,
+    Map,
+    TimeLimitDown,
+    TimeLimitUp,
+    Leave,
+}
+
+// Tag component used to mark the text of a lobby button
+#[derive(Component, Clone, Copy, Debug)]
+struct LobbyButtonLabel;
+
+#[derive(Component, Clone, Copy, Debug)]
+struct LobbySettingsText;
+
+#[derive(Component, Clone, Copy, Debug)]
+struct LobbyPlayerList;
+
+// Tag component used to mark the buttons that only the host can use
+#[derive(Component, Clone, Copy, Debug)]
+struct LobbyHostControls;
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LobbyMenuSet;
+
+/// This plugin keeps the `Lobby` resource up to date and shows the lobby screen. The client goes
+/// to `GameStates::Playing` when the match of its room starts and back to `GameStates::Lobby`
+/// when it ends.
+#[derive(Debug, Clone)]
+pub struct LobbyMenuPlugin;
+
+impl Plugin for LobbyMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            handle_lobby_state
+                .in_set(LobbyMenuSet)
+                .run_if(in_state(InGame)),
+        );
+        app.add_systems(OnExit(InGame), remove_lobby);
+
+        app.add_systems(OnEnter(GameStates::Lobby), spawn_lobby_ui);
+        app.add_systems(
+            Update,
+            (
+                handle_lobby_buttons,
+                handle_lobby_leave,
+                handle_button_interact,
+                update_lobby_ui.run_if(resource_exists::<Lobby>),
+            )
+                .in_set(LobbyMenuSet)
+                .run_if(in_state(GameStates::Lobby)),
+        );
+    }
+}
+
+fn handle_lobby_state(
+    mut commands: Commands,
+    mut lobby_state: EventReader<LobbyStateEvent>,
+    state: Res<State<GameStates>>,
+    mut next_state: ResMut<NextState<GameStates>>,
+) {
+    let Some(event) = lobby_state.read().last() else {
+        return;
+    };
+
+    match (state.get(), event.in_match) {
+        (GameStates::Lobby, true) => next_state.set(GameStates::Playing),
+        (GameStates::Playing, false) => next_state.set(GameStates::Lobby),
+        _ => {}
+    }
+
+    commands.insert_resource(Lobby(event.clone()));
+}
+
+fn remove_lobby(mut commands: Commands) {
+    commands.remove_resource::<Lobby>();
+}
+
+fn spawn_lobby_ui(mut commands: Commands) {
+    let button_node = Node {
+        width: Val::Px(220.0),
+        height: Val::Px(55.0),
+        margin: UiRect::all(Val::Px(10.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let button_text_style = (
+        TextFont {
+            font_size: 24.0,
+            ..default()
+        },
+        TextColor(TEXT_COLOR),
+    );
+    let row_node = Node {
+        flex_direction: FlexDirection::Row,
+        justify_content: JustifyContent::Center,
+        ..default()
+    };
+
+    let spawn_button = |parent: &mut ChildBuilder, button: LobbyButton| {
+        parent
+            .spawn((
+                Button,
+                button_node.clone(),
+                BackgroundColor(NORMAL_BUTTON),
+                button,
+            ))
+            .with_child((LobbyButtonLabel, Text::default(), button_text_style.clone()));
+    };
+
+    commands.spawn((
+        Name::new("CameraUI"),
+        Camera2d,
+        StateScoped(GameStates::Lobby),
+    ));
+
+    commands
+        .spawn((
+            Name::new("LobbyMenu"),
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            StateScoped(GameStates::Lobby),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new("Lobby"),
+                TextFont {
+                    font_size: 40.0,
+                    ..default()
+                },
+                TextColor(TEXT_COLOR),
+            ));
+            parent.spawn((
+                LobbySettingsText,
+                Text::default(),
+                button_text_style.clone(),
+                Node {
+                    margin: UiRect::all(Val::Px(10.0)),
+                    ..default()
+                },
+            ));
+            parent.spawn((
+                LobbyPlayerList,
+                Node {
+                    width: Val::Px(600.0),
+                    min_height: Val::Px(200.0),
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(10.0)),
+                    ..default()
+                },
+                BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+            ));
+            parent.spawn(row_node.clone()).with_children(|row| {
+                for button in [
+                    LobbyButton::Team,
+                    LobbyButton::Color,
+                    LobbyButton::Class,
+                    LobbyButton::Ready,
+                ] {
+                    spawn_button(row, button);
+                }
+            });
+            parent
+                .spawn((LobbyHostControls, row_node.clone()))
+                .with_children(|row| {
+                    for button in [
+                        LobbyButton::Mode,
+                        LobbyButton::Map,
+                        LobbyButton::TimeLimitDown,
+                        LobbyButton::TimeLimitUp,
+                    ] {
+                        spawn_button(row, button);
+                    }
+                });
+            spawn_button(parent, LobbyButton::Leave);
+        });
+}
+
+/// The item after the current one, the first item when the current one is not in the list
+fn next_of<T: Copy + PartialEq>(items: &[T], current: T) -> T {
+    let index = items
+        .iter()
+        .position(|item| *item == current)
+        .map_or(0, |index| (index + 1) % items.len());
+    items[index]
+}
+
+fn handle_lobby_buttons(
+    q_button: Query<(&Interaction, &LobbyButton), Changed<Interaction>>,
+    lobby: Option<Res<Lobby>>,
+    local_player: Res<LocalPlayer>,
+    mut select: EventWriter<LobbySelectEvent>,
+    mut ready: EventWriter<LobbyReadyEvent>,
+    mut match_settings: EventWriter<MatchSettingsEvent>,
+    mut next_state: ResMut<NextState<GameStates>>,
+) {
+    for (interaction, button) in q_button.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        if *button == LobbyButton::Leave {
+            next_state.set(GameStates::MainMenu);
+            continue;
+        }
+
+        let Some(lobby) = lobby.as_ref() else {
+            continue;
+        };
+        let Some(player) = lobby.player(**local_player) else {
+            continue;
+        };
+
+        let mut pick = LobbySelectEvent {
+            team: player.team,
+            color: player.color,
+            class: player.class,
+        };
+        let mut settings = lobby.settings.clone();
+        match button {
+            LobbyButton::Team => pick.team = next_of(&Team::ALL, player.team),
+            LobbyButton::Color => pick.color = next_of(&TankColor::PALETTE, player.color),
+            LobbyButton::Class => pick.class = next_of(&TankClass::ALL, player.class),
+            LobbyButton::Ready => {
+                ready.send(LobbyReadyEvent {
+                    ready: !player.ready,
+                });
+            }
+            LobbyButton::Mode => {
+                settings.mode = next_of(&LOBBY_MODES, settings.mode.as_str()).to_string();
+            }
+            // The host picks from the map rotation of the server
+            LobbyButton::Map if !lobby.maps.is_empty() => {
+                let maps = lobby.maps.iter().map(String::as_str).collect::<Vec<_>>();
+                settings.map = next_of(&maps, settings.map.as_str()).to_string();
+            }
+            LobbyButton::Map => {}
+            LobbyButton::TimeLimitDown => {
+                settings.time_limit_secs = settings
+                    .time_limit_secs
+                    .saturating_sub(TIME_LIMIT_STEP_SECS);
+            }
+            LobbyButton::TimeLimitUp => {
+                settings.time_limit_secs =
+                    (settings.time_limit_secs + TIME_LIMIT_STEP_SECS).min(MAX_TIME_LIMIT_SECS);
+            }
+            LobbyButton::Leave => {}
+        }
+
+        if (pick.team, pick.color, pick.class) != (player.team, player.color, player.class) {
+            select.send(pick);
+        }
+        if settings != lobby.settings {
+            match_settings.send(MatchSettingsEvent { settings });
+        }
+    }
+}
+
+fn handle_lobby_leave(
+    keys: Res<ButtonInput<KeyCode>>,
+    q_gamepad: Query<&Gamepad>,
+    mut next_state: ResMut<NextState<GameStates>>,
+) {
+    let back = keys.just_pressed(KeyCode::Escape)
+        || q_gamepad
+            .iter()
+            .any(|gamepad| gamepad.just_pressed(GamepadButton::East));
+
+    if back {
+        next_state.set(GameStates::MainMenu);
+    }
+}
+
+fn time_limit_text(time_limit_secs: u32) -> String {
+    match time_limit_secs {
+        0 => "No time limit".to_string(),
+        secs => format!("{} min", secs.div_ceil(60)),
+    }
+}
+
+fn update_lobby_ui(
+    mut commands: Commands,
+    lobby: Res<Lobby>,
+    local_player: Res<LocalPlayer>,
+    q_added: Query<(), Added<LobbyPlayerList>>,
+    q_list: Query<Entity, With<LobbyPlayerList>>,
+    q_button: Query<&LobbyButton>,
+    mut q_label: Query<(&Parent, &mut Text, &mut TextColor), With<LobbyButtonLabel>>,
+    mut q_settings: Query<&mut Text, (With<LobbySettingsText>, Without<LobbyButtonLabel>)>,
+    mut q_host: Query<&mut Visibility, With<LobbyHostControls>>,
+) {
+    // The screen is spawned again each time the match ends
+    if !lobby.is_changed() && q_added.is_empty() {
+        return;
+    }
+
+    let is_host = lobby.host() == Some(**local_player);
+    for mut visibility in q_host.iter_mut() {
+        *visibility = match is_host {
+            true => Visibility::Inherited,
+            false => Visibility::Hidden,
+        };
+    }
+
+    let settings = &lobby.settings;
+    for mut text in q_settings.iter_mut() {
+        text.0 = format!(
+            "{} on {} - {}",
+            settings.mode,
+            settings.map,
+            time_limit_text(settings.time_limit_secs)
+        );
+    }
+
+    let player = lobby.player(**local_player);
+    for (parent, mut text, mut color) in q_label.iter_mut() {
+        let Ok(button) = q_button.get(parent.get()) else {
+            continue;
+        };
+
+        text.0 = match (button, player) {
+            (LobbyButton::Team, Some(player)) => format!("Team: {:?}", player.team),
+            (LobbyButton::Color, _) => "Color".to_string(),
+            (LobbyButton::Class, Some(player)) => format!("Class: {:?}", player.class),
+            (LobbyButton::Ready, Some(player)) if player.ready => "Ready".to_string(),
+            (LobbyButton::Ready, _) => "Not ready".to_string(),
+            (LobbyButton::Mode, _) => "Mode".to_string(),
+            (LobbyButton::Map, _) => "Map".to_string(),
+            (LobbyButton::TimeLimitDown, _) => "- 1 min".to_string(),
+            (LobbyButton::TimeLimitUp, _) => "+ 1 min".to_string(),
+            (LobbyButton::Leave, _) => "Leave".to_string(),
+            (_, None) => String::new(),
+        };
+        color.0 = match (button, player) {
+            (LobbyButton::Color, Some(player)) => player.color,
+            _ => TEXT_COLOR,
+        };
+    }
+
+    for entity in q_list.iter() {
+        commands.entity(entity).despawn_descendants();
+        commands.entity(entity).with_children(|parent| {
+            for (index, player) in lobby.players.iter().enumerate() {
+                let host = match index {
+                    0 => " (host)",
+                    _ => "",
+                };
+                let ready = match player.ready {
+                    true => "Ready",
+                    false => "",
+                };
+
+                parent
+                    .spawn(Node {
+                        width: Val::Percent(100.0),
+                        flex_direction: FlexDirection::Row,
+                        ..default()
+                    })
+                    .with_children(|row| {
+                        for (text, width, color) in [
+                            (format!("{}{}", player.name, host), 40.0, player.color),
+                            (format!("{:?}", player.team), 20.0, TEXT_COLOR),
+                            (format!("{:?}", player.class), 20.0, TEXT_COLOR),
+                            (ready.to_string(), 20.0, TEXT_COLOR),
+                        ] {
+                            row.spawn((
+                                Text::new(text),
+                                TextColor(color),
+                                Node {
+                                    width: Val::Percent(width),
+                                    ..default()
+                                },
+                            ));
+                        }
+                    });
+            }
+        });
+    }
+}
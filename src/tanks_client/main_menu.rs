@@ -1,16 +1,32 @@
-use bevy::{prelude::*, ui::FocusPolicy};
+use std::path::PathBuf;
+
+use bevy::{
+    input::mouse::{AccumulatedMouseMotion, MouseWheel},
+    prelude::*,
+    render::camera::RenderTarget,
+    ui::FocusPolicy,
+};
 use bevy_simple_text_input::*;
+use serde::{Deserialize, Serialize};
 
 use crate::prelude::*;
+use crate::tanks_client::renderer::{render_target_image, tank_material, TankMaterial};
 
 pub mod prelude {
     pub use super::{ClientInfo, MainMenuPlugin, PlayButtonPressed};
 }
 
-#[derive(Resource, Debug, Clone)]
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ClientInfo {
     pub address: String,
     pub name: String,
+    /// The address of the master server used by the server browser
+    pub master_address: String,
+    /// The room to join on the server, None joins the default room. The rooms do not outlive
+    /// the server, so it is not saved with the settings.
+    #[serde(skip)]
+    pub room: Option<RoomId>,
 }
 
 impl Default for ClientInfo {
@@ -18,11 +34,13 @@ impl Default for ClientInfo {
         Self {
             address: "127.0.0.1".to_string(),
             name: "Player".to_string(),
+            master_address: format!("127.0.0.1:{}", DEFAULT_MASTER_PORT),
+            room: None,
         }
     }
 }
 
-const NORMAL_BUTTON: Color = Color::srgb(0.15, 0.15, 0.15);
+pub(crate) const NORMAL_BUTTON: Color = Color::srgb(0.15, 0.15, 0.15);
 const HOVERED_BUTTON: Color = Color::srgb(0.25, 0.25, 0.25);
 const HOVERED_PRESSED_BUTTON: Color = Color::srgb(0.25, 0.65, 0.25);
 const PRESSED_BUTTON: Color = Color::srgb(0.35, 0.75, 0.35);
@@ -32,7 +50,17 @@ const BORDER_COLOR_ACTIVE: Color = Color::srgb(0.75, 0.52, 0.99);
 
 const BACKGROUND_COLOR: Color = Color::srgb(0.15, 0.15, 0.15);
 
-const TEXT_COLOR: Color = Color::srgb(0.9, 0.9, 0.9);
+pub(crate) const TEXT_COLOR: Color = Color::srgb(0.9, 0.9, 0.9);
+const CONFLICT_TEXT_COLOR: Color = Color::srgb(0.95, 0.35, 0.35);
+
+/// The size of the texture the tank preview is rendered to (in pixels)
+const TANK_PREVIEW_SIZE: u32 = 256;
+
+/// How fast the tank preview turns (in radians per second)
+const TANK_PREVIEW_SPEED: f32 = 0.8;
+
+/// How far the mouse has to move in one frame to be bound to the orbit (in pixels)
+const REBIND_MOUSE_MOVE_DISTANCE: f32 = 30.0;
 
 // State used for the current menu screen
 #[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, States)]
@@ -41,22 +69,15 @@ enum MenuState {
     Settings,
     SettingsDisplay,
     SettingsSound,
+    SettingsControls,
+    Customize,
+    ServerBrowser,
+    Replays,
+    ConnectionError,
     #[default]
     Disabled,
 }
 
-// One of the two settings that can be set through the menu. It will be a resource in the app
-#[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy)]
-enum DisplayQuality {
-    Low,
-    Medium,
-    High,
-}
-
-// One of the two settings that can be set through the menu. It will be a resource in the app
-#[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy)]
-struct Volume(u32);
-
 #[derive(Component, Clone, Copy, Debug)]
 struct MainMenu;
 
@@ -70,13 +91,45 @@ struct NameInput;
 #[derive(Component)]
 struct SelectedOption;
 
+// Tag component used to mark the text that warns about conflicting bindings
+#[derive(Component)]
+struct ConflictWarning;
+
+// Tag component used to mark the button that cancels a pending rebind
+#[derive(Component)]
+struct CancelRebind;
+
+// Tag component used to mark the node that holds the rows of the server browser
+#[derive(Component)]
+struct ServerListContainer;
+
+// Tag component used to mark the tank shown in the customization menu
+#[derive(Component)]
+struct TankPreview;
+
+// Tag component used to mark the button that is focused with the gamepad
+#[derive(Component)]
+struct GamepadFocus;
+
+// The control action that is waiting for a new binding
+#[derive(Resource, Debug, Clone, Copy, Deref)]
+struct PendingRebind(ControlAction);
+
 // All actions that can be triggered from a button click
 #[derive(Component)]
 enum MenuButtonAction {
     Play,
+    ServerBrowser,
+    RefreshServers,
+    JoinServer(String),
+    Replays,
+    WatchReplay(PathBuf),
+    Customize,
     Settings,
     SettingsDisplay,
     SettingsSound,
+    SettingsControls,
+    ResetControls,
     BackToMainMenu,
     BackToSettings,
     Quit,
@@ -97,10 +150,16 @@ impl Plugin for MainMenuPlugin {
         app.init_state::<MenuState>();
         app.enable_state_scoped_entities::<MenuState>();
 
+        // The settings are usually loaded by the `SettingsPlugin`, fall back to the defaults
         app.init_resource::<ClientInfo>()
-            // Insert as resource the initial value for the settings resources
-            .insert_resource(DisplayQuality::Medium)
-            .insert_resource(Volume(7))
+            .init_resource::<DisplayQuality>()
+            .init_resource::<Volume>()
+            .init_resource::<EffectsVolume>()
+            .init_resource::<EngineVolume>()
+            .init_resource::<RenderScale>()
+            .init_resource::<Keybindings>()
+            .init_resource::<TankColor>()
+            .init_resource::<TankSkin>()
             .add_event::<PlayButtonPressed>();
 
         app.add_systems(OnEnter(GameStates::MainMenu), menu_setup);
@@ -113,18 +172,72 @@ impl Plugin for MainMenuPlugin {
         );
         app.add_systems(
             Update,
-            (setting_button::<DisplayQuality>.run_if(in_state(MenuState::SettingsDisplay)),),
+            (
+                setting_button::<DisplayQuality>,
+                setting_button::<RenderScale>,
+            )
+                .run_if(in_state(MenuState::SettingsDisplay)),
         );
 
         app.add_systems(OnEnter(MenuState::SettingsSound), sound_settings_menu_setup);
         app.add_systems(
             Update,
-            setting_button::<Volume>.run_if(in_state(MenuState::SettingsSound)),
+            (
+                setting_button::<Volume>,
+                setting_button::<EffectsVolume>,
+                setting_button::<EngineVolume>,
+            )
+                .run_if(in_state(MenuState::SettingsSound)),
+        );
+
+        app.add_systems(
+            OnEnter(MenuState::ConnectionError),
+            connection_error_menu_setup,
+        );
+        app.add_systems(OnExit(MenuState::ConnectionError), clear_connection_error);
+
+        app.add_systems(OnEnter(MenuState::Customize), customize_menu_setup);
+        app.add_systems(
+            Update,
+            (
+                setting_button::<TankColor>,
+                setting_button::<TankSkin>,
+                update_tank_preview
+                    .run_if(resource_changed::<TankColor>.or(resource_changed::<TankSkin>)),
+                rotate_tank_preview,
+            )
+                .chain()
+                .run_if(in_state(MenuState::Customize)),
+        );
+
+        app.add_systems(OnEnter(MenuState::ServerBrowser), server_browser_menu_setup);
+        app.add_systems(
+            Update,
+            update_server_list.run_if(in_state(MenuState::ServerBrowser)),
+        );
+
+        app.add_systems(OnEnter(MenuState::Replays), replays_menu_setup);
+
+        app.add_systems(
+            OnEnter(MenuState::SettingsControls),
+            controls_settings_menu_setup,
+        );
+        app.add_systems(OnExit(MenuState::SettingsControls), cancel_rebind);
+        app.add_systems(
+            Update,
+            (
+                start_rebind.run_if(not(resource_exists::<PendingRebind>)),
+                capture_rebind.run_if(resource_exists::<PendingRebind>),
+                update_rebind_buttons,
+            )
+                .chain()
+                .run_if(in_state(MenuState::SettingsControls)),
         );
 
         app.add_systems(
             Update,
             (
+                handle_gamepad_navigation.before(handle_button_interact),
                 menu_action,
                 handle_button_interact,
                 handle_text_interact.before(TextInputSystem),
@@ -135,7 +248,7 @@ impl Plugin for MainMenuPlugin {
 }
 
 // This system handles changing all buttons color based on mouse interaction
-fn handle_button_interact(
+pub(crate) fn handle_button_interact(
     mut interaction_query: Query<
         (&Interaction, &mut BackgroundColor, Option<&SelectedOption>),
         (Changed<Interaction>, With<Button>),
@@ -151,6 +264,101 @@ fn handle_button_interact(
     }
 }
 
+// This system moves the focus between the buttons with the gamepad, confirms with the south
+// button and goes back with the east button
+fn handle_gamepad_navigation(
+    mut commands: Commands,
+    q_gamepad: Query<&Gamepad>,
+    mut q_button: Query<
+        (
+            Entity,
+            &GlobalTransform,
+            &mut Interaction,
+            Has<GamepadFocus>,
+            Option<&MenuButtonAction>,
+        ),
+        With<Button>,
+    >,
+    mut last_stick_direction: Local<i32>,
+) {
+    let mut direction = 0;
+    let mut confirm = false;
+    let mut back = false;
+    let mut stick_direction = 0;
+
+    for gamepad in q_gamepad.iter() {
+        if gamepad.just_pressed(GamepadButton::DPadUp)
+            || gamepad.just_pressed(GamepadButton::DPadLeft)
+        {
+            direction = -1;
+        }
+        if gamepad.just_pressed(GamepadButton::DPadDown)
+            || gamepad.just_pressed(GamepadButton::DPadRight)
+        {
+            direction = 1;
+        }
+
+        let stick = gamepad.left_stick();
+        if stick.y > 0.5 || stick.x < -0.5 {
+            stick_direction = -1;
+        } else if stick.y < -0.5 || stick.x > 0.5 {
+            stick_direction = 1;
+        }
+
+        confirm |= gamepad.just_pressed(GamepadButton::South);
+        back |= gamepad.just_pressed(GamepadButton::East);
+    }
+
+    // Only move once each time the stick leaves the center
+    if stick_direction != *last_stick_direction && direction == 0 {
+        direction = stick_direction;
+    }
+    *last_stick_direction = stick_direction;
+
+    let mut buttons = q_button
+        .iter()
+        .map(|(entity, transform, _, focused, _)| (entity, transform.translation(), focused))
+        .collect::<Vec<_>>();
+    buttons.sort_by(|(_, a, _), (_, b, _)| a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x)));
+
+    let current = buttons.iter().position(|(_, _, focused)| *focused);
+    let focused = match (current, direction) {
+        (_, 0) => current,
+        (None, _) => (!buttons.is_empty()).then_some(0),
+        (Some(index), direction) => {
+            Some((index as i32 + direction).rem_euclid(buttons.len() as i32) as usize)
+        }
+    };
+
+    if focused != current {
+        if let Some(index) = current {
+            commands.entity(buttons[index].0).remove::<GamepadFocus>();
+        }
+        if let Some(index) = focused {
+            commands.entity(buttons[index].0).insert(GamepadFocus);
+        }
+    }
+
+    let focused = focused.map(|index| buttons[index].0);
+    for (entity, _, mut interaction, _, action) in q_button.iter_mut() {
+        let is_back = matches!(
+            action,
+            Some(MenuButtonAction::BackToMainMenu | MenuButtonAction::BackToSettings)
+        );
+
+        if back && is_back {
+            *interaction = Interaction::Pressed;
+        } else if Some(entity) == focused {
+            if confirm {
+                *interaction = Interaction::Pressed;
+            } else if *interaction == Interaction::None {
+                // Reuse the hover highlight to show the focused button
+                *interaction = Interaction::Hovered;
+            }
+        }
+    }
+}
+
 fn handle_text_interact(
     query: Query<(Entity, &Interaction), Changed<Interaction>>,
     mut text_input_query: Query<(Entity, &mut TextInputInactive, &mut BorderColor)>,
@@ -174,7 +382,7 @@ fn handle_text_interact(
 // the button as the one currently selected
 fn setting_button<T: Resource + Component + PartialEq + Copy>(
     interaction_query: Query<(&Interaction, &T, Entity), (Changed<Interaction>, With<Button>)>,
-    selected_query: Single<(Entity, &mut BackgroundColor), With<SelectedOption>>,
+    selected_query: Single<(Entity, &mut BackgroundColor), (With<SelectedOption>, With<T>)>,
     mut commands: Commands,
     mut setting: ResMut<T>,
 ) {
@@ -189,17 +397,89 @@ fn setting_button<T: Resource + Component + PartialEq + Copy>(
     }
 }
 
-fn menu_setup(mut commands: Commands, mut menu_state: ResMut<NextState<MenuState>>) {
+fn menu_setup(
+    mut commands: Commands,
+    mut menu_state: ResMut<NextState<MenuState>>,
+    connection_error: Option<Res<ConnectionError>>,
+) {
     commands.spawn((
         Name::new("CameraUI"),
         Camera2d,
         StateScoped(GameStates::MainMenu),
     ));
 
-    menu_state.set(MenuState::Main);
+    // Show why the last connection failed before going back to the main menu
+    match connection_error {
+        Some(_) => menu_state.set(MenuState::ConnectionError),
+        None => menu_state.set(MenuState::Main),
+    }
+}
+
+fn connection_error_menu_setup(mut commands: Commands, connection_error: Res<ConnectionError>) {
+    let button_node = Node {
+        width: Val::Px(200.0),
+        height: Val::Px(65.0),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let button_text_style = (
+        TextFont {
+            font_size: 33.0,
+            ..default()
+        },
+        TextColor(TEXT_COLOR),
+    );
+
+    commands
+        .spawn((
+            Name::new("ConnectionErrorMenu"),
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            StateScoped(MenuState::ConnectionError),
+        ))
+        .with_children(|parent| {
+            parent.spawn((Text::new("Connection Failed"), button_text_style.clone()));
+            parent.spawn((
+                Text::new(connection_error.0.clone()),
+                TextFont {
+                    font_size: 24.0,
+                    ..default()
+                },
+                TextColor(CONFLICT_TEXT_COLOR),
+                Node {
+                    max_width: Val::Px(800.0),
+                    margin: UiRect::all(Val::Px(20.0)),
+                    ..default()
+                },
+            ));
+            parent
+                .spawn((
+                    Button,
+                    button_node,
+                    BackgroundColor(NORMAL_BUTTON),
+                    MenuButtonAction::BackToMainMenu,
+                ))
+                .with_child((Text::new("Back"), button_text_style));
+        });
+}
+
+fn clear_connection_error(mut commands: Commands) {
+    commands.remove_resource::<ConnectionError>();
 }
 
-fn main_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn main_menu_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    client_info: Res<ClientInfo>,
+) {
     // Common style for all buttons on the screen
     let button_node = Node {
         width: Val::Px(300.0),
@@ -283,6 +563,34 @@ fn main_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                             ));
                         });
 
+                    parent
+                        .spawn((
+                            Name::new("ServerBrowserButton"),
+                            Button,
+                            button_node.clone(),
+                            BackgroundColor(NORMAL_BUTTON),
+                            MenuButtonAction::ServerBrowser,
+                        ))
+                        .with_child((
+                            Text::new("Servers"),
+                            button_text_font.clone(),
+                            TextColor(TEXT_COLOR),
+                        ));
+
+                    parent
+                        .spawn((
+                            Name::new("ReplaysButton"),
+                            Button,
+                            button_node.clone(),
+                            BackgroundColor(NORMAL_BUTTON),
+                            MenuButtonAction::Replays,
+                        ))
+                        .with_child((
+                            Text::new("Watch Replay"),
+                            button_text_font.clone(),
+                            TextColor(TEXT_COLOR),
+                        ));
+
                     parent.spawn((
                         Name::new("AddressInput"),
                         AddressInput,
@@ -302,7 +610,7 @@ fn main_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                             ..default()
                         }),
                         TextInputTextColor(TextColor(TEXT_COLOR)),
-                        TextInputValue("127.0.0.1".to_string()),
+                        TextInputValue(client_info.address.clone()),
                         TextInputSettings {
                             retain_on_submit: true,
                             ..default()
@@ -329,7 +637,7 @@ fn main_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                             ..default()
                         }),
                         TextInputTextColor(TextColor(TEXT_COLOR)),
-                        TextInputValue("Player".to_string()),
+                        TextInputValue(client_info.name.clone()),
                         TextInputSettings {
                             retain_on_submit: true,
                             ..default()
@@ -337,6 +645,20 @@ fn main_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                         TextInputInactive(true),
                     ));
 
+                    parent
+                        .spawn((
+                            Name::new("CustomizeButton"),
+                            Button,
+                            button_node.clone(),
+                            BackgroundColor(NORMAL_BUTTON),
+                            MenuButtonAction::Customize,
+                        ))
+                        .with_child((
+                            Text::new("Customize"),
+                            button_text_font.clone(),
+                            TextColor(TEXT_COLOR),
+                        ));
+
                     parent
                         .spawn((
                             Name::new("SettingsButton"),
@@ -421,6 +743,7 @@ fn settings_menu_setup(mut commands: Commands) {
                     for (action, text) in [
                         (MenuButtonAction::SettingsDisplay, "Display"),
                         (MenuButtonAction::SettingsSound, "Sound"),
+                        (MenuButtonAction::SettingsControls, "Controls"),
                         (MenuButtonAction::BackToMainMenu, "Back"),
                     ] {
                         parent
@@ -438,7 +761,11 @@ fn settings_menu_setup(mut commands: Commands) {
         });
 }
 
-fn display_settings_menu_setup(mut commands: Commands, display_quality: Res<DisplayQuality>) {
+fn display_settings_menu_setup(
+    mut commands: Commands,
+    display_quality: Res<DisplayQuality>,
+    render_scale: Res<RenderScale>,
+) {
     let button_node = Node {
         width: Val::Px(200.0),
         height: Val::Px(65.0),
@@ -519,6 +846,33 @@ fn display_settings_menu_setup(mut commands: Commands, display_quality: Res<Disp
                                 }
                             }
                         });
+                    parent
+                        .spawn(Node {
+                            align_items: AlignItems::Center,
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            parent.spawn((Text::new("Render Scale"), button_text_style.clone()));
+                            for scale_setting in RenderScale::OPTIONS.map(RenderScale) {
+                                let mut entity = parent.spawn((
+                                    Button,
+                                    Node {
+                                        width: Val::Px(110.0),
+                                        height: Val::Px(65.0),
+                                        ..button_node.clone()
+                                    },
+                                    BackgroundColor(NORMAL_BUTTON),
+                                    scale_setting,
+                                ));
+                                entity.with_child((
+                                    Text::new(format!("{}%", scale_setting.0)),
+                                    button_text_style.clone(),
+                                ));
+                                if *render_scale == scale_setting {
+                                    entity.insert(SelectedOption);
+                                }
+                            }
+                        });
                     // Display the back button to return to the settings screen
                     parent
                         .spawn((
@@ -534,7 +888,12 @@ fn display_settings_menu_setup(mut commands: Commands, display_quality: Res<Disp
         });
 }
 
-fn sound_settings_menu_setup(mut commands: Commands, volume: Res<Volume>) {
+fn sound_settings_menu_setup(
+    mut commands: Commands,
+    volume: Res<Volume>,
+    effects_volume: Res<EffectsVolume>,
+    engine_volume: Res<EngineVolume>,
+) {
     let button_node = Node {
         width: Val::Px(200.0),
         height: Val::Px(65.0),
@@ -575,32 +934,9 @@ fn sound_settings_menu_setup(mut commands: Commands, volume: Res<Volume>) {
                     // BackgroundColor(CRIMSON.into()),
                 ))
                 .with_children(|parent| {
-                    parent
-                        .spawn((
-                            Node {
-                                align_items: AlignItems::Center,
-                                ..default()
-                            },
-                            // BackgroundColor(CRIMSON.into()),
-                        ))
-                        .with_children(|parent| {
-                            parent.spawn((Text::new("Volume"), button_text_style.clone()));
-                            for volume_setting in [0, 1, 2, 3, 4, 5, 6, 7, 8, 9] {
-                                let mut entity = parent.spawn((
-                                    Button,
-                                    Node {
-                                        width: Val::Px(30.0),
-                                        height: Val::Px(65.0),
-                                        ..button_node.clone()
-                                    },
-                                    BackgroundColor(NORMAL_BUTTON),
-                                    Volume(volume_setting),
-                                ));
-                                if *volume == Volume(volume_setting) {
-                                    entity.insert(SelectedOption);
-                                }
-                            }
-                        });
+                    spawn_volume_slider(parent, "Master", *volume, Volume);
+                    spawn_volume_slider(parent, "Effects", *effects_volume, EffectsVolume);
+                    spawn_volume_slider(parent, "Engine", *engine_volume, EngineVolume);
                     parent
                         .spawn((
                             Button,
@@ -613,40 +949,820 @@ fn sound_settings_menu_setup(mut commands: Commands, volume: Res<Volume>) {
         });
 }
 
-fn menu_action(
-    interaction_query: Query<
-        (&Interaction, &MenuButtonAction),
-        (Changed<Interaction>, With<Button>),
-    >,
-    mut app_exit_events: EventWriter<AppExit>,
-    mut menu_state: ResMut<NextState<MenuState>>,
-    q_address: Query<&TextInputValue, With<AddressInput>>,
-    q_name: Query<&TextInputValue, With<NameInput>>,
-    mut events: EventWriter<PlayButtonPressed>,
+// Spawns a row with a button for each volume from muted to full, it works as a stepped slider
+fn spawn_volume_slider<T: Component + PartialEq>(
+    parent: &mut ChildBuilder,
+    label: &str,
+    current: T,
+    volume: impl Fn(u32) -> T,
 ) {
-    for (interaction, menu_button_action) in &interaction_query {
-        if *interaction == Interaction::Pressed {
-            match menu_button_action {
-                MenuButtonAction::Quit => {
-                    app_exit_events.send(AppExit::Success);
+    let button_node = Node {
+        width: Val::Px(30.0),
+        height: Val::Px(65.0),
+        margin: UiRect::all(Val::Px(10.0)),
+        ..default()
+    };
+
+    parent
+        .spawn(Node {
+            align_items: AlignItems::Center,
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(label),
+                TextFont {
+                    font_size: 33.0,
+                    ..default()
+                },
+                TextColor(TEXT_COLOR),
+                Node {
+                    width: Val::Px(150.0),
+                    ..default()
+                },
+            ));
+            for volume_setting in 0..=Volume::MAX {
+                let setting = volume(volume_setting);
+                let selected = setting == current;
+                let mut entity = parent.spawn((
+                    Button,
+                    button_node.clone(),
+                    BackgroundColor(NORMAL_BUTTON),
+                    setting,
+                ));
+                if selected {
+                    entity.insert(SelectedOption);
                 }
-                MenuButtonAction::Play => {
-                    let address = q_address.get_single().expect("AddressInput not found");
-                    let name = q_name.get_single().expect("NameInput not found");
+            }
+        });
+}
 
-                    events.send(PlayButtonPressed {
-                        address: address.0.clone(),
-                        name: name.0.clone(),
-                    });
+fn customize_menu_setup(
+    mut commands: Commands,
+    tank_color: Res<TankColor>,
+    tank_skin: Res<TankSkin>,
+    game_assets: Res<GameAssets>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let button_node = Node {
+        width: Val::Px(200.0),
+        height: Val::Px(65.0),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let option_node = Node {
+        width: Val::Px(60.0),
+        height: Val::Px(60.0),
+        margin: UiRect::all(Val::Px(5.0)),
+        padding: UiRect::all(Val::Px(6.0)),
+        ..default()
+    };
+    let swatch_node = Node {
+        width: Val::Percent(100.0),
+        height: Val::Percent(100.0),
+        ..default()
+    };
+    let button_text_style = (
+        TextFont {
+            font_size: 33.0,
+            ..default()
+        },
+        TextColor(TEXT_COLOR),
+    );
 
-                    menu_state.set(MenuState::Disabled);
-                }
-                MenuButtonAction::Settings => menu_state.set(MenuState::Settings),
-                MenuButtonAction::SettingsDisplay => {
-                    menu_state.set(MenuState::SettingsDisplay);
-                }
-                MenuButtonAction::SettingsSound => {
-                    menu_state.set(MenuState::SettingsSound);
+    // The preview is rendered to a texture that is shown in the menu
+    let preview = images.add(render_target_image(UVec2::splat(TANK_PREVIEW_SIZE)));
+
+    commands.spawn((
+        Name::new("TankPreviewCamera"),
+        Camera3d::default(),
+        Camera {
+            target: RenderTarget::Image(preview.clone()),
+            order: -1,
+            clear_color: ClearColorConfig::Custom(BACKGROUND_COLOR),
+            ..default()
+        },
+        Transform::from_xyz(0.0, 1.2, 2.0).looking_at(Vec3::new(0.0, 0.2, 0.0), Vec3::Y),
+        StateScoped(MenuState::Customize),
+    ));
+    commands.spawn((
+        Name::new("TankPreviewLight"),
+        DirectionalLight::default(),
+        Transform::from_xyz(1.0, 2.0, 1.0).looking_at(Vec3::ZERO, Vec3::Y),
+        StateScoped(MenuState::Customize),
+    ));
+    commands.spawn((
+        Name::new("TankPreview"),
+        TankPreview,
+        Transform::from_scale(Vec3::splat(2.0)),
+        SceneRoot(game_assets.tank.clone()),
+        TankMaterial(materials.add(tank_material(tank_color.0, *tank_skin, &game_assets))),
+        StateScoped(MenuState::Customize),
+    ));
+
+    commands
+        .spawn((
+            Name::new("CustomizeMenu"),
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            StateScoped(MenuState::Customize),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                ImageNode::new(preview),
+                Node {
+                    width: Val::Px(TANK_PREVIEW_SIZE as f32),
+                    height: Val::Px(TANK_PREVIEW_SIZE as f32),
+                    margin: UiRect::all(Val::Px(20.0)),
+                    ..default()
+                },
+            ));
+
+            // A button for each color of the palette, the color is shown inside the button so
+            // that the highlight of the selection stays visible
+            parent
+                .spawn(Node {
+                    align_items: AlignItems::Center,
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((Text::new("Color"), button_text_style.clone()));
+                    for color in TankColor::PALETTE {
+                        let mut entity = parent.spawn((
+                            Button,
+                            option_node.clone(),
+                            BackgroundColor(NORMAL_BUTTON),
+                            TankColor(color),
+                        ));
+                        entity.with_child((swatch_node.clone(), BackgroundColor(color)));
+                        if *tank_color == TankColor(color) {
+                            entity.insert(SelectedOption);
+                        }
+                    }
+                });
+
+            // A button for each skin, showing its prototype texture
+            parent
+                .spawn(Node {
+                    align_items: AlignItems::Center,
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((Text::new("Skin"), button_text_style.clone()));
+                    for (index, texture) in game_assets.prototype_textures.iter().enumerate() {
+                        let skin = TankSkin(index as u8);
+                        let mut entity = parent.spawn((
+                            Button,
+                            option_node.clone(),
+                            BackgroundColor(NORMAL_BUTTON),
+                            skin,
+                        ));
+                        entity.with_child((ImageNode::new(texture.clone()), swatch_node.clone()));
+                        if *tank_skin == skin {
+                            entity.insert(SelectedOption);
+                        }
+                    }
+                });
+
+            parent
+                .spawn((
+                    Button,
+                    button_node,
+                    BackgroundColor(NORMAL_BUTTON),
+                    MenuButtonAction::BackToMainMenu,
+                ))
+                .with_child((Text::new("Back"), button_text_style));
+        });
+}
+
+fn update_tank_preview(
+    tank_color: Res<TankColor>,
+    tank_skin: Res<TankSkin>,
+    game_assets: Res<GameAssets>,
+    q_preview: Query<&TankMaterial, With<TankPreview>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for TankMaterial(handle) in q_preview.iter() {
+        if let Some(material) = materials.get_mut(handle) {
+            *material = tank_material(tank_color.0, *tank_skin, &game_assets);
+        }
+    }
+}
+
+fn rotate_tank_preview(time: Res<Time>, mut q_preview: Query<&mut Transform, With<TankPreview>>) {
+    for mut transform in q_preview.iter_mut() {
+        transform.rotate_y(TANK_PREVIEW_SPEED * time.delta_secs());
+    }
+}
+
+fn server_browser_menu_setup(
+    mut commands: Commands,
+    mut refresh_events: EventWriter<RefreshServerListEvent>,
+) {
+    let button_node = Node {
+        width: Val::Px(200.0),
+        height: Val::Px(65.0),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let button_text_style = (
+        TextFont {
+            font_size: 33.0,
+            ..default()
+        },
+        TextColor(TEXT_COLOR),
+    );
+
+    commands
+        .spawn((
+            Name::new("ServerBrowserMenu"),
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            StateScoped(MenuState::ServerBrowser),
+        ))
+        .with_children(|parent| {
+            parent.spawn((Text::new("Servers"), button_text_style.clone()));
+
+            parent.spawn((
+                Name::new("ServerList"),
+                ServerListContainer,
+                Node {
+                    width: Val::Px(900.0),
+                    min_height: Val::Px(300.0),
+                    margin: UiRect::all(Val::Px(20.0)),
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                BackgroundColor(BACKGROUND_COLOR),
+            ));
+
+            parent.spawn(Node::default()).with_children(|parent| {
+                for (action, text) in [
+                    (MenuButtonAction::RefreshServers, "Refresh"),
+                    (MenuButtonAction::BackToMainMenu, "Back"),
+                ] {
+                    parent
+                        .spawn((
+                            Button,
+                            button_node.clone(),
+                            BackgroundColor(NORMAL_BUTTON),
+                            action,
+                        ))
+                        .with_child((Text::new(text), button_text_style.clone()));
+                }
+            });
+        });
+
+    refresh_events.send(RefreshServerListEvent);
+}
+
+// This system rebuilds the rows of the server browser whenever the server list changes
+fn update_server_list(
+    mut commands: Commands,
+    server_list: Res<ServerList>,
+    q_container: Query<(Entity, Ref<ServerListContainer>)>,
+) {
+    let Ok((container, tag)) = q_container.get_single() else {
+        return;
+    };
+    if !server_list.is_changed() && !tag.is_added() {
+        return;
+    }
+
+    let text_style = (
+        TextFont {
+            font_size: 24.0,
+            ..default()
+        },
+        TextColor(TEXT_COLOR),
+    );
+    let row_node = Node {
+        align_items: AlignItems::Center,
+        justify_content: JustifyContent::SpaceBetween,
+        padding: UiRect::horizontal(Val::Px(10.0)),
+        ..default()
+    };
+    let join_node = Node {
+        width: Val::Px(100.0),
+        height: Val::Px(45.0),
+        margin: UiRect::all(Val::Px(5.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+
+    commands
+        .entity(container)
+        .despawn_descendants()
+        .with_children(|parent| {
+            let status = match &server_list.status {
+                ServerListStatus::Idle | ServerListStatus::Loading => {
+                    Some("Loading...".to_string())
+                }
+                ServerListStatus::Failed(error) => {
+                    Some(format!("Failed to reach the master server: {}", error))
+                }
+                ServerListStatus::Ready if server_list.servers.is_empty() => {
+                    Some("No servers found".to_string())
+                }
+                ServerListStatus::Ready => None,
+            };
+            if let Some(status) = status {
+                parent.spawn((Text::new(status), text_style.clone(), row_node.clone()));
+                return;
+            }
+
+            for server in server_list.servers.iter() {
+                let listing = &server.listing;
+                let latency = match server.latency {
+                    Some(latency) => format!("{} ms", latency.as_millis()),
+                    None => "-".to_string(),
+                };
+
+                parent.spawn(row_node.clone()).with_children(|parent| {
+                    parent.spawn((
+                        Text::new(format!(
+                            "{}  |  {}  |  {}  |  {}/{}  |  {}",
+                            listing.name,
+                            listing.map,
+                            listing.mode,
+                            listing.players,
+                            listing.max_players,
+                            latency
+                        )),
+                        text_style.clone(),
+                    ));
+                    parent
+                        .spawn((
+                            Button,
+                            join_node.clone(),
+                            BackgroundColor(NORMAL_BUTTON),
+                            MenuButtonAction::JoinServer(listing.address.clone()),
+                        ))
+                        .with_child((Text::new("Join"), text_style.clone()));
+                });
+            }
+        });
+}
+
+fn replays_menu_setup(mut commands: Commands) {
+    let button_node = Node {
+        width: Val::Px(200.0),
+        height: Val::Px(65.0),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let button_text_style = (
+        TextFont {
+            font_size: 33.0,
+            ..default()
+        },
+        TextColor(TEXT_COLOR),
+    );
+    let text_style = (
+        TextFont {
+            font_size: 24.0,
+            ..default()
+        },
+        TextColor(TEXT_COLOR),
+    );
+    let row_node = Node {
+        align_items: AlignItems::Center,
+        justify_content: JustifyContent::SpaceBetween,
+        padding: UiRect::horizontal(Val::Px(10.0)),
+        ..default()
+    };
+    let watch_node = Node {
+        width: Val::Px(100.0),
+        height: Val::Px(45.0),
+        margin: UiRect::all(Val::Px(5.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+
+    let replays = list_replays();
+
+    commands
+        .spawn((
+            Name::new("ReplaysMenu"),
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            StateScoped(MenuState::Replays),
+        ))
+        .with_children(|parent| {
+            parent.spawn((Text::new("Replays"), button_text_style.clone()));
+
+            parent
+                .spawn((
+                    Name::new("ReplayList"),
+                    Node {
+                        width: Val::Px(900.0),
+                        min_height: Val::Px(300.0),
+                        margin: UiRect::all(Val::Px(20.0)),
+                        flex_direction: FlexDirection::Column,
+                        ..default()
+                    },
+                    BackgroundColor(BACKGROUND_COLOR),
+                ))
+                .with_children(|parent| {
+                    if replays.is_empty() {
+                        parent.spawn((
+                            Text::new(format!("No replays found in {}", REPLAY_DIR)),
+                            text_style.clone(),
+                            row_node.clone(),
+                        ));
+                    }
+
+                    for path in replays {
+                        let name = path
+                            .file_stem()
+                            .map(|name| name.to_string_lossy().to_string())
+                            .unwrap_or_default();
+
+                        parent.spawn(row_node.clone()).with_children(|parent| {
+                            parent.spawn((Text::new(name), text_style.clone()));
+                            parent
+                                .spawn((
+                                    Button,
+                                    watch_node.clone(),
+                                    BackgroundColor(NORMAL_BUTTON),
+                                    MenuButtonAction::WatchReplay(path),
+                                ))
+                                .with_child((Text::new("Watch"), text_style.clone()));
+                        });
+                    }
+                });
+
+            parent
+                .spawn((
+                    Button,
+                    button_node,
+                    BackgroundColor(NORMAL_BUTTON),
+                    MenuButtonAction::BackToMainMenu,
+                ))
+                .with_child((Text::new("Back"), button_text_style));
+        });
+}
+
+fn controls_settings_menu_setup(mut commands: Commands, keybindings: Res<Keybindings>) {
+    let button_node = Node {
+        width: Val::Px(200.0),
+        height: Val::Px(65.0),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let binding_node = Node {
+        width: Val::Px(180.0),
+        height: Val::Px(45.0),
+        margin: UiRect::all(Val::Px(5.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let label_node = Node {
+        width: Val::Px(180.0),
+        ..default()
+    };
+    let button_text_style = (
+        TextFont {
+            font_size: 33.0,
+            ..default()
+        },
+        TextColor(TEXT_COLOR),
+    );
+    let binding_text_style = (
+        TextFont {
+            font_size: 24.0,
+            ..default()
+        },
+        TextColor(TEXT_COLOR),
+    );
+
+    commands
+        .spawn((
+            Name::new("ControlsSettingsMenu"),
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            StateScoped(MenuState::SettingsControls),
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(Node {
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    ..default()
+                })
+                .with_children(|parent| {
+                    // Display the tank and the camera controls side by side
+                    parent.spawn(Node::default()).with_children(|parent| {
+                        for actions in [ControlAction::TANK, ControlAction::CAMERA] {
+                            parent
+                                .spawn(Node {
+                                    flex_direction: FlexDirection::Column,
+                                    margin: UiRect::horizontal(Val::Px(20.0)),
+                                    ..default()
+                                })
+                                .with_children(|parent| {
+                                    for action in actions {
+                                        parent
+                                            .spawn(Node {
+                                                align_items: AlignItems::Center,
+                                                ..default()
+                                            })
+                                            .with_children(|parent| {
+                                                parent.spawn((
+                                                    Text::new(action.label()),
+                                                    binding_text_style.clone(),
+                                                    label_node.clone(),
+                                                ));
+                                                let label = keybindings
+                                                    .get(action)
+                                                    .map(|binding| binding.label())
+                                                    .unwrap_or_default();
+                                                parent
+                                                    .spawn((
+                                                        Button,
+                                                        binding_node.clone(),
+                                                        BackgroundColor(NORMAL_BUTTON),
+                                                        action,
+                                                    ))
+                                                    .with_child((
+                                                        Text::new(label),
+                                                        binding_text_style.clone(),
+                                                    ));
+                                            });
+                                    }
+                                });
+                        }
+                    });
+
+                    parent.spawn((
+                        ConflictWarning,
+                        Text::new(""),
+                        binding_text_style.clone(),
+                        TextColor(CONFLICT_TEXT_COLOR),
+                    ));
+
+                    parent.spawn(Node::default()).with_children(|parent| {
+                        parent
+                            .spawn((
+                                Button,
+                                button_node.clone(),
+                                BackgroundColor(NORMAL_BUTTON),
+                                CancelRebind,
+                            ))
+                            .with_child((Text::new("Cancel"), button_text_style.clone()));
+                        for (action, text) in [
+                            (MenuButtonAction::ResetControls, "Reset"),
+                            (MenuButtonAction::BackToSettings, "Back"),
+                        ] {
+                            parent
+                                .spawn((
+                                    Button,
+                                    button_node.clone(),
+                                    BackgroundColor(NORMAL_BUTTON),
+                                    action,
+                                ))
+                                .with_child((Text::new(text), button_text_style.clone()));
+                        }
+                    });
+                });
+        });
+}
+
+// This system starts listening for a new binding when a control button is pressed
+fn start_rebind(
+    mut commands: Commands,
+    interaction_query: Query<(&Interaction, &ControlAction), (Changed<Interaction>, With<Button>)>,
+) {
+    for (interaction, action) in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            commands.insert_resource(PendingRebind(*action));
+        }
+    }
+}
+
+// This system binds the next key, mouse button or wheel movement to the pending action. The
+// orbit binds a mouse movement. Clicking the cancel button cancels the rebind, so every key can
+// be bound.
+fn capture_rebind(
+    mut commands: Commands,
+    pending: Res<PendingRebind>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    motion: Res<AccumulatedMouseMotion>,
+    mut wheel: EventReader<MouseWheel>,
+    q_cancel: Query<&Interaction, With<CancelRebind>>,
+    mut keybindings: ResMut<Keybindings>,
+) {
+    // Ignore the click that started the rebind
+    if pending.is_added() {
+        wheel.clear();
+        return;
+    }
+
+    if q_cancel
+        .iter()
+        .any(|interaction| *interaction == Interaction::Pressed)
+    {
+        commands.remove_resource::<PendingRebind>();
+        return;
+    }
+
+    let binding = if let Some(key) = keys.get_just_pressed().next() {
+        Some(InputBinding::Key(*key))
+    } else if let Some(button) = mouse.get_just_pressed().next() {
+        Some(InputBinding::Mouse(*button))
+    } else if pending.axis() == Some(InputBinding::MouseMove)
+        && motion.delta.length() > REBIND_MOUSE_MOVE_DISTANCE
+    {
+        Some(InputBinding::MouseMove)
+    } else {
+        wheel
+            .read()
+            .find(|event| event.y != 0.0)
+            .map(|event| match pending.axis() {
+                Some(_) => InputBinding::Wheel,
+                None if event.y > 0.0 => InputBinding::WheelUp,
+                None => InputBinding::WheelDown,
+            })
+    };
+    let binding = binding.filter(|binding| pending.accepts(*binding));
+
+    if let Some(binding) = binding {
+        keybindings.set(**pending, binding);
+        commands.remove_resource::<PendingRebind>();
+    }
+}
+
+fn cancel_rebind(mut commands: Commands) {
+    commands.remove_resource::<PendingRebind>();
+}
+
+// This system updates the labels of the control buttons and highlights the conflicting bindings
+fn update_rebind_buttons(
+    keybindings: Res<Keybindings>,
+    pending: Option<Res<PendingRebind>>,
+    q_button: Query<(&ControlAction, &Children), With<Button>>,
+    mut q_text: Query<(&mut Text, &mut TextColor), Without<ConflictWarning>>,
+    mut q_warning: Query<&mut Text, With<ConflictWarning>>,
+) {
+    let conflicts = keybindings.conflicts();
+
+    for (action, children) in q_button.iter() {
+        let label = match pending.as_deref() {
+            Some(PendingRebind(pending)) if pending == action => match pending.axis() {
+                Some(InputBinding::MouseMove) => "Move the mouse...".to_string(),
+                Some(_) => "Scroll...".to_string(),
+                None => "Press a key...".to_string(),
+            },
+            _ => keybindings
+                .get(*action)
+                .map(|binding| binding.label())
+                .unwrap_or_default(),
+        };
+        let color = match conflicts.contains(action) {
+            true => CONFLICT_TEXT_COLOR,
+            false => TEXT_COLOR,
+        };
+
+        for child in children.iter() {
+            if let Ok((mut text, mut text_color)) = q_text.get_mut(*child) {
+                if text.0 != label {
+                    text.0 = label.clone();
+                }
+                if text_color.0 != color {
+                    text_color.0 = color;
+                }
+            }
+        }
+    }
+
+    for mut text in q_warning.iter_mut() {
+        let warning = match conflicts.is_empty() {
+            true => "",
+            false => "Some actions share the same binding",
+        };
+        if text.0 != warning {
+            text.0 = warning.to_string();
+        }
+    }
+}
+
+fn menu_action(
+    mut commands: Commands,
+    interaction_query: Query<
+        (&Interaction, &MenuButtonAction),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut app_exit_events: EventWriter<AppExit>,
+    mut menu_state: ResMut<NextState<MenuState>>,
+    q_address: Query<&TextInputValue, With<AddressInput>>,
+    q_name: Query<&TextInputValue, With<NameInput>>,
+    mut events: EventWriter<PlayButtonPressed>,
+    mut keybindings: ResMut<Keybindings>,
+    mut client_info: ResMut<ClientInfo>,
+    mut refresh_events: EventWriter<RefreshServerListEvent>,
+) {
+    for (interaction, menu_button_action) in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            match menu_button_action {
+                MenuButtonAction::Quit => {
+                    app_exit_events.send(AppExit::Success);
+                }
+                MenuButtonAction::Play => {
+                    let address = q_address.get_single().expect("AddressInput not found");
+                    let name = q_name.get_single().expect("NameInput not found");
+
+                    events.send(PlayButtonPressed {
+                        address: address.0.clone(),
+                        name: name.0.clone(),
+                    });
+
+                    menu_state.set(MenuState::Disabled);
+                }
+                MenuButtonAction::ServerBrowser | MenuButtonAction::Customize => {
+                    // Keep the typed name, the inputs are despawned with the main menu
+                    if let Ok(name) = q_name.get_single() {
+                        client_info.name = name.0.clone();
+                    }
+                    if let Ok(address) = q_address.get_single() {
+                        client_info.address = address.0.clone();
+                    }
+
+                    menu_state.set(match menu_button_action {
+                        MenuButtonAction::Customize => MenuState::Customize,
+                        _ => MenuState::ServerBrowser,
+                    });
+                }
+                MenuButtonAction::RefreshServers => {
+                    refresh_events.send(RefreshServerListEvent);
+                }
+                MenuButtonAction::JoinServer(address) => {
+                    events.send(PlayButtonPressed {
+                        address: address.clone(),
+                        name: client_info.name.clone(),
+                    });
+
+                    menu_state.set(MenuState::Disabled);
+                }
+                MenuButtonAction::Replays => menu_state.set(MenuState::Replays),
+                MenuButtonAction::WatchReplay(path) => match load_replay(path) {
+                    Ok(replay) => {
+                        commands.insert_resource(ReplayPlayback::new(replay));
+                        menu_state.set(MenuState::Disabled);
+                    }
+                    Err(error) => {
+                        commands.insert_resource(ConnectionError(error));
+                        menu_state.set(MenuState::ConnectionError);
+                    }
+                },
+                MenuButtonAction::Settings => menu_state.set(MenuState::Settings),
+                MenuButtonAction::SettingsDisplay => {
+                    menu_state.set(MenuState::SettingsDisplay);
+                }
+                MenuButtonAction::SettingsSound => {
+                    menu_state.set(MenuState::SettingsSound);
+                }
+                MenuButtonAction::SettingsControls => {
+                    menu_state.set(MenuState::SettingsControls);
+                }
+                MenuButtonAction::ResetControls => {
+                    *keybindings = Keybindings::default();
                 }
                 MenuButtonAction::BackToMainMenu => menu_state.set(MenuState::Main),
                 MenuButtonAction::BackToSettings => {
@@ -3,28 +3,50 @@
 #![allow(clippy::type_complexity)]
 
 pub mod audio;
+pub mod browser;
 pub mod camera;
 pub mod client;
 pub mod gui;
 pub mod input;
+pub mod keybindings;
+pub mod level;
+pub mod lobby;
 pub mod main_menu;
+pub mod netstats;
 pub mod particles;
 pub mod protocol;
+pub mod reconnect;
 pub mod renderer;
+pub mod replay;
+pub mod rooms;
+pub mod rumble;
+pub mod settings;
+pub mod touch;
 
 #[cfg(feature = "debug")]
 pub mod debug;
 
 pub mod prelude {
     pub use super::audio::prelude::*;
+    pub use super::browser::prelude::*;
     pub use super::camera::prelude::*;
     pub use super::client::prelude::*;
     pub use super::gui::prelude::*;
     pub use super::input::prelude::*;
+    pub use super::keybindings::prelude::*;
+    pub use super::level::prelude::*;
+    pub use super::lobby::prelude::*;
     pub use super::main_menu::prelude::*;
+    pub use super::netstats::prelude::*;
     pub use super::particles::prelude::*;
     pub use super::protocol::prelude::*;
+    pub use super::reconnect::prelude::*;
     pub use super::renderer::prelude::*;
+    pub use super::replay::prelude::*;
+    pub use super::rooms::prelude::*;
+    pub use super::rumble::prelude::*;
+    pub use super::settings::prelude::*;
+    pub use super::touch::prelude::*;
 
     #[cfg(feature = "debug")]
     pub use super::debug::prelude::*;
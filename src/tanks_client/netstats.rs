@@ -0,0 +1,155 @@
+//! Overlay with the quality of the connection to the server
+
+use std::time::Duration;
+
+use bevy::{prelude::*, time::common_conditions::on_timer};
+use bevy_replicon_renet2::renet2::RenetClient;
+
+use crate::prelude::*;
+
+pub mod prelude {
+    pub use super::{NetStatsPlugin, NetStatsVisible};
+}
+
+/// The key that shows and hides the net stats overlay
+const NET_STATS_KEY: KeyCode = KeyCode::F3;
+
+/// How often the overlay is refreshed
+const NET_STATS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The round trip time (in milliseconds) above which the warning icon is shown
+const HIGH_RTT_MS: f64 = 150.0;
+
+/// The packet loss (as a fraction) above which the warning icon is shown
+const HIGH_PACKET_LOSS: f64 = 0.05;
+
+/// Whether the net stats overlay is shown
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NetStatsVisible(pub bool);
+
+#[derive(Component, Clone, Copy, Debug)]
+struct NetStatsOverlay;
+
+#[derive(Component, Clone, Copy, Debug)]
+struct NetStatsWarning;
+
+/// This plugin shows the round trip time, the packet loss and the bandwidth of the connection
+/// when `NET_STATS_KEY` is pressed, and a warning icon when the connection is bad.
+#[derive(Debug, Clone)]
+pub struct NetStatsPlugin;
+
+impl Plugin for NetStatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NetStatsVisible>();
+
+        app.add_systems(OnEnter(GameStates::Playing), setup_net_stats);
+        app.add_systems(
+            Update,
+            (
+                toggle_net_stats,
+                update_net_stats.run_if(on_timer(NET_STATS_INTERVAL)),
+            )
+                .chain()
+                .run_if(in_state(GameStates::Playing)),
+        );
+    }
+}
+
+fn setup_net_stats(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    visible: Res<NetStatsVisible>,
+) {
+    commands.spawn((
+        Name::new("NetStatsOverlay"),
+        NetStatsOverlay,
+        Text::new(""),
+        TextFont {
+            font_size: 16.0,
+            ..default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.0),
+            right: Val::Px(10.0),
+            padding: UiRect::all(Val::Px(6.0)),
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.5)),
+        net_stats_visibility(visible.0),
+        StateScoped(GameStates::Playing),
+    ));
+
+    commands.spawn((
+        Name::new("NetStatsWarning"),
+        NetStatsWarning,
+        ImageNode::new(asset_server.load("textures/GameIcons/warning.png"))
+            .with_color(Color::srgb(1.0, 0.8, 0.0)),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.0),
+            left: Val::Px(10.0),
+            width: Val::Px(32.0),
+            height: Val::Px(32.0),
+            ..default()
+        },
+        Visibility::Hidden,
+        StateScoped(GameStates::Playing),
+    ));
+}
+
+fn net_stats_visibility(visible: bool) -> Visibility {
+    match visible {
+        true => Visibility::Inherited,
+        false => Visibility::Hidden,
+    }
+}
+
+fn toggle_net_stats(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut visible: ResMut<NetStatsVisible>,
+    mut q_overlay: Query<&mut Visibility, With<NetStatsOverlay>>,
+) {
+    if !keys.just_pressed(NET_STATS_KEY) {
+        return;
+    }
+
+    visible.0 = !visible.0;
+    for mut visibility in q_overlay.iter_mut() {
+        *visibility = net_stats_visibility(visible.0);
+    }
+}
+
+fn update_net_stats(
+    client: Option<Res<RenetClient>>,
+    mut q_overlay: Query<&mut Text, With<NetStatsOverlay>>,
+    mut q_warning: Query<&mut Visibility, With<NetStatsWarning>>,
+) {
+    let network_info = client
+        .filter(|client| client.is_connected())
+        .map(|client| client.network_info());
+
+    let (text, warning) = match network_info {
+        Some(info) => (
+            format!(
+                "RTT: {:.0} ms\nLoss: {:.1}%\nUp: {:.1} KB/s\nDown: {:.1} KB/s",
+                info.rtt,
+                info.packet_loss * 100.0,
+                info.bytes_sent_per_second / 1024.0,
+                info.bytes_received_per_second / 1024.0
+            ),
+            info.rtt > HIGH_RTT_MS || info.packet_loss > HIGH_PACKET_LOSS,
+        ),
+        None => ("Not connected".to_string(), false),
+    };
+
+    for mut overlay in q_overlay.iter_mut() {
+        if overlay.0 != text {
+            overlay.0 = text.clone();
+        }
+    }
+
+    for mut visibility in q_warning.iter_mut() {
+        visibility.set_if_neq(net_stats_visibility(warning));
+    }
+}
@@ -26,7 +26,7 @@ impl Plugin for ParticleEffectsPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(HanabiPlugin);
 
-        app.add_systems(Startup, setup);
+        app.add_systems(Update, setup.run_if(resource_changed::<DisplayQuality>));
         app.add_systems(
             Update,
             (play_cannon_fired, play_shell_impact, play_player_died)
@@ -38,9 +38,14 @@ impl Plugin for ParticleEffectsPlugin {
 
 fn setup(
     mut commands: Commands,
+    display_quality: Res<DisplayQuality>,
     mut effects: ResMut<Assets<EffectAsset>>,
     mut meshes: ResMut<Assets<Mesh>>,
 ) {
+    // Scale down the number of particles for the lower display qualities
+    let explosion_count = (2048.0 * display_quality.particle_scale()) as u32;
+    let smoke_count = (16.0 * display_quality.particle_scale()).max(1.0);
+
     // Small explosion
     let mut color_gradient1 = Gradient::new();
     color_gradient1.add_key(0.0, Vec4::new(4.0, 4.0, 4.0, 1.0));
@@ -93,14 +98,14 @@ fn setup(
     let trail = ParticleGroupSet::single(1);
 
     let effect = EffectAsset::new(
-        // 2k lead particles, with 32 trail particles each
-        2048,
-        Spawner::once(2048.0.into(), true),
+        // up to 2k lead particles, with 32 trail particles each
+        explosion_count,
+        Spawner::once((explosion_count as f32).into(), true),
         writer.finish(),
     )
     // Tie together trail particles to make arcs. This way we don't need a lot of them, yet there's
     // a continuity between them.
-    .with_ribbons(2048 * 32, 1.0 / 64.0, 0.2, 0)
+    .with_ribbons(explosion_count * 32, 1.0 / 64.0, 0.2, 0)
     .with_name("Explosion")
     .init_groups(init_pos, lead)
     .init_groups(init_vel, lead)
@@ -190,14 +195,14 @@ fn setup(
     let trail = ParticleGroupSet::single(1);
 
     let effect = EffectAsset::new(
-        // 2k lead particles, with 32 trail particles each
-        2048,
-        Spawner::once(2048.0.into(), true),
+        // up to 2k lead particles, with 32 trail particles each
+        explosion_count,
+        Spawner::once((explosion_count as f32).into(), true),
         writer.finish(),
     )
     // Tie together trail particles to make arcs. This way we don't need a lot of them, yet there's
     // a continuity between them.
-    .with_ribbons(2048 * 32, 1.0 / 64.0, 0.2, 0)
+    .with_ribbons(explosion_count * 32, 1.0 / 64.0, 0.2, 0)
     .with_name("Explosion")
     .init_groups(init_pos, lead)
     .init_groups(init_vel, lead)
@@ -286,7 +291,7 @@ fn setup(
 
     let module = writer.finish();
 
-    let effect = EffectAsset::new(256, Spawner::once(16.0.into(), true), module)
+    let effect = EffectAsset::new(256, Spawner::once(smoke_count.into(), true), module)
         .with_name("Smoke")
         .init(init_xz_pos)
         .init(init_y_pos)
@@ -341,7 +346,7 @@ fn play_shell_impact(
             Name::new("Impact"),
             ParticleEffectBundle {
                 effect: ParticleEffect::new(particle_systems.impact.clone()),
-                transform: Transform::from_translation(**event),
+                transform: Transform::from_translation(event.position),
                 ..Default::default()
             },
             DespawnAfter::new(2.0),
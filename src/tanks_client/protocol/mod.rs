@@ -1,3 +1,5 @@
+use std::{net::Ipv6Addr, time::Duration};
+
 use bevy::{
     ecs::world::CommandQueue,
     prelude::*,
@@ -5,6 +7,7 @@ use bevy::{
 };
 use bevy_replicon::prelude::*;
 use bevy_replicon_renet2::{
+    netcode::NetcodeClientTransport,
     renet2::{ConnectionConfig, RenetClient},
     RenetChannelsExt, RepliconRenetPlugins,
 };
@@ -16,22 +19,85 @@ use crate::prelude::*;
 mod protocol_native;
 
 #[cfg(not(target_family = "wasm"))]
-use protocol_native::create_client;
+pub(crate) use protocol_native::{create_client, http_get};
 
 #[cfg(target_family = "wasm")]
 mod protocol_wasm;
 
 #[cfg(target_family = "wasm")]
-use protocol_wasm::create_client;
+pub(crate) use protocol_wasm::{create_client, http_get};
 
 pub mod prelude {
     pub use super::{
-        ClientConnectEvent, ClientProtocolPlugin, ClientProtocolSet, LocalPlayer, LocalPlayerEntity,
+        ClientConnectEvent, ClientProtocolPlugin, ClientProtocolSet, ConnectionError, LocalPlayer,
+        LocalPlayerEntity, SessionToken, DEFAULT_HTTP_PORT,
+    };
+}
+
+/// How long the client waits for the server before giving up
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The default port of the HTTP server of a game server
+pub const DEFAULT_HTTP_PORT: u16 = 5000;
+
+/// Splits an address in the form `host[:port]` into the host and the HTTP port. An IPv6 host has
+/// to be written in brackets, like `[::1]:5000`, the brackets are not part of the returned host.
+pub(crate) fn split_address(address: &str) -> Result<(String, u16), String> {
+    let address = address.trim();
+    let parse_port = |port: &str| {
+        port.parse::<u16>()
+            .map_err(|_| format!("Invalid port in the address {}", address))
     };
+
+    if let Some(rest) = address.strip_prefix('[') {
+        let (host, port) = rest
+            .split_once(']')
+            .ok_or_else(|| format!("Missing ] in the address {}", address))?;
+        host.parse::<Ipv6Addr>()
+            .map_err(|_| format!("Invalid IPv6 address {}", host))?;
+        let port = match port {
+            "" => DEFAULT_HTTP_PORT,
+            port => match port.strip_prefix(':') {
+                Some(port) => parse_port(port)?,
+                None => return Err(format!("Invalid address {}", address)),
+            },
+        };
+
+        return Ok((host.to_string(), port));
+    }
+
+    match address.split_once(':') {
+        Some((_, port)) if port.contains(':') => Err(format!(
+            "IPv6 addresses have to be written in brackets, like [{}]",
+            address
+        )),
+        Some((host, port)) => Ok((host.to_string(), parse_port(port)?)),
+        None => Ok((address.to_string(), DEFAULT_HTTP_PORT)),
+    }
+}
+
+/// The host as it is written in an url, in brackets if it is an IPv6 address
+pub(crate) fn url_host(host: &str) -> String {
+    match host.contains(':') {
+        true => format!("[{}]", host),
+        false => host.to_string(),
+    }
+}
+
+/// Fetches the version of the server from its `/info` endpoint and compares it with ours
+async fn check_server_version(address: &str) -> Result<(), String> {
+    let (host, http_port) = split_address(address)?;
+    let body = http_get(format!("http://{}:{}/info", url_host(&host), http_port))
+        .await
+        .map_err(|e| format!("Failed to get the server version: {}", e))?;
+    let server = serde_json::from_str::<ServerVersion>(&body)
+        .map_err(|e| format!("Invalid server version: {}", e))?;
+
+    ServerVersion::current().check(&server)
 }
 
 /// The ClientConnectEvent is an event that is sent when the client wants to connect to a server
-/// with the given address.
+/// with the given address. The address can contain the HTTP port of the server (`host:port`).
 #[derive(Debug, Clone, Event)]
 pub struct ClientConnectEvent {
     pub address: String,
@@ -43,9 +109,28 @@ pub struct LocalPlayer(pub ClientId);
 #[derive(Resource, Debug, Clone, Deref, DerefMut)]
 pub struct LocalPlayerEntity(pub Entity);
 
+/// The SessionToken identifies this client across reconnects, the server uses it to restore the
+/// score of a player that lost the connection
+#[derive(Resource, Debug, Clone, Copy, Deref)]
+pub struct SessionToken(pub u64);
+
+impl Default for SessionToken {
+    fn default() -> Self {
+        Self(rand::random())
+    }
+}
+
+/// The ConnectionError resource holds the reason of the last failed connection attempt
+#[derive(Resource, Debug, Clone, Deref)]
+pub struct ConnectionError(pub String);
+
 #[derive(Resource, Debug)]
 struct ConnectTask(pub Task<CommandQueue>);
 
+/// The time left to connect to the server
+#[derive(Resource, Debug)]
+struct ConnectTimeout(Timer);
+
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ClientProtocolSet;
 
@@ -56,8 +141,12 @@ impl Plugin for ClientProtocolPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(NetworkPlugin);
         app.add_plugins(RepliconRenetPlugins);
+        app.add_plugins(NetworkSimulatorPlugin);
 
         app.add_event::<ClientConnectEvent>();
+        app.init_resource::<SessionToken>();
+        app.init_resource::<ServerTick>();
+        app.init_resource::<CurrentLevel>();
 
         app.add_systems(
             Update,
@@ -73,6 +162,17 @@ impl Plugin for ClientProtocolPlugin {
                 .run_if(not(resource_exists::<RenetClient>))
                 .run_if(resource_exists::<ConnectTask>),
         );
+        app.add_systems(
+            PreUpdate,
+            (
+                (reset_server_tick, reset_current_level).run_if(client_just_connected),
+                sync_server_tick,
+                sync_current_level,
+            )
+                .chain()
+                .in_set(ClientProtocolSet)
+                .after(ClientSet::Receive),
+        );
         app.add_systems(
             Update,
             (update_local_player_entity)
@@ -82,11 +182,39 @@ impl Plugin for ClientProtocolPlugin {
         );
 
         app.add_systems(
-            OnExit(GameStates::Playing),
+            Update,
+            (
+                update_connect_timeout.run_if(resource_exists::<ConnectTimeout>),
+                clear_connect_timeout.run_if(client_just_connected),
+            )
+                .in_set(ClientProtocolSet),
+        );
+        app.add_systems(
+            Update,
+            handle_connection_failed
+                .in_set(ClientProtocolSet)
+                .run_if(in_state(GameStates::Connecting))
+                .run_if(client_just_disconnected),
+        );
+
+        app.add_systems(
+            OnExit(InGame),
             (disconnect_client)
                 .in_set(ClientProtocolSet)
                 .run_if(resource_exists::<LocalPlayer>),
         );
+        // The client is connected without a room while it picks one
+        app.add_systems(
+            OnTransition {
+                exited: GameStates::Rooms,
+                entered: GameStates::MainMenu,
+            },
+            (disconnect_client).in_set(ClientProtocolSet),
+        );
+        app.add_systems(
+            OnExit(GameStates::Connecting),
+            (reset_connection).in_set(ClientProtocolSet),
+        );
     }
 }
 
@@ -94,30 +222,54 @@ pub fn generate_connect_task(
     mut commands: Commands,
     channels: Res<RepliconChannels>,
     mut connect_events: EventReader<ClientConnectEvent>,
+    network_conditions: Res<SharedNetworkConditions>,
 ) {
     let thread_pool = AsyncComputeTaskPool::get();
 
     for ClientConnectEvent { address } in connect_events.read() {
         let address = address.clone();
+        let network_conditions = network_conditions.clone();
         let config = ConnectionConfig::from_channels(
             channels.get_server_configs(),
             channels.get_client_configs(),
         );
 
-        let task = thread_pool.spawn(async move {
-            let (client, transport) = create_client(address, config, PROTOCOL_ID).await.unwrap();
+        info!("Connecting to {}", address);
 
+        let task = thread_pool.spawn(async move {
             let mut command_queue = CommandQueue::default();
-            command_queue.push(move |world: &mut World| {
-                world.insert_resource(LocalPlayer(ClientId::new(transport.client_id())));
-                world.insert_resource(client);
-                world.insert_resource(transport);
-            });
+
+            let client = match check_server_version(&address).await {
+                Ok(()) => {
+                    create_client(address.clone(), config, PROTOCOL_ID, network_conditions).await
+                }
+                Err(error) => Err(error),
+            };
+
+            match client {
+                Ok((client, transport)) => {
+                    command_queue.push(move |world: &mut World| {
+                        world.insert_resource(LocalPlayer(ClientId::new(transport.client_id())));
+                        world.insert_resource(client);
+                        world.insert_resource(transport);
+                    });
+                }
+                Err(error) => {
+                    command_queue.push(move |world: &mut World| {
+                        world.insert_resource(ConnectionError(format!(
+                            "Could not connect to {}: {}",
+                            address, error
+                        )));
+                    });
+                }
+            }
 
             command_queue
         });
 
+        commands.remove_resource::<ConnectionError>();
         commands.insert_resource(ConnectTask(task));
+        commands.insert_resource(ConnectTimeout(Timer::new(CONNECT_TIMEOUT, TimerMode::Once)));
     }
 }
 
@@ -128,6 +280,56 @@ fn handle_connect_task(mut commands: Commands, mut task: ResMut<ConnectTask>) {
     }
 }
 
+fn update_connect_timeout(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut timeout: ResMut<ConnectTimeout>,
+) {
+    if timeout.0.tick(time.delta()).just_finished() {
+        // Dropping the task cancels it, a late connection can not replace the error
+        commands.remove_resource::<ConnectTask>();
+        commands.remove_resource::<ConnectTimeout>();
+        commands.insert_resource(ConnectionError(
+            "Timed out while connecting to the server".to_string(),
+        ));
+    }
+}
+
+fn clear_connect_timeout(mut commands: Commands) {
+    commands.remove_resource::<ConnectTimeout>();
+}
+
+fn handle_connection_failed(mut commands: Commands, client: Option<Res<RenetClient>>) {
+    let reason = client
+        .and_then(|client| client.disconnect_reason())
+        .map(|reason| format!("The server closed the connection: {}", reason))
+        .unwrap_or_else(|| "The server closed the connection".to_string());
+
+    commands.insert_resource(ConnectionError(reason));
+}
+
+// Drops the pending connection when leaving the connecting screen without connecting
+fn reset_connection(
+    mut commands: Commands,
+    client: Option<ResMut<RenetClient>>,
+    local_player_entity: Option<Res<LocalPlayerEntity>>,
+) {
+    commands.remove_resource::<ConnectTask>();
+    commands.remove_resource::<ConnectTimeout>();
+
+    let connected = client.as_ref().is_some_and(|client| client.is_connected());
+    if connected || local_player_entity.is_some() {
+        return;
+    }
+
+    if let Some(mut client) = client {
+        client.disconnect();
+    }
+    commands.remove_resource::<LocalPlayer>();
+    commands.remove_resource::<RenetClient>();
+    commands.remove_resource::<NetcodeClientTransport>();
+}
+
 fn update_local_player_entity(
     mut commands: Commands,
     local_player: Res<LocalPlayer>,
@@ -140,9 +342,73 @@ fn update_local_player_entity(
     }
 }
 
-fn disconnect_client(mut commands: Commands, mut client: ResMut<RenetClient>) {
-    client.disconnect();
+// The ticks of the previous server would look like future ticks to the new one
+fn reset_server_tick(mut tick: ResMut<ServerTick>) {
+    *tick = ServerTick::default();
+}
+
+fn sync_server_tick(q_tick: Query<&ServerTick, Changed<ServerTick>>, mut tick: ResMut<ServerTick>) {
+    for replicated in q_tick.iter() {
+        *tick = *replicated;
+    }
+}
+
+// The level of the previous server must not be loaded while the new one replicates its own
+fn reset_current_level(mut current_level: ResMut<CurrentLevel>) {
+    *current_level = CurrentLevel::default();
+}
+
+fn sync_current_level(
+    q_level: Query<&CurrentLevel, Changed<CurrentLevel>>,
+    mut current_level: ResMut<CurrentLevel>,
+) {
+    for replicated in q_level.iter() {
+        current_level.set_if_neq(replicated.clone());
+    }
+}
+
+fn disconnect_client(mut commands: Commands, client: Option<ResMut<RenetClient>>) {
+    if let Some(mut client) = client {
+        client.disconnect();
+    }
+    commands.remove_resource::<ConnectTask>();
+    commands.remove_resource::<ConnectTimeout>();
     commands.remove_resource::<LocalPlayer>();
     commands.remove_resource::<LocalPlayerEntity>();
     commands.remove_resource::<RenetClient>();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn address_is_split_into_host_and_port() {
+        assert_eq!(
+            split_address("localhost"),
+            Ok(("localhost".to_string(), DEFAULT_HTTP_PORT))
+        );
+        assert_eq!(
+            split_address("127.0.0.1:8080"),
+            Ok(("127.0.0.1".to_string(), 8080))
+        );
+        assert!(split_address("localhost:port").is_err());
+    }
+
+    #[test]
+    fn ipv6_address_needs_brackets() {
+        assert_eq!(
+            split_address("[::1]"),
+            Ok(("::1".to_string(), DEFAULT_HTTP_PORT))
+        );
+        assert_eq!(
+            split_address("[2001:db8::1]:8080"),
+            Ok(("2001:db8::1".to_string(), 8080))
+        );
+        assert!(split_address("::1").is_err());
+        assert!(split_address("2001:db8::1:8080").is_err());
+        assert!(split_address("[::1").is_err());
+        assert_eq!(url_host("::1"), "[::1]");
+        assert_eq!(url_host("localhost"), "localhost");
+    }
+}
@@ -1,34 +1,70 @@
 use std::{
-    net::{Ipv4Addr, SocketAddr, UdpSocket},
-    time::SystemTime,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+    time::{Duration, SystemTime},
 };
 
 use bevy_replicon_renet2::renet2::{ConnectionConfig, RenetClient};
 use renet2_netcode::{ClientAuthentication, ClientSocket, NativeSocket, NetcodeClientTransport};
 
+use super::{split_address, url_host, CONNECT_TIMEOUT};
+use crate::netsim::{SharedNetworkConditions, SimulatedSocket};
+
+/// Sends a GET request to the given url and returns the body of the response
+pub async fn http_get(url: String) -> Result<String, String> {
+    let agent = ureq::AgentBuilder::new().timeout(CONNECT_TIMEOUT).build();
+
+    agent
+        .get(&url)
+        .call()
+        .map_err(|e| e.to_string())?
+        .into_string()
+        .map_err(|e| e.to_string())
+}
+
+/// Resolves the host (an IP address or a hostname) to a socket address
+fn resolve(host: &str, port: u16) -> Result<SocketAddr, String> {
+    let addrs = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| format!("Failed to resolve {}: {}", host, e))?
+        .collect::<Vec<_>>();
+
+    // Prefer IPv4, an IPv6 address is used when the host has no IPv4 one
+    addrs
+        .iter()
+        .find(|addr| addr.is_ipv4())
+        .or_else(|| addrs.first())
+        .copied()
+        .ok_or_else(|| format!("No address found for {}", host))
+}
+
 pub async fn create_client(
     address: String,
     config: ConnectionConfig,
     protocol_id: u64,
+    network_conditions: SharedNetworkConditions,
 ) -> Result<(RenetClient, NetcodeClientTransport), String> {
-    let http_path = format!("http://{}:5000/native", address);
-    let server_port = ureq::get(&http_path)
-        .call()
-        .unwrap()
-        .into_string()
-        .unwrap()
+    let (address, http_port) = split_address(&address)?;
+    let http_path = format!("http://{}:{}/native", url_host(&address), http_port);
+    let server_port = http_get(http_path)
+        .await
+        .map_err(|e| format!("Failed to reach the server: {}", e))?
+        .trim()
         .parse::<u16>()
-        .unwrap();
+        .map_err(|e| format!("Invalid server response: {}", e))?;
 
-    let server_addr = SocketAddr::new(address.parse().unwrap(), server_port);
+    let server_addr = resolve(&address, server_port)?;
 
-    let client_socket = NativeSocket::new(
-        UdpSocket::bind(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0)).unwrap(),
-    )
-    .unwrap();
+    let local_ip = match server_addr {
+        SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    };
+    let client_socket = UdpSocket::bind(SocketAddr::new(local_ip, 0))
+        .and_then(NativeSocket::new)
+        .map_err(|e| format!("Failed to bind the socket: {}", e))?;
+    let client_socket = SimulatedSocket::new(client_socket, network_conditions);
     let current_time = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap();
+        .unwrap_or(Duration::ZERO);
     let client_id = current_time.as_millis() as u64;
     let authentication = ClientAuthentication::Unsecure {
         socket_id: 0,
@@ -39,8 +75,8 @@ pub async fn create_client(
     };
 
     let client = RenetClient::new(config, client_socket.is_reliable());
-    let transport =
-        NetcodeClientTransport::new(current_time, authentication, client_socket).unwrap();
+    let transport = NetcodeClientTransport::new(current_time, authentication, client_socket)
+        .map_err(|e| e.to_string())?;
 
     Ok((client, transport))
 }
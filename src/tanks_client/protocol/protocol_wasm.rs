@@ -1,4 +1,4 @@
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 
 use bevy_replicon_renet2::renet2::{ConnectionConfig, RenetClient};
 use renet2_netcode::{
@@ -17,30 +17,53 @@ extern "C" {
     fn fetch_with_str(url: &str) -> js_sys::Promise;
 }
 
+use super::{split_address, url_host};
+use crate::netsim::SharedNetworkConditions;
+
+/// Sends a GET request to the given url and returns the body of the response
+pub async fn http_get(url: String) -> Result<String, String> {
+    let opts = RequestInit::new();
+    opts.set_method("GET");
+    opts.set_mode(RequestMode::Cors);
+
+    let request = Request::new_with_str_and_init(&url, &opts).map_err(|e| format!("{:?}", e))?;
+
+    let window = web_sys::window().ok_or("no window")?;
+    let resp_value = JsFuture::from(window.fetch_with_request(&request))
+        .await
+        .map_err(|e| format!("{:?}", e))?;
+
+    let resp: Response = resp_value.dyn_into().map_err(|e| format!("{:?}", e))?;
+    if !resp.ok() {
+        return Err(format!("{} returned {}", url, resp.status()));
+    }
+
+    let text = JsFuture::from(resp.text().map_err(|e| format!("{:?}", e))?)
+        .await
+        .map_err(|e| format!("{:?}", e))?;
+
+    text.as_string()
+        .ok_or_else(|| "response is not text".to_string())
+}
+
 pub async fn create_client(
     address: String,
     config: ConnectionConfig,
     protocol_id: u64,
+    // The browser sockets are not wrapped, the conditions are only simulated on native
+    _network_conditions: SharedNetworkConditions,
 ) -> Result<(RenetClient, NetcodeClientTransport), String> {
-    let opts = RequestInit::new();
-    opts.set_method("GET");
-    opts.set_mode(RequestMode::Cors);
+    let (address, http_port) = split_address(&address)?;
 
-    let url = format!("http://{}:5000/wasm", address);
+    let url = format!("http://{}:{}/wasm", url_host(&address), http_port);
 
     tracing::info!("getting server info from {}", url);
-    let request = Request::new_with_str_and_init(&url, &opts).unwrap();
-
-    let window = web_sys::window().unwrap();
-    let resp_value = JsFuture::from(window.fetch_with_request(&request))
+    let body = http_get(url)
         .await
-        .unwrap();
-
-    let resp: Response = resp_value.dyn_into().unwrap();
-    let json = JsFuture::from(resp.json().unwrap()).await.unwrap();
+        .map_err(|e| format!("Failed to reach the server: {}", e))?;
 
-    let (wt_port, cert_hash, ws_port) =
-        serde_wasm_bindgen::from_value::<(u16, ServerCertHash, u16)>(json).unwrap();
+    let (wt_port, cert_hash, ws_port) = serde_json::from_str::<(u16, ServerCertHash, u16)>(&body)
+        .map_err(|e| format!("Invalid server response: {}", e))?;
     tracing::debug!(
         "wt_port = {}, cert_hash = {:?}, ws_port = {}",
         wt_port,
@@ -50,55 +73,68 @@ pub async fn create_client(
 
     let current_time = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap();
+        .map_err(|e| e.to_string())?;
     let client_id = current_time.as_millis() as u64;
-    if webtransport_is_available_with_cert_hashes() {
-        let server_addr = SocketAddr::new(address.parse().unwrap(), wt_port);
-        tracing::info!(
-            "setting up webtransport client (server = {:?})",
-            server_addr
-        );
-
-        let authentication = ClientAuthentication::Unsecure {
-            client_id,
-            protocol_id,
-            socket_id: 1,
-            server_addr,
-            user_data: None,
-        };
-        let socket_config = WebTransportClientConfig {
-            server_dest: server_addr.into(),
-            congestion_control: CongestionControl::default(),
-            server_cert_hashes: Vec::from([cert_hash]),
-        };
-        let socket = WebTransportClient::new(socket_config);
-
-        let client = RenetClient::new(config, socket.is_reliable());
-        let transport = NetcodeClientTransport::new(current_time, authentication, socket).unwrap();
-
-        Ok((client, transport))
-    } else {
-        tracing::warn!("webtransport with cert hashes is not supported on this platform, falling back to websockets");
-        let server_url = url::Url::parse(&format!("ws://{}:{}/ws", address, ws_port)).unwrap();
-        tracing::info!(
-            "setting up websocket client (server = {:?})",
-            server_url.as_str()
-        );
-
-        let socket_config = WebSocketClientConfig { server_url };
-        let server_addr = socket_config.server_address().unwrap();
-        let authentication = ClientAuthentication::Unsecure {
-            client_id,
-            protocol_id,
-            socket_id: 2,
-            server_addr,
-            user_data: None,
-        };
-
-        let socket = WebSocketClient::new(socket_config).unwrap();
-        let client = RenetClient::new(config, socket.is_reliable());
-        let transport = NetcodeClientTransport::new(current_time, authentication, socket).unwrap();
-
-        Ok((client, transport))
+
+    // The browser resolves hostnames only for WebSocket urls, WebTransport needs an IP address
+    let server_ip = address.parse::<IpAddr>().ok();
+    match server_ip {
+        Some(server_ip) if webtransport_is_available_with_cert_hashes() => {
+            let server_addr = SocketAddr::new(server_ip, wt_port);
+            tracing::info!(
+                "setting up webtransport client (server = {:?})",
+                server_addr
+            );
+
+            let authentication = ClientAuthentication::Unsecure {
+                client_id,
+                protocol_id,
+                socket_id: 1,
+                server_addr,
+                user_data: None,
+            };
+            let socket_config = WebTransportClientConfig {
+                server_dest: server_addr.into(),
+                congestion_control: CongestionControl::default(),
+                server_cert_hashes: Vec::from([cert_hash]),
+            };
+            let socket = WebTransportClient::new(socket_config);
+
+            let client = RenetClient::new(config, socket.is_reliable());
+            let transport = NetcodeClientTransport::new(current_time, authentication, socket)
+                .map_err(|e| e.to_string())?;
+
+            Ok((client, transport))
+        }
+        _ => {
+            tracing::warn!("webtransport with cert hashes is not available for this server, falling back to websockets");
+            let server_url =
+                url::Url::parse(&format!("ws://{}:{}/ws", url_host(&address), ws_port))
+                    .map_err(|e| format!("Invalid address {}: {}", address, e))?;
+            tracing::info!(
+                "setting up websocket client (server = {:?})",
+                server_url.as_str()
+            );
+
+            let socket_config = WebSocketClientConfig { server_url };
+            let server_addr = socket_config
+                .server_address()
+                .map_err(|e| format!("Invalid address {}: {:?}", address, e))?;
+            let authentication = ClientAuthentication::Unsecure {
+                client_id,
+                protocol_id,
+                socket_id: 2,
+                server_addr,
+                user_data: None,
+            };
+
+            let socket = WebSocketClient::new(socket_config)
+                .map_err(|e| format!("Failed to open the websocket: {:?}", e))?;
+            let client = RenetClient::new(config, socket.is_reliable());
+            let transport = NetcodeClientTransport::new(current_time, authentication, socket)
+                .map_err(|e| e.to_string())?;
+
+            Ok((client, transport))
+        }
     }
 }
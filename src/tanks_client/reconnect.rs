@@ -0,0 +1,230 @@
+//! Automatic reconnection when the connection to the server is lost during a match
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_replicon_renet2::{netcode::NetcodeClientTransport, renet2::RenetClient};
+
+use crate::prelude::*;
+
+pub mod prelude {
+    pub use super::{ReconnectPlugin, Reconnecting, MAX_RECONNECT_ATTEMPTS};
+}
+
+/// How many times the client tries to reconnect before going back to the main menu
+pub const MAX_RECONNECT_ATTEMPTS: u32 = 6;
+
+/// The delay before the first reconnect attempt, it doubles after each failed attempt
+const RECONNECT_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// The longest delay between two reconnect attempts
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(16);
+
+/// The Reconnecting resource exists while the client is trying to get back into the match
+#[derive(Resource, Debug, Clone)]
+pub struct Reconnecting {
+    /// The number of attempts made so far
+    pub attempt: u32,
+    /// The time left before the next attempt
+    pub delay: Timer,
+    /// Whether an attempt is waiting for an answer from the server
+    in_flight: bool,
+}
+
+impl Reconnecting {
+    fn backoff(attempt: u32) -> Timer {
+        let delay = RECONNECT_BASE_DELAY
+            .saturating_mul(1 << attempt.min(8))
+            .min(RECONNECT_MAX_DELAY);
+        Timer::new(delay, TimerMode::Once)
+    }
+}
+
+#[derive(Component, Clone, Copy, Debug)]
+struct ReconnectingOverlay;
+
+/// This plugin reconnects to the server in `ClientInfo::address` with an exponential backoff when
+/// the connection drops in the lobby or while playing. The server keeps the score of the player
+/// for a grace period and recognizes it by the `SessionToken`.
+#[derive(Debug, Clone)]
+pub struct ReconnectPlugin;
+
+impl Plugin for ReconnectPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                start_reconnecting
+                    .run_if(client_just_disconnected)
+                    .run_if(not(resource_exists::<ServerShutdown>))
+                    .run_if(not(resource_exists::<Reconnecting>)),
+                (
+                    handle_reconnect_failed
+                        .run_if(resource_added::<ConnectionError>.or(client_just_disconnected)),
+                    handle_reconnected.run_if(client_just_connected),
+                    update_reconnecting,
+                    update_reconnecting_overlay,
+                )
+                    .chain()
+                    .run_if(resource_exists::<Reconnecting>),
+            )
+                .chain()
+                .after(ClientProtocolSet)
+                .run_if(in_state(InGame)),
+        );
+        app.add_systems(OnExit(InGame), stop_reconnecting);
+    }
+}
+
+fn start_reconnecting(
+    mut commands: Commands,
+    client: Option<Res<RenetClient>>,
+    client_info: Res<ClientInfo>,
+) {
+    let reason = client
+        .and_then(|client| client.disconnect_reason())
+        .map(|reason| reason.to_string())
+        .unwrap_or_else(|| "unknown reason".to_string());
+    warn!(
+        "Lost the connection to {} ({}), reconnecting",
+        client_info.address, reason
+    );
+
+    drop_connection(&mut commands);
+    commands.insert_resource(Reconnecting {
+        attempt: 0,
+        delay: Reconnecting::backoff(0),
+        in_flight: false,
+    });
+
+    commands.spawn((
+        Name::new("ReconnectingOverlay"),
+        ReconnectingOverlay,
+        Text::new(""),
+        TextFont {
+            font_size: 33.0,
+            ..default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(40.0),
+            width: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        TextLayout::new_with_justify(JustifyText::Center),
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+        StateScoped(InGame),
+    ));
+}
+
+fn update_reconnecting(
+    time: Res<Time>,
+    mut reconnecting: ResMut<Reconnecting>,
+    client_info: Res<ClientInfo>,
+    mut connect: EventWriter<ClientConnectEvent>,
+) {
+    if reconnecting.in_flight || !reconnecting.delay.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    reconnecting.attempt += 1;
+    reconnecting.in_flight = true;
+    info!(
+        "Reconnect attempt {}/{}",
+        reconnecting.attempt, MAX_RECONNECT_ATTEMPTS
+    );
+
+    connect.send(ClientConnectEvent {
+        address: client_info.address.clone(),
+    });
+}
+
+fn handle_reconnect_failed(
+    mut commands: Commands,
+    mut reconnecting: ResMut<Reconnecting>,
+    error: Option<Res<ConnectionError>>,
+    mut next_state: ResMut<NextState<GameStates>>,
+) {
+    // Dropping the connection of a failed attempt also reports a disconnect
+    if !reconnecting.in_flight {
+        return;
+    }
+
+    if let Some(error) = error {
+        warn!("{}", **error);
+    }
+    drop_connection(&mut commands);
+    reconnecting.in_flight = false;
+
+    if reconnecting.attempt >= MAX_RECONNECT_ATTEMPTS {
+        commands.insert_resource(ConnectionError(
+            "Lost the connection to the server".to_string(),
+        ));
+        next_state.set(GameStates::MainMenu);
+        return;
+    }
+
+    commands.remove_resource::<ConnectionError>();
+    reconnecting.delay = Reconnecting::backoff(reconnecting.attempt);
+}
+
+fn handle_reconnected(
+    mut commands: Commands,
+    client_info: Res<ClientInfo>,
+    session_token: Res<SessionToken>,
+    tank_color: Res<TankColor>,
+    tank_skin: Res<TankSkin>,
+    q_network: Query<Entity, With<NetworkEntity>>,
+    q_overlay: Query<Entity, With<ReconnectingOverlay>>,
+    mut join: EventWriter<PlayerJoinEvent>,
+) {
+    info!("Reconnected to {}", client_info.address);
+
+    // The server replicates the world again, so the entities of the old connection are stale
+    for entity in q_network.iter().chain(q_overlay.iter()) {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.remove_resource::<Reconnecting>();
+
+    join.send(PlayerJoinEvent {
+        name: client_info.name.clone(),
+        color: tank_color.0,
+        skin: *tank_skin,
+        session_token: **session_token,
+        room: client_info.room,
+    });
+}
+
+fn update_reconnecting_overlay(
+    reconnecting: Res<Reconnecting>,
+    mut q_overlay: Query<&mut Text, With<ReconnectingOverlay>>,
+) {
+    let overlay = match reconnecting.in_flight {
+        true => format!(
+            "Reconnecting... (attempt {}/{})",
+            reconnecting.attempt, MAX_RECONNECT_ATTEMPTS
+        ),
+        false => format!(
+            "Connection lost, reconnecting in {:.0} seconds",
+            reconnecting.delay.remaining_secs().ceil()
+        ),
+    };
+
+    for mut text in q_overlay.iter_mut() {
+        if text.0 != overlay {
+            text.0 = overlay.clone();
+        }
+    }
+}
+
+fn stop_reconnecting(mut commands: Commands) {
+    commands.remove_resource::<Reconnecting>();
+}
+
+// Keeps `LocalPlayer` so the game systems keep running until the new client id arrives
+fn drop_connection(commands: &mut Commands) {
+    commands.remove_resource::<LocalPlayerEntity>();
+    commands.remove_resource::<RenetClient>();
+    commands.remove_resource::<NetcodeClientTransport>();
+}
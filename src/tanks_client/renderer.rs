@@ -1,4 +1,12 @@
-use bevy::prelude::*;
+use bevy::{
+    pbr::DirectionalLightShadowMap,
+    prelude::*,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages},
+    },
+    scene::SceneInstanceReady,
+};
 use serde::{Deserialize, Serialize};
 
 use crate::prelude::*;
@@ -11,6 +19,42 @@ pub mod prelude {
 #[derive(Component, Clone, Copy, Debug)]
 struct ClientRenderer;
 
+/// The TankMaterial replaces the materials of a tank scene once it is spawned
+#[derive(Component, Clone, Debug)]
+pub(crate) struct TankMaterial(pub(crate) Handle<StandardMaterial>);
+
+/// An image a camera can render to, with the given size (in pixels)
+pub(crate) fn render_target_image(size: UVec2) -> Image {
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: size.x.max(1),
+            height: size.y.max(1),
+            ..default()
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 0],
+        TextureFormat::Bgra8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    image.texture_descriptor.usage =
+        TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST | TextureUsages::RENDER_ATTACHMENT;
+
+    image
+}
+
+/// The material of a tank, the prototype texture of the skin tinted with the color
+pub(crate) fn tank_material(
+    color: Color,
+    skin: TankSkin,
+    game_assets: &GameAssets,
+) -> StandardMaterial {
+    StandardMaterial {
+        base_color: color,
+        base_color_texture: game_assets.prototype_textures.get(skin.0 as usize).cloned(),
+        ..default()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RendererPlugin;
 
@@ -21,55 +65,84 @@ impl Plugin for RendererPlugin {
             ..default()
         });
 
+        app.add_observer(apply_tank_material);
+
         app.add_systems(OnEnter(GameStates::Playing), spawn_renderer);
         app.add_systems(
             Update,
             (add_player_cosmetics, add_shell_cosmetics).run_if(in_state(GameStates::Playing)),
         );
+        app.add_systems(
+            Update,
+            apply_display_quality.run_if(resource_changed::<DisplayQuality>),
+        );
     }
 }
 
-fn spawn_renderer(mut commands: Commands) {
+fn spawn_renderer(mut commands: Commands, display_quality: Res<DisplayQuality>) {
     commands.spawn((
         Name::new("DirectionalLight"),
-        DirectionalLight::default(),
+        DirectionalLight {
+            shadows_enabled: display_quality.shadows_enabled(),
+            ..default()
+        },
         Transform::from_translation(Vec3::ONE).looking_at(Vec3::ZERO, Vec3::Y),
         StateScoped(GameStates::Playing),
     ));
+}
 
-    // here we actually spawn our game world/level
-    commands.spawn((
-        BlueprintInfo::from_path("levels/World.glb"), // all we need is a Blueprint info...
-        SpawnBlueprint, // and spawnblueprint to tell blenvy to spawn the blueprint now
-        HideUntilReady, // only reveal the level once it is ready
-        GameWorldTag,
-    ));
+fn apply_display_quality(
+    mut commands: Commands,
+    display_quality: Res<DisplayQuality>,
+    mut q_light: Query<&mut DirectionalLight>,
+) {
+    commands.insert_resource(DirectionalLightShadowMap {
+        size: display_quality.shadow_map_size(),
+    });
+
+    for mut light in q_light.iter_mut() {
+        light.shadows_enabled = display_quality.shadows_enabled();
+    }
 }
 
 fn add_player_cosmetics(
     mut commands: Commands,
-    q_player: Query<(Entity, &Player), Without<ClientRenderer>>,
+    q_player: Query<(Entity, &Player, Option<&TankSkin>), Without<ClientRenderer>>,
     game_assets: Res<GameAssets>,
     mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
-    for (entity, Player { name, color, .. }) in q_player.iter() {
-        // TODO: add cosmetics for player
+    for (entity, Player { name, color, .. }, skin) in q_player.iter() {
         info!("Adding cosmetics for player: {}", name);
-        let material = StandardMaterial {
-            base_color: *color,
-            ..Default::default()
-        };
+        let material = tank_material(*color, skin.copied().unwrap_or_default(), &game_assets);
         commands
             .entity(entity)
             .insert((Visibility::default(), ClientRenderer))
             .with_child((
                 Transform::from_scale(Vec3::splat(2.0)),
                 SceneRoot(game_assets.tank.clone()),
-                MeshMaterial3d(materials.add(material)),
+                TankMaterial(materials.add(material)),
             ));
     }
 }
 
+// Gives the meshes of a tank scene its material, the scene spawns them with the default ones
+fn apply_tank_material(
+    trigger: Trigger<SceneInstanceReady>,
+    q_tank: Query<&TankMaterial>,
+    q_children: Query<&Children>,
+    mut q_mesh: Query<&mut MeshMaterial3d<StandardMaterial>>,
+) {
+    let Ok(TankMaterial(material)) = q_tank.get(trigger.entity()) else {
+        return;
+    };
+
+    for entity in q_children.iter_descendants(trigger.entity()) {
+        if let Ok(mut mesh_material) = q_mesh.get_mut(entity) {
+            mesh_material.0 = material.clone();
+        }
+    }
+}
+
 fn add_shell_cosmetics(
     mut commands: Commands,
     q_shell: Query<(Entity, &Shell), Without<ClientRenderer>>,
@@ -0,0 +1,476 @@
+//! Playback of the match replays recorded by the server
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_replicon::prelude::*;
+
+use crate::prelude::*;
+use utils::prelude::*;
+
+pub mod prelude {
+    pub use super::{
+        list_replays, load_replay, ReplayCamera, ReplayPlayback, ReplayPlaybackPlugin, REPLAY_DIR,
+    };
+}
+
+/// The directory where the client looks for replays
+pub const REPLAY_DIR: &str = "replays";
+
+/// How far a seek moves in the replay (in seconds)
+const SEEK_STEP: f32 = 5.0;
+
+/// The playback speeds that can be selected
+const PLAYBACK_SPEEDS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+
+/// The speed of the free-fly camera (in meters per second)
+const FREE_FLY_SPEED: f32 = 12.0;
+
+const PAUSE_KEY: KeyCode = KeyCode::Space;
+const SEEK_BACK_KEY: KeyCode = KeyCode::Comma;
+const SEEK_FORWARD_KEY: KeyCode = KeyCode::Period;
+const SLOWER_KEY: KeyCode = KeyCode::Minus;
+const FASTER_KEY: KeyCode = KeyCode::Equal;
+const CAMERA_KEY: KeyCode = KeyCode::KeyC;
+
+/// Lists the replay files in `REPLAY_DIR`, the newest first
+pub fn list_replays() -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(REPLAY_DIR) else {
+        return Vec::new();
+    };
+
+    let mut replays = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == REPLAY_EXTENSION))
+        .collect::<Vec<_>>();
+    // The files start with the time of the recording
+    replays.sort();
+    replays.reverse();
+    replays
+}
+
+/// Reads a replay file
+pub fn load_replay(path: impl AsRef<Path>) -> Result<Replay, String> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+
+    Replay::read(BufReader::new(file))
+}
+
+/// What the camera looks at during the playback
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayCamera {
+    FreeFly,
+    Follow(ClientId),
+}
+
+/// The ReplayPlayback resource exists while a replay is watched instead of playing online
+#[derive(Resource, Debug)]
+pub struct ReplayPlayback {
+    replay: Replay,
+    /// The position in the replay (in seconds)
+    pub time: f32,
+    pub speed: f32,
+    pub paused: bool,
+    pub camera: ReplayCamera,
+    /// The index of the next frame to apply
+    next_frame: usize,
+    /// The local entities of the recorded entities
+    entities: HashMap<u64, Entity>,
+}
+
+impl ReplayPlayback {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            time: 0.0,
+            speed: 1.0,
+            paused: false,
+            camera: ReplayCamera::FreeFly,
+            next_frame: 0,
+            entities: HashMap::new(),
+        }
+    }
+
+    pub fn header(&self) -> &ReplayHeader {
+        &self.replay.header
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.replay.duration()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.time >= self.duration()
+    }
+}
+
+#[derive(Component, Clone, Copy, Debug)]
+struct ReplayOverlay;
+
+/// The local events that the replayed server events are turned into
+#[derive(SystemParam)]
+struct ReplayEventWriters<'w> {
+    fired: EventWriter<'w, CannonFiredEvent>,
+    impact: EventWriter<'w, ShellImpactEvent>,
+    joined: EventWriter<'w, PlayerJoinedEvent>,
+    left: EventWriter<'w, PlayerLeftEvent>,
+    died: EventWriter<'w, PlayerDiedEvent>,
+}
+
+impl ReplayEventWriters<'_> {
+    fn send(&mut self, event: &ReplayEvent, tick: ServerTick) {
+        match event.clone() {
+            ReplayEvent::CannonFired { position, rotation } => {
+                self.fired.send(CannonFiredEvent {
+                    position,
+                    rotation,
+                    owner: None,
+                    tick,
+                });
+            }
+            ReplayEvent::ShellImpact(position) => {
+                self.impact.send(ShellImpactEvent { position, tick });
+            }
+            ReplayEvent::PlayerJoined { client_id, name } => {
+                self.joined.send(PlayerJoinedEvent { client_id, name });
+            }
+            ReplayEvent::PlayerLeft { client_id } => {
+                self.left.send(PlayerLeftEvent { client_id });
+            }
+            ReplayEvent::PlayerDied {
+                client_id,
+                position,
+            } => {
+                self.died.send(PlayerDiedEvent {
+                    client_id,
+                    position,
+                    tick,
+                });
+            }
+        }
+    }
+}
+
+/// This plugin plays back a `ReplayPlayback` without a connection to a server. The recorded
+/// entities are spawned like replicated ones, so the renderer, the audio and the particles work
+/// as in a match.
+#[derive(Debug, Clone)]
+pub struct ReplayPlaybackPlugin;
+
+impl Plugin for ReplayPlaybackPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            start_playback
+                .run_if(in_state(GameStates::MainMenu))
+                .run_if(resource_added::<ReplayPlayback>),
+        );
+        app.add_systems(
+            OnEnter(GameStates::Playing),
+            setup_replay_overlay.run_if(resource_exists::<ReplayPlayback>),
+        );
+        app.add_systems(
+            Update,
+            (
+                update_playback_controls,
+                update_free_fly_camera.run_if(is_free_fly),
+                advance_playback,
+                update_replay_overlay,
+            )
+                .chain()
+                .run_if(in_state(GameStates::Playing))
+                .run_if(resource_exists::<ReplayPlayback>),
+        );
+        app.add_systems(OnExit(GameStates::Playing), stop_playback);
+    }
+}
+
+fn start_playback(
+    mut commands: Commands,
+    playback: Res<ReplayPlayback>,
+    mut next_state: ResMut<NextState<GameStates>>,
+) {
+    let header = playback.header();
+    info!(
+        "Watching the replay of {} ({:.0} seconds)",
+        header.level,
+        playback.duration()
+    );
+
+    // The server never gives this id to a player, so the camera does not follow anyone
+    commands.insert_resource(LocalPlayer(ClientId::SERVER));
+    commands.insert_resource(CurrentLevel(header.level.clone()));
+    next_state.set(GameStates::Playing);
+}
+
+fn stop_playback(mut commands: Commands) {
+    commands.remove_resource::<ReplayPlayback>();
+}
+
+fn is_free_fly(playback: Res<ReplayPlayback>) -> bool {
+    playback.camera == ReplayCamera::FreeFly
+}
+
+fn setup_replay_overlay(mut commands: Commands) {
+    commands.spawn((
+        Name::new("ReplayOverlay"),
+        ReplayOverlay,
+        Text::new(""),
+        TextFont {
+            font_size: 18.0,
+            ..default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(10.0),
+            left: Val::Px(10.0),
+            padding: UiRect::all(Val::Px(6.0)),
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.5)),
+        StateScoped(GameStates::Playing),
+    ));
+}
+
+/// Applies the frames up to the current time, the events are only sent while playing
+fn apply_frames(
+    commands: &mut Commands,
+    playback: &mut ReplayPlayback,
+    mut events: Option<&mut ReplayEventWriters>,
+) {
+    let ReplayPlayback {
+        replay,
+        time,
+        next_frame,
+        entities,
+        ..
+    } = playback;
+
+    while let Some(frame) = replay.frames.get(*next_frame) {
+        if frame.time > *time {
+            break;
+        }
+        *next_frame += 1;
+
+        for id in frame.despawned.iter() {
+            if let Some(entity) = entities.remove(id) {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+
+        for (id, replay_entity) in frame.entities.iter() {
+            let entity = *entities
+                .entry(*id)
+                .or_insert_with(|| commands.spawn(NetworkEntity).id());
+
+            match replay_entity.clone() {
+                ReplayEntity::Player {
+                    player,
+                    skin,
+                    transform,
+                    throttle,
+                } => commands.entity(entity).insert((
+                    Name::new("Player"),
+                    player,
+                    skin,
+                    transform,
+                    Throttle { value: throttle },
+                )),
+                ReplayEntity::Shell { transform } => {
+                    commands
+                        .entity(entity)
+                        .insert((Name::new("Shell"), Shell, transform))
+                }
+                ReplayEntity::Stats(stats) => commands
+                    .entity(entity)
+                    .insert((Name::new("PlayerStats"), stats)),
+            };
+        }
+
+        for (id, transform) in frame.transforms.iter() {
+            if let Some(entity) = entities.get(id) {
+                commands.entity(*entity).insert(*transform);
+            }
+        }
+
+        if let Some(events) = events.as_deref_mut() {
+            for event in frame.events.iter() {
+                events.send(event, frame.tick);
+            }
+        }
+    }
+}
+
+/// Moves the playback to the given time, going back replays the frames from the start
+fn seek(commands: &mut Commands, playback: &mut ReplayPlayback, time: f32) {
+    let time = time.clamp(0.0, playback.duration());
+    if time < playback.time {
+        for (_, entity) in playback.entities.drain() {
+            commands.entity(entity).despawn_recursive();
+        }
+        playback.next_frame = 0;
+    }
+
+    playback.time = time;
+    apply_frames(commands, playback, None);
+}
+
+fn update_playback_controls(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut local_player: ResMut<LocalPlayer>,
+    q_player: Query<&Player>,
+) {
+    if keys.just_pressed(PAUSE_KEY) {
+        if playback.is_finished() {
+            seek(&mut commands, &mut playback, 0.0);
+            playback.paused = false;
+        } else {
+            playback.paused = !playback.paused;
+        }
+    }
+
+    if keys.just_pressed(SEEK_BACK_KEY) {
+        let time = playback.time - SEEK_STEP;
+        seek(&mut commands, &mut playback, time);
+    }
+    if keys.just_pressed(SEEK_FORWARD_KEY) {
+        let time = playback.time + SEEK_STEP;
+        seek(&mut commands, &mut playback, time);
+    }
+
+    let speed = PLAYBACK_SPEEDS
+        .iter()
+        .position(|speed| *speed == playback.speed)
+        .unwrap_or(2);
+    if keys.just_pressed(SLOWER_KEY) {
+        playback.speed = PLAYBACK_SPEEDS[speed.saturating_sub(1)];
+    }
+    if keys.just_pressed(FASTER_KEY) {
+        playback.speed = PLAYBACK_SPEEDS[(speed + 1).min(PLAYBACK_SPEEDS.len() - 1)];
+    }
+
+    if keys.just_pressed(CAMERA_KEY) {
+        let mut players = q_player
+            .iter()
+            .map(|player| player.client_id)
+            .collect::<Vec<_>>();
+        players.sort_by_key(|client_id| client_id.get());
+
+        // Cycle through the players and back to the free-fly camera
+        let next = match playback.camera {
+            ReplayCamera::FreeFly => players.first(),
+            ReplayCamera::Follow(current) => players
+                .iter()
+                .position(|client_id| *client_id == current)
+                .and_then(|index| players.get(index + 1)),
+        };
+        playback.camera = match next {
+            Some(client_id) => ReplayCamera::Follow(*client_id),
+            None => ReplayCamera::FreeFly,
+        };
+
+        local_player.0 = match playback.camera {
+            ReplayCamera::FreeFly => ClientId::SERVER,
+            ReplayCamera::Follow(client_id) => client_id,
+        };
+        commands.remove_resource::<LocalPlayerEntity>();
+    }
+}
+
+fn update_free_fly_camera(
+    time: Res<Time<Real>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    q_camera: Query<&GlobalTransform, With<Camera3d>>,
+    mut q_smooth: Query<&mut SmoothTransform>,
+) {
+    let Ok(camera) = q_camera.get_single() else {
+        return;
+    };
+
+    let forward = camera.forward().with_y(0.0).normalize_or_zero();
+    let right = camera.right().with_y(0.0).normalize_or_zero();
+    let mut direction = Vec3::ZERO;
+    for (key, axis) in [
+        (KeyCode::KeyW, forward),
+        (KeyCode::KeyS, -forward),
+        (KeyCode::KeyD, right),
+        (KeyCode::KeyA, -right),
+        (KeyCode::KeyE, Vec3::Y),
+        (KeyCode::KeyQ, Vec3::NEG_Y),
+    ] {
+        if keys.pressed(key) {
+            direction += axis;
+        }
+    }
+
+    let offset = direction.normalize_or_zero() * FREE_FLY_SPEED * time.delta_secs();
+    for mut smooth in q_smooth.iter_mut() {
+        smooth.target += offset;
+    }
+}
+
+fn advance_playback(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut events: ReplayEventWriters,
+) {
+    if !playback.paused {
+        playback.time =
+            (playback.time + time.delta_secs() * playback.speed).min(playback.duration());
+    }
+
+    apply_frames(&mut commands, &mut playback, Some(&mut events));
+
+    if playback.is_finished() {
+        playback.paused = true;
+    }
+}
+
+fn format_time(seconds: f32) -> String {
+    let seconds = seconds as u32;
+    format!("{:02}:{:02}", seconds / 60, seconds % 60)
+}
+
+fn update_replay_overlay(
+    playback: Res<ReplayPlayback>,
+    q_player: Query<&Player>,
+    mut q_overlay: Query<&mut Text, With<ReplayOverlay>>,
+) {
+    let state = match (playback.paused, playback.is_finished()) {
+        (_, true) => "Ended",
+        (true, false) => "Paused",
+        (false, false) => "Playing",
+    };
+    let camera = match playback.camera {
+        ReplayCamera::FreeFly => "Free".to_string(),
+        ReplayCamera::Follow(client_id) => q_player
+            .iter()
+            .find(|player| player.client_id == client_id)
+            .map(|player| format!("Following {}", player.name))
+            .unwrap_or_else(|| "Following a dead tank".to_string()),
+    };
+
+    let overlay = format!(
+        "Replay {}  {} / {}  x{}\nCamera: {}\n[Space] pause  [,/.] seek  [-/=] speed  [C] camera  [Esc] leave",
+        state,
+        format_time(playback.time),
+        format_time(playback.duration()),
+        playback.speed,
+        camera
+    );
+
+    for mut text in q_overlay.iter_mut() {
+        if text.0 != overlay {
+            text.0 = overlay.clone();
+        }
+    }
+}
@@ -0,0 +1,325 @@
+//! The room screen, the client picks the room of the server to join or creates its own
+
+use bevy::prelude::*;
+
+use crate::prelude::*;
+use crate::tanks_client::main_menu::{handle_button_interact, NORMAL_BUTTON, TEXT_COLOR};
+
+pub mod prelude {
+    pub use super::{RoomMenuPlugin, RoomMenuSet};
+}
+
+/// The RoomList resource holds the rooms of the latest `RoomListEvent`
+#[derive(Resource, Debug, Default, Clone, Deref)]
+struct RoomList(Vec<RoomInfo>);
+
+// All the actions of the room buttons
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+enum RoomButton {
+    Join(RoomId),
+    Create,
+    Refresh,
+    Back,
+}
+
+#[derive(Component, Clone, Copy, Debug)]
+struct RoomListNode;
+
+// Tag component used to mark the text that shows why the server refused a room
+#[derive(Component, Clone, Copy, Debug)]
+struct RoomStatusText;
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RoomMenuSet;
+
+/// This plugin shows the rooms of the server once the client is connected. Joining a room or
+/// creating one sets `ClientInfo::room` and moves on to `GameStates::Lobby`, where the client
+/// joins it.
+#[derive(Debug, Clone)]
+pub struct RoomMenuPlugin;
+
+impl Plugin for RoomMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnEnter(GameStates::Rooms),
+            (spawn_room_ui, request_room_list),
+        );
+        app.add_systems(
+            Update,
+            (
+                handle_room_list,
+                handle_room_buttons,
+                handle_room_created,
+                handle_room_rejected,
+                handle_room_back,
+                handle_button_interact,
+                update_room_list.run_if(resource_changed::<RoomList>),
+                handle_rooms_disconnected.run_if(client_just_disconnected),
+            )
+                .in_set(RoomMenuSet)
+                .run_if(in_state(GameStates::Rooms)),
+        );
+        app.add_systems(OnExit(GameStates::Rooms), remove_room_list);
+    }
+}
+
+fn request_room_list(mut commands: Commands, mut list: EventWriter<ListRoomsEvent>) {
+    commands.init_resource::<RoomList>();
+    list.send(ListRoomsEvent);
+}
+
+fn remove_room_list(mut commands: Commands) {
+    commands.remove_resource::<RoomList>();
+}
+
+fn spawn_room_ui(mut commands: Commands, client_info: Res<ClientInfo>) {
+    let button_node = Node {
+        width: Val::Px(220.0),
+        height: Val::Px(55.0),
+        margin: UiRect::all(Val::Px(10.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let button_text_style = (
+        TextFont {
+            font_size: 24.0,
+            ..default()
+        },
+        TextColor(TEXT_COLOR),
+    );
+
+    commands.spawn((
+        Name::new("CameraUI"),
+        Camera2d,
+        StateScoped(GameStates::Rooms),
+    ));
+
+    commands
+        .spawn((
+            Name::new("RoomMenu"),
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            StateScoped(GameStates::Rooms),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(format!("Rooms of {}", client_info.address)),
+                TextFont {
+                    font_size: 40.0,
+                    ..default()
+                },
+                TextColor(TEXT_COLOR),
+            ));
+            parent.spawn((
+                RoomStatusText,
+                Text::default(),
+                button_text_style.clone(),
+                Node {
+                    margin: UiRect::all(Val::Px(10.0)),
+                    ..default()
+                },
+            ));
+            parent.spawn((
+                RoomListNode,
+                Node {
+                    width: Val::Px(600.0),
+                    min_height: Val::Px(200.0),
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(10.0)),
+                    ..default()
+                },
+                BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+            ));
+            parent
+                .spawn(Node {
+                    flex_direction: FlexDirection::Row,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                })
+                .with_children(|row| {
+                    for (button, label) in [
+                        (RoomButton::Create, "Create Room"),
+                        (RoomButton::Refresh, "Refresh"),
+                        (RoomButton::Back, "Back"),
+                    ] {
+                        row.spawn((
+                            Button,
+                            button_node.clone(),
+                            BackgroundColor(NORMAL_BUTTON),
+                            button,
+                        ))
+                        .with_child((Text::new(label), button_text_style.clone()));
+                    }
+                });
+        });
+}
+
+fn handle_room_list(mut commands: Commands, mut room_list: EventReader<RoomListEvent>) {
+    if let Some(event) = room_list.read().last() {
+        commands.insert_resource(RoomList(event.rooms.clone()));
+    }
+}
+
+fn update_room_list(
+    mut commands: Commands,
+    room_list: Res<RoomList>,
+    q_list: Query<Entity, With<RoomListNode>>,
+) {
+    for entity in q_list.iter() {
+        commands.entity(entity).despawn_descendants();
+        commands.entity(entity).with_children(|parent| {
+            for room in room_list.iter() {
+                let settings = &room.settings;
+                let full = room.players >= settings.max_players;
+
+                parent
+                    .spawn(Node {
+                        width: Val::Percent(100.0),
+                        flex_direction: FlexDirection::Row,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    })
+                    .with_children(|row| {
+                        for (text, width) in [
+                            (settings.name.clone(), 30.0),
+                            (format!("{} on {}", settings.mode, settings.map), 40.0),
+                            (format!("{}/{}", room.players, settings.max_players), 10.0),
+                        ] {
+                            row.spawn((
+                                Text::new(text),
+                                TextColor(TEXT_COLOR),
+                                Node {
+                                    width: Val::Percent(width),
+                                    ..default()
+                                },
+                            ));
+                        }
+
+                        let label = match full {
+                            true => "Full",
+                            false => "Join",
+                        };
+                        let mut button = row.spawn((
+                            Button,
+                            Node {
+                                width: Val::Percent(20.0),
+                                height: Val::Px(40.0),
+                                margin: UiRect::all(Val::Px(5.0)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            BackgroundColor(NORMAL_BUTTON),
+                        ));
+                        button.with_child((Text::new(label), TextColor(TEXT_COLOR)));
+                        if !full {
+                            button.insert(RoomButton::Join(room.id));
+                        }
+                    });
+            }
+        });
+    }
+}
+
+fn handle_room_buttons(
+    q_button: Query<(&Interaction, &RoomButton), Changed<Interaction>>,
+    room_list: Res<RoomList>,
+    mut client_info: ResMut<ClientInfo>,
+    mut list: EventWriter<ListRoomsEvent>,
+    mut create: EventWriter<CreateRoomEvent>,
+    mut q_status: Query<&mut Text, With<RoomStatusText>>,
+    mut next_state: ResMut<NextState<GameStates>>,
+) {
+    for (interaction, button) in q_button.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        match button {
+            RoomButton::Join(room) => {
+                client_info.room = Some(*room);
+                next_state.set(GameStates::Lobby);
+            }
+            // The new room plays like the room that is opened with the server
+            RoomButton::Create => {
+                let Some(template) = room_list
+                    .iter()
+                    .find(|room| room.id == RoomId::DEFAULT)
+                    .or(room_list.first())
+                else {
+                    continue;
+                };
+
+                create.send(CreateRoomEvent {
+                    settings: RoomSettings {
+                        name: client_info.name.clone(),
+                        ..template.settings.clone()
+                    },
+                });
+                for mut text in q_status.iter_mut() {
+                    text.0 = "Creating the room...".to_string();
+                }
+            }
+            RoomButton::Refresh => {
+                list.send(ListRoomsEvent);
+            }
+            RoomButton::Back => next_state.set(GameStates::MainMenu),
+        }
+    }
+}
+
+fn handle_room_created(
+    mut created: EventReader<RoomCreatedEvent>,
+    mut client_info: ResMut<ClientInfo>,
+    mut next_state: ResMut<NextState<GameStates>>,
+) {
+    if let Some(event) = created.read().last() {
+        info!("Created the room {}", event.room.settings.name);
+        client_info.room = Some(event.room.id);
+        next_state.set(GameStates::Lobby);
+    }
+}
+
+fn handle_room_rejected(
+    mut rejected: EventReader<RoomRejectedEvent>,
+    mut q_status: Query<&mut Text, With<RoomStatusText>>,
+) {
+    if let Some(event) = rejected.read().last() {
+        warn!("The server rejected the room: {}", event.reason);
+        for mut text in q_status.iter_mut() {
+            text.0 = event.reason.clone();
+        }
+    }
+}
+
+fn handle_room_back(
+    keys: Res<ButtonInput<KeyCode>>,
+    q_gamepad: Query<&Gamepad>,
+    mut next_state: ResMut<NextState<GameStates>>,
+) {
+    let back = keys.just_pressed(KeyCode::Escape)
+        || q_gamepad
+            .iter()
+            .any(|gamepad| gamepad.just_pressed(GamepadButton::East));
+
+    if back {
+        next_state.set(GameStates::MainMenu);
+    }
+}
+
+fn handle_rooms_disconnected(
+    mut commands: Commands,
+    mut next_state: ResMut<NextState<GameStates>>,
+) {
+    commands.insert_resource(ConnectionError(
+        "Lost the connection to the server".to_string(),
+    ));
+    next_state.set(GameStates::MainMenu);
+}
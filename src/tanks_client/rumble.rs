@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use bevy::{
+    input::gamepad::{GamepadRumbleIntensity, GamepadRumbleRequest},
+    prelude::*,
+};
+
+use crate::prelude::*;
+
+pub mod prelude {
+    pub use super::GamepadRumblePlugin;
+}
+
+/// This plugin rumbles the connected gamepads when the local player fires or gets hit
+#[derive(Debug, Clone)]
+pub struct GamepadRumblePlugin;
+
+impl Plugin for GamepadRumblePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                rumble_on_cannon_fired,
+                rumble_on_player_hit,
+                rumble_on_player_died,
+            )
+                .run_if(in_state(GameStates::Playing))
+                .run_if(resource_exists::<LocalPlayer>),
+        );
+    }
+}
+
+fn rumble(
+    rumble_requests: &mut EventWriter<GamepadRumbleRequest>,
+    q_gamepad: &Query<Entity, With<Gamepad>>,
+    intensity: GamepadRumbleIntensity,
+    duration: Duration,
+) {
+    for gamepad in q_gamepad.iter() {
+        rumble_requests.send(GamepadRumbleRequest::Add {
+            gamepad,
+            intensity,
+            duration,
+        });
+    }
+}
+
+fn rumble_on_cannon_fired(
+    mut fired: EventReader<CannonFiredEvent>,
+    mut rumble_requests: EventWriter<GamepadRumbleRequest>,
+    q_gamepad: Query<Entity, With<Gamepad>>,
+    local_player: Res<LocalPlayer>,
+) {
+    for event in fired.read() {
+        if event.owner == Some(**local_player) {
+            rumble(
+                &mut rumble_requests,
+                &q_gamepad,
+                GamepadRumbleIntensity::weak_motor(0.5),
+                Duration::from_millis(150),
+            );
+        }
+    }
+}
+
+fn rumble_on_player_hit(
+    mut hits: EventReader<PlayerHitEvent>,
+    mut rumble_requests: EventWriter<GamepadRumbleRequest>,
+    q_gamepad: Query<Entity, With<Gamepad>>,
+    local_player: Res<LocalPlayer>,
+) {
+    for event in hits.read() {
+        if event.client_id == **local_player {
+            rumble(
+                &mut rumble_requests,
+                &q_gamepad,
+                GamepadRumbleIntensity::strong_motor(0.6),
+                Duration::from_millis(300),
+            );
+        }
+    }
+}
+
+fn rumble_on_player_died(
+    mut died: EventReader<PlayerDiedEvent>,
+    mut rumble_requests: EventWriter<GamepadRumbleRequest>,
+    q_gamepad: Query<Entity, With<Gamepad>>,
+    local_player: Res<LocalPlayer>,
+) {
+    for event in died.read() {
+        if event.client_id == **local_player {
+            rumble(
+                &mut rumble_requests,
+                &q_gamepad,
+                GamepadRumbleIntensity::MAX,
+                Duration::from_millis(600),
+            );
+        }
+    }
+}
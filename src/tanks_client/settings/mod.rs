@@ -0,0 +1,282 @@
+//! Client settings that are persisted between launches.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+
+#[cfg(not(target_family = "wasm"))]
+mod storage_native;
+
+#[cfg(not(target_family = "wasm"))]
+use storage_native::{load_settings, save_settings};
+
+#[cfg(target_family = "wasm")]
+mod storage_wasm;
+
+#[cfg(target_family = "wasm")]
+use storage_wasm::{load_settings, save_settings};
+
+pub mod prelude {
+    pub use super::{
+        DisplayQuality, EffectsVolume, EngineVolume, RenderScale, SettingsPlugin, SettingsSet,
+        TankColor, Volume,
+    };
+}
+
+/// The overall rendering quality of the client
+#[derive(
+    Resource, Debug, Component, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize,
+)]
+pub enum DisplayQuality {
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+impl DisplayQuality {
+    /// Whether the directional light should cast shadows
+    pub fn shadows_enabled(&self) -> bool {
+        !matches!(self, DisplayQuality::Low)
+    }
+
+    /// The size of the directional light shadow map
+    pub fn shadow_map_size(&self) -> usize {
+        match self {
+            DisplayQuality::Low => 512,
+            DisplayQuality::Medium => 1024,
+            DisplayQuality::High => 2048,
+        }
+    }
+
+    /// The multisample anti-aliasing used by the game camera
+    pub fn msaa(&self) -> Msaa {
+        match self {
+            DisplayQuality::Low => Msaa::Off,
+            DisplayQuality::Medium => Msaa::Sample2,
+            DisplayQuality::High => Msaa::Sample4,
+        }
+    }
+
+    /// The fraction of the particles that are spawned by the particle effects
+    pub fn particle_scale(&self) -> f32 {
+        match self {
+            DisplayQuality::Low => 0.25,
+            DisplayQuality::Medium => 0.5,
+            DisplayQuality::High => 1.0,
+        }
+    }
+}
+
+/// The master volume of the client, from 0 (muted) to 9 (full volume)
+#[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct Volume(pub u32);
+
+impl Default for Volume {
+    fn default() -> Self {
+        Self(7)
+    }
+}
+
+impl Volume {
+    pub const MAX: u32 = 9;
+
+    /// The volume as an amplitude that can be fed to the audio channels
+    pub fn amplitude(&self) -> f64 {
+        self.0.min(Self::MAX) as f64 / Self::MAX as f64
+    }
+}
+
+/// The volume of the cannon, impact and death sounds, from 0 to `Volume::MAX`. It is scaled by
+/// the master volume.
+#[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct EffectsVolume(pub u32);
+
+impl Default for EffectsVolume {
+    fn default() -> Self {
+        Self(Volume::MAX)
+    }
+}
+
+impl EffectsVolume {
+    /// The amplitude of the effects channel, with the master volume applied
+    pub fn amplitude(&self, master: Volume) -> f64 {
+        Volume(self.0).amplitude() * master.amplitude()
+    }
+}
+
+/// The volume of the tank engines, from 0 to `Volume::MAX`. It is scaled by the master volume.
+#[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct EngineVolume(pub u32);
+
+impl Default for EngineVolume {
+    fn default() -> Self {
+        Self(Volume::MAX)
+    }
+}
+
+impl EngineVolume {
+    /// The amplitude of the engine channels, with the master volume applied
+    pub fn amplitude(&self, master: Volume) -> f64 {
+        Volume(self.0).amplitude() * master.amplitude()
+    }
+}
+
+/// The resolution the game is rendered at, in percent of the window resolution
+#[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct RenderScale(pub u32);
+
+impl Default for RenderScale {
+    fn default() -> Self {
+        Self(100)
+    }
+}
+
+impl RenderScale {
+    /// The scales offered in the display settings
+    pub const OPTIONS: [u32; 4] = [50, 67, 85, 100];
+
+    /// The scale as a factor of the window resolution
+    pub fn factor(&self) -> f32 {
+        self.0.clamp(Self::OPTIONS[0], 100) as f32 / 100.0
+    }
+
+    /// Whether the game is rendered at the window resolution
+    pub fn is_native(&self) -> bool {
+        self.0 >= 100
+    }
+}
+
+/// The color of the tank of the player, picked from the palette in the customization menu
+#[derive(Resource, Debug, Component, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct TankColor(pub Color);
+
+impl Default for TankColor {
+    fn default() -> Self {
+        Self(Self::PALETTE[3])
+    }
+}
+
+impl TankColor {
+    /// The colors offered in the customization menu and the lobby
+    pub const PALETTE: [Color; 8] = [
+        Color::srgb(0.9, 0.2, 0.2),
+        Color::srgb(0.95, 0.55, 0.15),
+        Color::srgb(0.95, 0.85, 0.2),
+        Color::srgb(0.2, 0.4, 0.9),
+        Color::srgb(0.3, 0.8, 0.3),
+        Color::srgb(0.6, 0.3, 0.85),
+        Color::srgb(0.2, 0.8, 0.8),
+        Color::srgb(0.9, 0.9, 0.9),
+    ];
+}
+
+/// The settings as they are stored on disk (native) or in the local storage (wasm)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    client_info: ClientInfo,
+    volume: Volume,
+    effects_volume: EffectsVolume,
+    engine_volume: EngineVolume,
+    display_quality: DisplayQuality,
+    render_scale: RenderScale,
+    keybindings: Keybindings,
+    tank_color: TankColor,
+    tank_skin: TankSkin,
+}
+
+impl Settings {
+    /// Replaces the values the menus do not offer, like the ones of an edited or older file
+    fn sanitized(mut self) -> Self {
+        self.volume.0 = self.volume.0.min(Volume::MAX);
+        self.effects_volume.0 = self.effects_volume.0.min(Volume::MAX);
+        self.engine_volume.0 = self.engine_volume.0.min(Volume::MAX);
+        if !RenderScale::OPTIONS.contains(&self.render_scale.0) {
+            self.render_scale = RenderScale::default();
+        }
+        self.keybindings = self.keybindings.sanitized();
+        if self.tank_skin.0 >= TankSkin::COUNT {
+            self.tank_skin = TankSkin::default();
+        }
+        // A color that is not finite is never in the palette either
+        if !TankColor::PALETTE.contains(&self.tank_color.0) {
+            self.tank_color = TankColor::default();
+        }
+
+        self
+    }
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SettingsSet;
+
+/// This plugin loads the settings on startup and saves them whenever they change
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        let settings = match load_settings() {
+            Ok(Some(settings)) => settings.sanitized(),
+            Ok(None) => Settings::default(),
+            Err(error) => {
+                warn!("Failed to load settings: {}", error);
+                Settings::default()
+            }
+        };
+
+        app.insert_resource(settings.client_info)
+            .insert_resource(settings.volume)
+            .insert_resource(settings.effects_volume)
+            .insert_resource(settings.engine_volume)
+            .insert_resource(settings.display_quality)
+            .insert_resource(settings.render_scale)
+            .insert_resource(settings.keybindings)
+            .insert_resource(settings.tank_color)
+            .insert_resource(settings.tank_skin);
+
+        app.add_systems(
+            Last,
+            persist_settings.in_set(SettingsSet).run_if(
+                resource_changed::<ClientInfo>
+                    .or(resource_changed::<Volume>)
+                    .or(resource_changed::<EffectsVolume>)
+                    .or(resource_changed::<EngineVolume>)
+                    .or(resource_changed::<DisplayQuality>)
+                    .or(resource_changed::<RenderScale>)
+                    .or(resource_changed::<Keybindings>)
+                    .or(resource_changed::<TankColor>)
+                    .or(resource_changed::<TankSkin>),
+            ),
+        );
+    }
+}
+
+fn persist_settings(
+    client_info: Res<ClientInfo>,
+    volume: Res<Volume>,
+    effects_volume: Res<EffectsVolume>,
+    engine_volume: Res<EngineVolume>,
+    display_quality: Res<DisplayQuality>,
+    render_scale: Res<RenderScale>,
+    keybindings: Res<Keybindings>,
+    tank_color: Res<TankColor>,
+    tank_skin: Res<TankSkin>,
+) {
+    let settings = Settings {
+        client_info: client_info.clone(),
+        volume: *volume,
+        effects_volume: *effects_volume,
+        engine_volume: *engine_volume,
+        display_quality: *display_quality,
+        render_scale: *render_scale,
+        keybindings: keybindings.clone(),
+        tank_color: *tank_color,
+        tank_skin: *tank_skin,
+    };
+
+    if let Err(error) = save_settings(&settings) {
+        warn!("Failed to save settings: {}", error);
+    }
+}
@@ -0,0 +1,41 @@
+use std::{fs, path::PathBuf};
+
+use super::Settings;
+
+const SETTINGS_FILE: &str = "settings.json";
+
+fn settings_dir() -> PathBuf {
+    if let Ok(dir) = std::env::var("XDG_CONFIG_HOME") {
+        return PathBuf::from(dir).join("tanks");
+    }
+
+    if let Ok(dir) = std::env::var("APPDATA") {
+        return PathBuf::from(dir).join("tanks");
+    }
+
+    if let Ok(home) = std::env::var("HOME") {
+        return PathBuf::from(home).join(".config").join("tanks");
+    }
+
+    PathBuf::from(".")
+}
+
+pub fn load_settings() -> Result<Option<Settings>, String> {
+    let path = settings_dir().join(SETTINGS_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let contents = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    let settings = serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+
+    Ok(Some(settings))
+}
+
+pub fn save_settings(settings: &Settings) -> Result<(), String> {
+    let dir = settings_dir();
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+
+    let contents = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    fs::write(dir.join(SETTINGS_FILE), contents).map_err(|e| e.to_string())
+}
@@ -0,0 +1,36 @@
+use web_sys::Storage;
+
+use super::Settings;
+
+const SETTINGS_KEY: &str = "tanks.settings";
+
+fn local_storage() -> Result<Storage, String> {
+    web_sys::window()
+        .ok_or("no window available")?
+        .local_storage()
+        .map_err(|e| format!("{:?}", e))?
+        .ok_or_else(|| "local storage is not available".to_string())
+}
+
+pub fn load_settings() -> Result<Option<Settings>, String> {
+    let storage = local_storage()?;
+
+    match storage
+        .get_item(SETTINGS_KEY)
+        .map_err(|e| format!("{:?}", e))?
+    {
+        Some(contents) => serde_json::from_str(&contents)
+            .map(Some)
+            .map_err(|e| e.to_string()),
+        None => Ok(None),
+    }
+}
+
+pub fn save_settings(settings: &Settings) -> Result<(), String> {
+    let storage = local_storage()?;
+
+    let contents = serde_json::to_string(settings).map_err(|e| e.to_string())?;
+    storage
+        .set_item(SETTINGS_KEY, &contents)
+        .map_err(|e| format!("{:?}", e))
+}
@@ -0,0 +1,278 @@
+//! On-screen touch controls for mobile browsers
+
+use bevy::{input::touch::Touch, prelude::*, window::PrimaryWindow};
+use leafwing_input_manager::prelude::*;
+
+use crate::prelude::*;
+use utils::prelude::*;
+
+pub mod prelude {
+    pub use super::{TouchControlsEnabled, TouchControlsPlugin, TouchControlsSet};
+}
+
+/// The radius of the virtual joystick (in logical pixels)
+const JOYSTICK_RADIUS: f32 = 80.0;
+/// The radius of the knob of the virtual joystick (in logical pixels)
+const KNOB_RADIUS: f32 = 30.0;
+/// The radius of the fire button (in logical pixels)
+const FIRE_BUTTON_RADIUS: f32 = 50.0;
+/// The distance of the controls from the edges of the screen (in logical pixels)
+const CONTROLS_MARGIN: f32 = 40.0;
+/// How much the camera zooms for each pixel of pinch
+const PINCH_ZOOM_SENSITIVITY: f32 = 0.05;
+
+const CONTROL_COLOR: Color = Color::srgba(0.9, 0.9, 0.9, 0.25);
+const CONTROL_PRESSED_COLOR: Color = Color::srgba(0.9, 0.9, 0.9, 0.5);
+
+/// Inserted once touch input is detected, enables the on-screen controls
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct TouchControlsEnabled;
+
+/// The touches that are currently used by the on-screen controls
+#[derive(Resource, Debug, Clone, Default)]
+struct TouchControlsState {
+    joystick: Option<u64>,
+    fire: Option<u64>,
+}
+
+#[derive(Component, Clone, Copy, Debug)]
+struct TouchControlsRoot;
+
+#[derive(Component, Clone, Copy, Debug)]
+struct TouchJoystick;
+
+#[derive(Component, Clone, Copy, Debug)]
+struct TouchJoystickKnob;
+
+#[derive(Component, Clone, Copy, Debug)]
+struct TouchFireButton;
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TouchControlsSet;
+
+/// This plugin adds a virtual joystick, a fire button, drag-to-orbit and pinch-to-zoom. The
+/// controls are shown automatically once touch input is detected.
+#[derive(Debug, Clone)]
+pub struct TouchControlsPlugin;
+
+impl Plugin for TouchControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TouchControlsState>();
+
+        app.configure_sets(
+            Update,
+            TouchControlsSet
+                .before(TankInputSet)
+                .before(OrbiterTransformSet),
+        );
+
+        app.add_systems(
+            Update,
+            detect_touch_input.run_if(not(resource_exists::<TouchControlsEnabled>)),
+        );
+        app.add_systems(
+            Update,
+            (
+                spawn_touch_controls,
+                update_touch_controls,
+                update_touch_camera,
+            )
+                .chain()
+                .in_set(TouchControlsSet)
+                .run_if(in_state(GameStates::Playing))
+                .run_if(resource_exists::<TouchControlsEnabled>),
+        );
+        app.add_systems(OnExit(GameStates::Playing), reset_touch_controls);
+    }
+}
+
+fn detect_touch_input(mut commands: Commands, touches: Res<Touches>) {
+    if touches.any_just_pressed() {
+        info!("Touch input detected, enabling the touch controls");
+        commands.insert_resource(TouchControlsEnabled);
+    }
+}
+
+fn spawn_touch_controls(mut commands: Commands, q_root: Query<(), With<TouchControlsRoot>>) {
+    if !q_root.is_empty() {
+        return;
+    }
+
+    commands
+        .spawn((
+            Name::new("TouchControls"),
+            TouchControlsRoot,
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                position_type: PositionType::Absolute,
+                ..default()
+            },
+            StateScoped(GameStates::Playing),
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    Name::new("TouchJoystick"),
+                    TouchJoystick,
+                    Node {
+                        width: Val::Px(JOYSTICK_RADIUS * 2.0),
+                        height: Val::Px(JOYSTICK_RADIUS * 2.0),
+                        position_type: PositionType::Absolute,
+                        left: Val::Px(CONTROLS_MARGIN),
+                        bottom: Val::Px(CONTROLS_MARGIN),
+                        ..default()
+                    },
+                    BorderRadius::MAX,
+                    BackgroundColor(CONTROL_COLOR),
+                ))
+                .with_child((
+                    Name::new("TouchJoystickKnob"),
+                    TouchJoystickKnob,
+                    Node {
+                        width: Val::Px(KNOB_RADIUS * 2.0),
+                        height: Val::Px(KNOB_RADIUS * 2.0),
+                        position_type: PositionType::Absolute,
+                        left: Val::Px(JOYSTICK_RADIUS - KNOB_RADIUS),
+                        top: Val::Px(JOYSTICK_RADIUS - KNOB_RADIUS),
+                        ..default()
+                    },
+                    BorderRadius::MAX,
+                    BackgroundColor(CONTROL_PRESSED_COLOR),
+                ));
+
+            parent.spawn((
+                Name::new("TouchFireButton"),
+                TouchFireButton,
+                Node {
+                    width: Val::Px(FIRE_BUTTON_RADIUS * 2.0),
+                    height: Val::Px(FIRE_BUTTON_RADIUS * 2.0),
+                    position_type: PositionType::Absolute,
+                    right: Val::Px(CONTROLS_MARGIN),
+                    bottom: Val::Px(CONTROLS_MARGIN),
+                    ..default()
+                },
+                BorderRadius::MAX,
+                BackgroundColor(CONTROL_COLOR),
+            ));
+        });
+}
+
+fn joystick_center(window: &Window) -> Vec2 {
+    Vec2::new(
+        CONTROLS_MARGIN + JOYSTICK_RADIUS,
+        window.height() - CONTROLS_MARGIN - JOYSTICK_RADIUS,
+    )
+}
+
+fn fire_button_center(window: &Window) -> Vec2 {
+    Vec2::new(
+        window.width() - CONTROLS_MARGIN - FIRE_BUTTON_RADIUS,
+        window.height() - CONTROLS_MARGIN - FIRE_BUTTON_RADIUS,
+    )
+}
+
+fn update_touch_controls(
+    touches: Res<Touches>,
+    mut state: ResMut<TouchControlsState>,
+    q_window: Query<&Window, With<PrimaryWindow>>,
+    mut q_action: Query<&mut ActionState<PlayerInputAction>>,
+    mut q_knob: Query<&mut Node, With<TouchJoystickKnob>>,
+    mut q_fire: Query<&mut BackgroundColor, With<TouchFireButton>>,
+) {
+    let Ok(window) = q_window.get_single() else {
+        return;
+    };
+    let joystick_center = joystick_center(window);
+    let fire_button_center = fire_button_center(window);
+
+    for touch in touches.iter_just_pressed() {
+        let position = touch.position();
+        if state.joystick.is_none() && position.distance(joystick_center) < JOYSTICK_RADIUS * 1.5 {
+            state.joystick = Some(touch.id());
+        } else if state.fire.is_none()
+            && position.distance(fire_button_center) < FIRE_BUTTON_RADIUS * 1.5
+        {
+            state.fire = Some(touch.id());
+
+            for mut action in q_action.iter_mut() {
+                action.press(&PlayerInputAction::Fire);
+            }
+        }
+    }
+
+    for touch in touches
+        .iter_just_released()
+        .chain(touches.iter_just_canceled())
+    {
+        if state.joystick == Some(touch.id()) {
+            state.joystick = None;
+        }
+        if state.fire == Some(touch.id()) {
+            state.fire = None;
+        }
+    }
+
+    // Dragging the stick up drives forward, the screen y axis points down
+    let offset = state
+        .joystick
+        .and_then(|id| touches.get_pressed(id))
+        .map(|touch| (touch.position() - joystick_center).clamp_length_max(JOYSTICK_RADIUS))
+        .unwrap_or(Vec2::ZERO);
+
+    if state.joystick.is_some() {
+        let movement = Vec2::new(offset.x, -offset.y) / JOYSTICK_RADIUS;
+        for mut action in q_action.iter_mut() {
+            action.set_axis_pair(&PlayerInputAction::Move, movement);
+        }
+    }
+
+    for mut node in q_knob.iter_mut() {
+        node.left = Val::Px(JOYSTICK_RADIUS - KNOB_RADIUS + offset.x);
+        node.top = Val::Px(JOYSTICK_RADIUS - KNOB_RADIUS + offset.y);
+    }
+
+    for mut background_color in q_fire.iter_mut() {
+        background_color.0 = match state.fire {
+            Some(_) => CONTROL_PRESSED_COLOR,
+            None => CONTROL_COLOR,
+        };
+    }
+}
+
+fn update_touch_camera(
+    touches: Res<Touches>,
+    state: Res<TouchControlsState>,
+    mut q_action: Query<&mut ActionState<OrbiterTransformAction>>,
+) {
+    // All the touches that are not used by the on-screen controls move the camera
+    let camera_touches = touches
+        .iter()
+        .filter(|touch| Some(touch.id()) != state.joystick && Some(touch.id()) != state.fire)
+        .collect::<Vec<&Touch>>();
+
+    let (pan, zoom) = match camera_touches.as_slice() {
+        [touch] => (touch.delta(), 0.0),
+        [first, second, ..] => {
+            let distance = first.position().distance(second.position());
+            let previous_distance = first
+                .previous_position()
+                .distance(second.previous_position());
+
+            (
+                Vec2::ZERO,
+                (distance - previous_distance) * PINCH_ZOOM_SENSITIVITY,
+            )
+        }
+        [] => return,
+    };
+
+    for mut action in q_action.iter_mut() {
+        action.set_axis_pair(&OrbiterTransformAction::Pan, pan);
+        action.set_value(&OrbiterTransformAction::Zoom, zoom);
+    }
+}
+
+fn reset_touch_controls(mut state: ResMut<TouchControlsState>) {
+    *state = TouchControlsState::default();
+}
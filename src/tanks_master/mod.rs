@@ -0,0 +1,155 @@
+//! The master server keeps track of the running game servers for the server browser
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use bevy::{app::ScheduleRunnerPlugin, log::LogPlugin, prelude::*};
+use warp::Filter;
+
+use crate::prelude::*;
+
+pub mod prelude {
+    pub use super::MasterServerPlugin;
+}
+
+/// A registered game server and the time of its last heartbeat
+#[derive(Debug, Clone)]
+struct RegisteredServer {
+    listing: ServerListing,
+    last_seen: Instant,
+}
+
+#[derive(Resource, Debug, Clone, Default, Deref)]
+struct ServerRegistry(Arc<Mutex<HashMap<String, RegisteredServer>>>);
+
+#[derive(Resource, Debug, Deref, DerefMut)]
+struct TokioRuntime(tokio::runtime::Runtime);
+
+/// This plugin runs the HTTP API of the master server. The game servers register with
+/// `POST /register` and the clients list the servers with `GET /servers`.
+#[derive(Debug, Clone)]
+pub struct MasterServerPlugin {
+    pub port: u16,
+}
+
+impl Default for MasterServerPlugin {
+    fn default() -> Self {
+        Self {
+            port: DEFAULT_MASTER_PORT,
+        }
+    }
+}
+
+impl Plugin for MasterServerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs(1))),
+            LogPlugin::default(),
+        ));
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let registry = ServerRegistry::default();
+
+        let addr = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), self.port);
+        let routes = master_routes(registry.clone());
+        runtime.spawn(async move {
+            info!("Master server listening on {}", addr);
+            warp::serve(routes).run(addr).await;
+        });
+
+        app.insert_resource(TokioRuntime(runtime));
+        app.insert_resource(registry);
+
+        app.add_systems(Update, prune_expired_servers);
+    }
+}
+
+/// The address of a listed server, an IPv6 host is written in brackets like `[::1]:5000`
+fn listing_address(host: &str, http_port: u16) -> String {
+    match host.parse::<IpAddr>() {
+        Ok(ip) => SocketAddr::new(ip.to_canonical(), http_port).to_string(),
+        Err(_) => format!("{}:{}", host, http_port),
+    }
+}
+
+fn master_routes(
+    registry: ServerRegistry,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let cors = warp::cors()
+        .allow_any_origin()
+        .allow_methods(vec!["GET", "POST"])
+        .allow_header("content-type");
+
+    let register = {
+        let registry = registry.clone();
+        warp::path("register")
+            .and(warp::post())
+            .and(warp::addr::remote())
+            .and(warp::body::json())
+            .map(
+                move |remote: Option<SocketAddr>, announcement: ServerAnnouncement| {
+                    let Some(host) = announcement
+                        .address
+                        .clone()
+                        .or_else(|| remote.map(|remote| remote.ip().to_string()))
+                    else {
+                        return warp::reply::with_status(
+                            "Unknown address",
+                            warp::http::StatusCode::BAD_REQUEST,
+                        );
+                    };
+
+                    let address = listing_address(&host, announcement.http_port);
+                    let listing = ServerListing {
+                        address: address.clone(),
+                        name: announcement.name,
+                        map: announcement.map,
+                        mode: announcement.mode,
+                        players: announcement.players,
+                        max_players: announcement.max_players,
+                    };
+
+                    let mut servers = registry.lock().unwrap();
+                    if !servers.contains_key(&address) {
+                        info!("Registered server {} at {}", listing.name, address);
+                    }
+                    servers.insert(
+                        address,
+                        RegisteredServer {
+                            listing,
+                            last_seen: Instant::now(),
+                        },
+                    );
+
+                    warp::reply::with_status("OK", warp::http::StatusCode::OK)
+                },
+            )
+    };
+
+    let servers = warp::path("servers").and(warp::get()).map(move || {
+        let servers = registry.lock().unwrap();
+        let listings = servers
+            .values()
+            .map(|server| server.listing.clone())
+            .collect::<Vec<_>>();
+        warp::reply::json(&listings)
+    });
+
+    register.or(servers).with(cors)
+}
+
+fn prune_expired_servers(registry: Res<ServerRegistry>) {
+    let expiry = Duration::from_secs(LISTING_EXPIRY_SECS);
+
+    registry.lock().unwrap().retain(|address, server| {
+        let alive = server.last_seen.elapsed() < expiry;
+        if !alive {
+            info!("Server {} at {} expired", server.listing.name, address);
+        }
+        alive
+    });
+}
@@ -0,0 +1,389 @@
+//! Administration of a running server from the stdin console and the HTTP admin API
+
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, BufRead},
+    net::IpAddr,
+    str::FromStr,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+use bevy_replicon_renet2::{netcode::NetcodeServerTransport, renet2::RenetServer};
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+use warp::{
+    http::StatusCode,
+    reply::{with_status, WithStatus},
+};
+
+use crate::prelude::*;
+use crate::tanks_server::{server::PlayerInfoMap, status::ServerStatusReceiver};
+
+pub mod prelude {
+    pub use super::{AdminCommand, AdminPlugin, AdminSet, BanList};
+}
+
+/// How long the HTTP admin API waits for the game loop to execute a command
+const ADMIN_REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The commands that can be sent from the console or the HTTP admin API
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdminCommand {
+    Kick(u64),
+    Ban(String),
+    Unban(String),
+    ChangeMap(String),
+    Say(String),
+    SetMode(String),
+    Restart,
+    Shutdown(String),
+    Status,
+}
+
+impl FromStr for AdminCommand {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let line = line.trim();
+        let (command, argument) = match line.split_once(char::is_whitespace) {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (line, ""),
+        };
+
+        let required = |usage: &str| match argument.is_empty() {
+            true => Err(format!("Usage: {}", usage)),
+            false => Ok(argument.to_string()),
+        };
+
+        match command {
+            "kick" => argument
+                .parse()
+                .map(AdminCommand::Kick)
+                .map_err(|_| "Usage: kick <id>".to_string()),
+            "ban" => required("ban <name|ip>").map(AdminCommand::Ban),
+            "unban" => required("unban <name|ip>").map(AdminCommand::Unban),
+            "changemap" => required("changemap <level>").map(AdminCommand::ChangeMap),
+            "say" => required("say <message>").map(AdminCommand::Say),
+            "setmode" => required("setmode <mode>").map(AdminCommand::SetMode),
+            "restart" => Ok(AdminCommand::Restart),
+            "shutdown" => Ok(AdminCommand::Shutdown(match argument.is_empty() {
+                true => "The server is shutting down".to_string(),
+                false => argument.to_string(),
+            })),
+            "status" => Ok(AdminCommand::Status),
+            "" => Err("Empty command".to_string()),
+            _ => Err(format!("Unknown command: {}", command)),
+        }
+    }
+}
+
+/// The result of an admin command, it is sent back to the HTTP client or logged
+type AdminReply = Result<String, String>;
+
+struct AdminRequest {
+    command: AdminCommand,
+    reply: Option<oneshot::Sender<AdminReply>>,
+}
+
+#[derive(Resource)]
+struct AdminRequestReceiver(Mutex<Receiver<AdminRequest>>);
+
+/// The HTTP admin API, it forwards the authenticated commands to the game loop
+#[derive(Resource, Clone)]
+pub(crate) struct AdminApi {
+    token: Option<String>,
+    requests: Sender<AdminRequest>,
+}
+
+impl AdminApi {
+    /// Handles a `POST /admin` request with the command in the body
+    pub(crate) async fn handle(
+        &self,
+        authorization: Option<String>,
+        body: &[u8],
+    ) -> WithStatus<String> {
+        let Some(token) = &self.token else {
+            return with_status(
+                "The admin API is disabled".to_string(),
+                StatusCode::NOT_FOUND,
+            );
+        };
+
+        let provided = authorization
+            .as_deref()
+            .and_then(|header| header.strip_prefix("Bearer "));
+        if provided != Some(token.as_str()) {
+            return with_status("Unauthorized".to_string(), StatusCode::UNAUTHORIZED);
+        }
+
+        let command = match String::from_utf8_lossy(body).parse::<AdminCommand>() {
+            Ok(command) => command,
+            Err(error) => return with_status(error, StatusCode::BAD_REQUEST),
+        };
+
+        let (reply, receiver) = oneshot::channel();
+        let request = AdminRequest {
+            command,
+            reply: Some(reply),
+        };
+        if self.requests.send(request).is_err() {
+            return with_status(
+                "The server is not running".to_string(),
+                StatusCode::SERVICE_UNAVAILABLE,
+            );
+        }
+
+        match tokio::time::timeout(ADMIN_REPLY_TIMEOUT, receiver).await {
+            Ok(Ok(Ok(reply))) => with_status(reply, StatusCode::OK),
+            Ok(Ok(Err(error))) => with_status(error, StatusCode::BAD_REQUEST),
+            _ => with_status(
+                "The server did not answer".to_string(),
+                StatusCode::GATEWAY_TIMEOUT,
+            ),
+        }
+    }
+}
+
+/// The BanList holds the banned player names and addresses, it is stored in
+/// `ServerConfig::bans_file`
+#[derive(Resource, Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BanList {
+    pub names: HashSet<String>,
+    pub ips: HashSet<IpAddr>,
+}
+
+impl BanList {
+    pub fn load(path: &str) -> Self {
+        let Ok(contents) = fs::read_to_string(path) else {
+            return Self::default();
+        };
+
+        serde_json::from_str(&contents).unwrap_or_else(|error| {
+            warn!("Failed to parse the bans in {}: {}", path, error);
+            Self::default()
+        })
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let contents = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| e.to_string())
+    }
+
+    pub fn is_name_banned(&self, name: &str) -> bool {
+        self.names.contains(&name.to_lowercase())
+    }
+
+    pub fn is_ip_banned(&self, ip: &IpAddr) -> bool {
+        self.ips.contains(ip)
+    }
+
+    /// Bans the given name or IP address
+    fn ban(&mut self, target: &str) {
+        match target.parse::<IpAddr>() {
+            Ok(ip) => self.ips.insert(ip),
+            Err(_) => self.names.insert(target.to_lowercase()),
+        };
+    }
+
+    /// Removes the ban of the given name or IP address, returns false if it was not banned
+    fn unban(&mut self, target: &str) -> bool {
+        match target.parse::<IpAddr>() {
+            Ok(ip) => self.ips.remove(&ip),
+            Err(_) => self.names.remove(&target.to_lowercase()),
+        }
+    }
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AdminSet;
+
+/// This plugin reads admin commands from stdin and from the HTTP admin API and executes them
+/// in the game loop.
+#[derive(Debug, Clone)]
+pub struct AdminPlugin;
+
+impl Plugin for AdminPlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = mpsc::channel();
+
+        let console = sender.clone();
+        std::thread::spawn(move || read_console(console));
+
+        let token = app
+            .world()
+            .get_resource::<ServerConfig>()
+            .and_then(|config| config.admin_token.clone());
+        app.insert_resource(AdminApi {
+            token,
+            requests: sender,
+        });
+        app.insert_resource(AdminRequestReceiver(Mutex::new(receiver)));
+        app.init_resource::<BanList>();
+
+        app.add_systems(Startup, load_bans);
+        app.add_systems(
+            Update,
+            handle_admin_requests
+                .in_set(AdminSet)
+                .run_if(resource_exists::<RenetServer>),
+        );
+    }
+}
+
+fn read_console(requests: Sender<AdminRequest>) {
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match line.parse::<AdminCommand>() {
+            Ok(command) => {
+                let request = AdminRequest {
+                    command,
+                    reply: None,
+                };
+                if requests.send(request).is_err() {
+                    return;
+                }
+            }
+            Err(error) => warn!("{}", error),
+        }
+    }
+}
+
+fn load_bans(mut commands: Commands, config: Res<ServerConfig>) {
+    let bans = BanList::load(&config.bans_file);
+    info!(
+        "Loaded {} banned names and {} banned addresses",
+        bans.names.len(),
+        bans.ips.len()
+    );
+    commands.insert_resource(bans);
+}
+
+fn handle_admin_requests(
+    receiver: Res<AdminRequestReceiver>,
+    mut server: ResMut<RenetServer>,
+    transport: Option<Res<NetcodeServerTransport>>,
+    player_info_map: Res<PlayerInfoMap>,
+    mut bans: ResMut<BanList>,
+    mut config: ResMut<ServerConfig>,
+    mut rooms: ResMut<Rooms>,
+    mut lobbies: ResMut<Lobbies>,
+    status: Res<ServerStatusReceiver>,
+    mut messages: EventWriter<ToClients<ServerMessageEvent>>,
+    mut change_map: EventWriter<ChangeMapEvent>,
+    mut shutdown: EventWriter<RequestShutdownEvent>,
+) {
+    let requests = receiver.0.lock().unwrap().try_iter().collect::<Vec<_>>();
+
+    for AdminRequest { command, reply } in requests {
+        info!("Admin command: {:?}", command);
+
+        let result = match command {
+            AdminCommand::Kick(client_id) => match server.is_connected(client_id) {
+                true => {
+                    server.disconnect(client_id);
+                    Ok(format!("Kicked {}", client_id))
+                }
+                false => Err(format!("No client with id {}", client_id)),
+            },
+            AdminCommand::Ban(target) => {
+                bans.ban(&target);
+
+                // Kick the connected clients that match the ban
+                let mut kicked = 0;
+                for client_id in server.clients_id() {
+                    let name_banned = player_info_map
+                        .get(&ClientId::new(client_id))
+                        .is_some_and(|info| bans.is_name_banned(&info.name));
+                    let ip_banned = transport
+                        .as_ref()
+                        .and_then(|transport| transport.client_addr(client_id))
+                        .is_some_and(|(_, addr)| bans.is_ip_banned(&addr.ip()));
+
+                    if name_banned || ip_banned {
+                        server.disconnect(client_id);
+                        kicked += 1;
+                    }
+                }
+
+                bans.save(&config.bans_file)
+                    .map(|_| format!("Banned {}, kicked {} clients", target, kicked))
+            }
+            AdminCommand::Unban(target) => match bans.unban(&target) {
+                true => bans
+                    .save(&config.bans_file)
+                    .map(|_| format!("Unbanned {}", target)),
+                false => Err(format!("{} is not banned", target)),
+            },
+            AdminCommand::ChangeMap(level) => validate_map(&level, &config.maps).map(|level| {
+                change_map.send(ChangeMapEvent {
+                    room: RoomId::DEFAULT,
+                    level: level.clone(),
+                    reset_scores: false,
+                });
+                format!("Changing the map to {}", level)
+            }),
+            AdminCommand::Say(message) => {
+                messages.send(ToClients {
+                    mode: SendMode::Broadcast,
+                    event: ServerMessageEvent {
+                        message: message.clone(),
+                    },
+                });
+                Ok(format!("Said {}", message))
+            }
+            AdminCommand::SetMode(mode) => validate_mode(&mode).map(|mode| {
+                config.mode = mode.clone();
+                if let Some(room) = rooms.get_mut(RoomId::DEFAULT) {
+                    room.settings.mode = mode.clone();
+                }
+                if let Some(lobby) = lobbies.get_mut(&RoomId::DEFAULT) {
+                    lobby.set_mode(&mode);
+                }
+                format!("Game mode set to {}", mode)
+            }),
+            // The match is restarted on the map the room plays, not the map of the config
+            AdminCommand::Restart => {
+                let level = rooms
+                    .get(RoomId::DEFAULT)
+                    .map(|room| room.settings.map.clone())
+                    .unwrap_or_else(|| config.level.clone());
+                change_map.send(ChangeMapEvent {
+                    room: RoomId::DEFAULT,
+                    level,
+                    reset_scores: true,
+                });
+                Ok("Restarting the match".to_string())
+            }
+            AdminCommand::Shutdown(reason) => {
+                shutdown.send(RequestShutdownEvent { reason });
+                Ok("Shutting down".to_string())
+            }
+            AdminCommand::Status => {
+                serde_json::to_string_pretty(&*status.borrow()).map_err(|e| e.to_string())
+            }
+        };
+
+        match &result {
+            Ok(message) => info!("{}", message),
+            Err(error) => warn!("{}", error),
+        }
+
+        if let Some(reply) = reply {
+            let _ = reply.send(result);
+        }
+    }
+}
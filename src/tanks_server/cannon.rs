@@ -10,7 +10,7 @@ use crate::prelude::*;
 use utils::prelude::*;
 
 pub mod prelude {
-    pub use super::{TankCannon, TankCannonInput, TankCannonPlugin, TankCannonSet};
+    pub use super::{DamagedBy, TankCannon, TankCannonInput, TankCannonPlugin, TankCannonSet};
 }
 
 #[derive(Component, Clone, Copy, Debug)]
@@ -51,6 +51,8 @@ impl Default for TankCannonState {
 #[derive(Component, Clone, Copy, Debug, Default)]
 pub struct TankCannonInput {
     pub fire: bool,
+    /// The tick of the world the player saw when it fired
+    pub seen_tick: ServerTick,
 }
 
 #[derive(Component, Clone, Copy, Debug)]
@@ -58,6 +60,10 @@ pub struct TankCannonInput {
 struct TankCannonShell {
     time_to_live: f32,
     damage: f32,
+    /// The player that fired the shell
+    owner: Option<ClientId>,
+    /// The team of the player that fired the shell, it does not hurt its teammates
+    team: Option<Team>,
 }
 
 impl Default for TankCannonShell {
@@ -65,10 +71,16 @@ impl Default for TankCannonShell {
         Self {
             time_to_live: 1.0,
             damage: 50.0,
+            owner: None,
+            team: None,
         }
     }
 }
 
+/// The DamagedBy component holds the player that last hit the entity with a shell
+#[derive(Component, Clone, Copy, Debug, Deref)]
+pub struct DamagedBy(pub ClientId);
+
 #[derive(Component, Clone, Debug)]
 struct TankCannonShellState {
     time_to_live: Timer,
@@ -82,7 +94,7 @@ pub struct TankCannonPlugin;
 impl Plugin for TankCannonPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(
-            Update,
+            FixedUpdate,
             (
                 initialize_cannon,
                 cannon_fire,
@@ -114,20 +126,29 @@ fn cannon_fire(
         &Transform,
         &TankCannon,
         &mut TankCannonState,
+        Option<&Player>,
+        Option<&Team>,
+        Option<&RoomId>,
     )>,
-    mut fired: EventWriter<ToClients<CannonFiredEvent>>,
+    tick: Res<ServerTick>,
+    config: Res<ServerConfig>,
+    mut fired: EventWriter<ToRoom<CannonFiredEvent>>,
 ) {
-    for (mut input, transform, cannon, mut state) in q_cannon.iter_mut() {
+    for (mut input, transform, cannon, mut state, player, team, room) in q_cannon.iter_mut() {
         if state.cooldown.tick(time.delta()).finished() {
             if !input.fire {
                 continue;
             }
 
-            let shell = TankCannonShell::default();
+            let shell = TankCannonShell {
+                owner: player.map(|player| player.client_id),
+                team: team.copied(),
+                ..default()
+            };
             let point = transform.translation + transform.rotation * cannon.offset;
             let rotation = transform.rotation * Quat::from_rotation_x(FRAC_PI_2);
 
-            commands.spawn((
+            let mut shell_entity = commands.spawn((
                 Replicated,
                 Name::new("TankCannonShell"),
                 Transform::from_translation(point).with_rotation(rotation),
@@ -145,14 +166,31 @@ fn cannon_fire(
                 },
                 ActiveEvents::COLLISION_EVENTS,
             ));
+            if let Some(room) = room {
+                shell_entity.insert(*room);
+            }
+
+            // The shells of a client hit the tanks where the client saw them
+            if let Some(owner) = shell.owner {
+                let rewind = rewind_ticks(*tick, input.seen_tick, &config);
+                if rewind > 0 {
+                    shell_entity.insert(LagCompensated {
+                        owner,
+                        rewind,
+                        previous: point,
+                    });
+                }
+            }
 
             state.cooldown = Timer::from_seconds(cannon.fire_rate_secs, TimerMode::Once);
 
-            fired.send(ToClients {
-                mode: SendMode::Broadcast,
+            fired.send(ToRoom {
+                room: room.copied().unwrap_or_default(),
                 event: CannonFiredEvent {
                     position: point,
                     rotation,
+                    owner: shell.owner,
+                    tick: *tick,
                 },
             });
         }
@@ -175,18 +213,55 @@ fn shell_update_time_to_live(
 
 fn shell_update_collision(
     mut commands: Commands,
-    q_shell: Query<(Entity, &Transform, &TankCannonShell, &CollisionWith)>,
-    mut impact: EventWriter<ToClients<ShellImpactEvent>>,
+    q_shell: Query<(
+        Entity,
+        &Transform,
+        &TankCannonShell,
+        &CollisionWith,
+        Option<&RoomId>,
+    )>,
+    q_player: Query<&Player>,
+    q_team: Query<&Team>,
+    tick: Res<ServerTick>,
+    mut impact: EventWriter<ToRoom<ShellImpactEvent>>,
+    mut hit: EventWriter<ToRoom<PlayerHitEvent>>,
 ) {
-    for (entity, transform, shell, collision_with) in q_shell.iter() {
+    for (entity, transform, shell, collision_with, room) in q_shell.iter() {
         commands.entity(entity).despawn_recursive();
-        commands.entity(collision_with.entity).insert(Damage {
-            amount: shell.damage,
-        });
 
-        impact.send(ToClients {
-            mode: SendMode::Broadcast,
-            event: ShellImpactEvent(transform.translation),
+        let friendly =
+            shell.team.is_some() && q_team.get(collision_with.entity).ok() == shell.team.as_ref();
+        if !friendly {
+            let mut target = commands.entity(collision_with.entity);
+            target.insert(Damage {
+                amount: shell.damage,
+            });
+            if let Some(owner) = shell.owner {
+                target.insert(DamagedBy(owner));
+            }
+        }
+
+        let room = room.copied().unwrap_or_default();
+        if let Some(player) = q_player
+            .get(collision_with.entity)
+            .ok()
+            .filter(|_| !friendly)
+        {
+            hit.send(ToRoom {
+                room,
+                event: PlayerHitEvent {
+                    client_id: player.client_id,
+                    position: transform.translation,
+                    tick: *tick,
+                },
+            });
+        }
+        impact.send(ToRoom {
+            room,
+            event: ShellImpactEvent {
+                position: transform.translation,
+                tick: *tick,
+            },
         });
     }
 }
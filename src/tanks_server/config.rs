@@ -0,0 +1,177 @@
+//! Server configuration
+
+use std::{env, str::FromStr};
+
+use bevy::prelude::*;
+
+pub mod prelude {
+    pub use super::ServerConfig;
+}
+
+/// The ServerConfig holds the settings of the game server. The values can be overridden with the
+/// `TANKS_*` environment variables.
+#[derive(Resource, Debug, Clone)]
+pub struct ServerConfig {
+    /// The name of the server shown in the server browser (`TANKS_SERVER_NAME`)
+    pub name: String,
+    /// The port of the HTTP server (`TANKS_HTTP_PORT`)
+    pub http_port: u16,
+    /// The port of the native UDP socket (`TANKS_NATIVE_PORT`)
+    pub native_port: u16,
+    /// The port of the WebTransport socket (`TANKS_WT_PORT`)
+    pub wt_port: u16,
+    /// The port of the WebSocket socket (`TANKS_WS_PORT`)
+    pub ws_port: u16,
+    /// The maximum number of connected clients (`TANKS_MAX_CLIENTS`)
+    pub max_clients: usize,
+    /// The address of the master server to register with (`TANKS_MASTER_ADDRESS`)
+    pub master_address: Option<String>,
+    /// The address advertised to the master server (`TANKS_PUBLIC_ADDRESS`)
+    pub public_address: Option<String>,
+    /// The level that is loaded by the server (`TANKS_LEVEL`)
+    pub level: String,
+    /// The maps the rooms rotate through at the end of each match, it defaults to the level
+    /// (`TANKS_MAPS`, comma separated). Every map has to fit inside `MAP_BOUNDS_MIN` and
+    /// `MAP_BOUNDS_MAX`, the replicated positions are clamped to them.
+    pub maps: Vec<String>,
+    /// The game mode of the server (`TANKS_GAME_MODE`)
+    pub mode: String,
+    /// The maximum number of rooms, including the default room (`TANKS_MAX_ROOMS`)
+    pub max_rooms: usize,
+    /// Whether the players wait in the lobby of their room until everyone is ready, without it
+    /// the rooms are always in a match (`TANKS_LOBBY`)
+    pub lobby: bool,
+    /// The default length of a match (in seconds), zero has no limit
+    /// (`TANKS_MATCH_TIME_LIMIT_SECS`)
+    pub match_time_limit_secs: u32,
+    /// The file where the bans are stored (`TANKS_BANS_FILE`)
+    pub bans_file: String,
+    /// The token required by the HTTP admin API, the API is disabled without it
+    /// (`TANKS_ADMIN_TOKEN`)
+    pub admin_token: Option<String>,
+    /// How long the score of a disconnected player is kept for a reconnect (in seconds)
+    /// (`TANKS_RECONNECT_GRACE_SECS`)
+    pub reconnect_grace_secs: f32,
+    /// The directory where the match replays are recorded, nothing is recorded without it
+    /// (`TANKS_REPLAY_DIR`)
+    pub replay_dir: Option<String>,
+    /// The violation score above which a client is kicked, the score goes down by one each
+    /// second (`TANKS_MAX_VIOLATIONS`)
+    pub max_violations: u32,
+    /// The distance from the tank of a client within which the entities are replicated to it,
+    /// zero replicates the whole room (`TANKS_RELEVANCE_RADIUS`)
+    pub relevance_radius: f32,
+    /// The longest rewind of the lag compensation (in milliseconds), zero disables it
+    /// (`TANKS_LAG_COMPENSATION_MAX_MS`)
+    pub lag_compensation_max_ms: u32,
+    /// The delay of the clients behind the latest state they received (in milliseconds), it is
+    /// added to the rewind to the tick the client saw (`TANKS_INTERPOLATION_DELAY_MS`)
+    pub interpolation_delay_ms: u32,
+    /// Sends the rewound hitboxes to the client that fired, for the debug overlay
+    /// (`TANKS_LAG_COMPENSATION_DEBUG`)
+    pub lag_compensation_debug: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            name: "Tanks Server".to_string(),
+            http_port: 5000,
+            native_port: 5001,
+            wt_port: 5002,
+            ws_port: 5003,
+            max_clients: 64,
+            master_address: None,
+            public_address: None,
+            level: "levels/World.glb".to_string(),
+            maps: vec!["levels/World.glb".to_string()],
+            mode: "deathmatch".to_string(),
+            max_rooms: 8,
+            lobby: true,
+            match_time_limit_secs: 600,
+            bans_file: "bans.json".to_string(),
+            admin_token: None,
+            reconnect_grace_secs: 60.0,
+            replay_dir: None,
+            max_violations: 20,
+            relevance_radius: 40.0,
+            lag_compensation_max_ms: 250,
+            interpolation_delay_ms: 17,
+            lag_compensation_debug: false,
+        }
+    }
+}
+
+fn env_var<T: FromStr>(key: &str) -> Option<T> {
+    let value = env::var(key).ok()?;
+    match value.parse() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            warn!("Ignoring invalid value for {}: {}", key, value);
+            None
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Creates the config from the defaults overridden by the environment variables
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let level = env_var("TANKS_LEVEL").unwrap_or(default.level);
+        let maps = env_var::<String>("TANKS_MAPS")
+            .map(|maps| {
+                maps.split(',')
+                    .map(str::trim)
+                    .filter(|map| !map.is_empty())
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            })
+            .filter(|maps| !maps.is_empty())
+            .unwrap_or_else(|| vec![level.clone()]);
+
+        Self {
+            name: env_var("TANKS_SERVER_NAME").unwrap_or(default.name),
+            http_port: env_var("TANKS_HTTP_PORT").unwrap_or(default.http_port),
+            native_port: env_var("TANKS_NATIVE_PORT").unwrap_or(default.native_port),
+            wt_port: env_var("TANKS_WT_PORT").unwrap_or(default.wt_port),
+            ws_port: env_var("TANKS_WS_PORT").unwrap_or(default.ws_port),
+            max_clients: env_var("TANKS_MAX_CLIENTS").unwrap_or(default.max_clients),
+            master_address: env_var("TANKS_MASTER_ADDRESS").or(default.master_address),
+            public_address: env_var("TANKS_PUBLIC_ADDRESS").or(default.public_address),
+            level,
+            maps,
+            mode: env_var("TANKS_GAME_MODE").unwrap_or(default.mode),
+            max_rooms: env_var("TANKS_MAX_ROOMS").unwrap_or(default.max_rooms),
+            lobby: env_var("TANKS_LOBBY").unwrap_or(default.lobby),
+            match_time_limit_secs: env_var("TANKS_MATCH_TIME_LIMIT_SECS")
+                .unwrap_or(default.match_time_limit_secs),
+            bans_file: env_var("TANKS_BANS_FILE").unwrap_or(default.bans_file),
+            admin_token: env_var("TANKS_ADMIN_TOKEN").or(default.admin_token),
+            reconnect_grace_secs: env_var("TANKS_RECONNECT_GRACE_SECS")
+                .unwrap_or(default.reconnect_grace_secs),
+            replay_dir: env_var("TANKS_REPLAY_DIR").or(default.replay_dir),
+            max_violations: env_var("TANKS_MAX_VIOLATIONS").unwrap_or(default.max_violations),
+            relevance_radius: env_var("TANKS_RELEVANCE_RADIUS").unwrap_or(default.relevance_radius),
+            lag_compensation_max_ms: env_var("TANKS_LAG_COMPENSATION_MAX_MS")
+                .unwrap_or(default.lag_compensation_max_ms),
+            interpolation_delay_ms: env_var("TANKS_INTERPOLATION_DELAY_MS")
+                .unwrap_or(default.interpolation_delay_ms),
+            lag_compensation_debug: env_var("TANKS_LAG_COMPENSATION_DEBUG")
+                .unwrap_or(default.lag_compensation_debug),
+        }
+    }
+
+    /// The map after `current` in the rotation, the rotation starts over after the last map and
+    /// starts from the first one when `current` is not in it
+    pub fn next_map(&self, current: &str) -> Option<&str> {
+        let index = self
+            .maps
+            .iter()
+            .position(|map| map == current)
+            .map_or(0, |index| index + 1);
+
+        self.maps
+            .get(index % self.maps.len().max(1))
+            .map(String::as_str)
+    }
+}
@@ -0,0 +1,126 @@
+//! Interest management, each client only gets the entities of its room near its tank
+
+use std::{collections::HashMap, mem::size_of};
+
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+
+use crate::prelude::*;
+
+pub mod prelude {
+    pub use super::{AlwaysRelevant, InterestPlugin, InterestSet};
+}
+
+/// How much farther than the relevance radius an entity must go before it is hidden again, so
+/// entities on the edge do not flicker
+const RELEVANCE_HYSTERESIS: f32 = 1.1;
+
+/// An estimate of the bytes of the entity that prefixes a transform update, the quantized
+/// transform itself is measured with `QuantizedTransform::encoded_len`
+const ENTITY_BYTES: usize = size_of::<u64>();
+
+/// Entities with AlwaysRelevant are replicated to every client regardless of the distance, like
+/// the player stats or the objectives of a game mode
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct AlwaysRelevant;
+
+/// The position each client is interested in, the position of its tank while it is alive
+#[derive(Resource, Debug, Default, Deref, DerefMut)]
+struct ClientFocus(HashMap<ClientId, Vec3>);
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InterestSet;
+
+/// This plugin hides the entities of the other rooms and the entities that are farther than
+/// `ServerConfig::relevance_radius` from the tank of a client. Clients without a tank, because
+/// they never spawned or their tank died, see their whole room and clients that did not join see only the entities without a `RoomId`. The
+/// client always sees its own tank and the entities of its room with `AlwaysRelevant`.
+#[derive(Debug, Clone)]
+pub struct InterestPlugin;
+
+impl Plugin for InterestPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ClientFocus>();
+
+        app.add_systems(
+            PostUpdate,
+            (update_client_focus, update_visibility)
+                .chain()
+                .in_set(InterestSet)
+                .before(ServerSet::Send)
+                .run_if(resource_exists::<ReplicatedClients>),
+        );
+    }
+}
+
+// The focus of a client whose tank died or who disconnected is dropped with the tank, so a dead
+// player falls back to the whole room instead of the spot where the tank died
+fn update_client_focus(q_player: Query<(&Player, &Transform)>, mut focus: ResMut<ClientFocus>) {
+    focus.clear();
+    for (player, transform) in q_player.iter() {
+        focus.insert(player.client_id, transform.translation);
+    }
+}
+
+fn update_visibility(
+    config: Res<ServerConfig>,
+    focus: Res<ClientFocus>,
+    rooms: Res<Rooms>,
+    mut clients: ResMut<ReplicatedClients>,
+    q_entity: Query<
+        (
+            Entity,
+            Option<Ref<Transform>>,
+            Option<&Player>,
+            Option<&RoomId>,
+            Has<AlwaysRelevant>,
+        ),
+        With<Replicated>,
+    >,
+    mut metrics: ResMut<ServerMetrics>,
+) {
+    let enter_radius = config.relevance_radius;
+    let leave_radius = config.relevance_radius * RELEVANCE_HYSTERESIS;
+    let mut hidden_entities = 0;
+    let mut saved_bytes = 0;
+
+    for client in clients.iter_mut() {
+        let client_id = client.id();
+        let client_room = rooms.room_of(client_id);
+        let center = focus
+            .get(&client_id)
+            .filter(|_| config.relevance_radius > 0.0);
+
+        let visibility = client.visibility_mut();
+        for (entity, transform, player, room, always_relevant) in q_entity.iter() {
+            let visible = visibility.is_visible(entity);
+            let in_room = room.is_none_or(|room| Some(*room) == client_room);
+            let own = player.is_some_and(|player| player.client_id == client_id);
+            let near = match (center, &transform) {
+                (Some(center), Some(transform)) if !always_relevant && !own => {
+                    let distance = center.distance(transform.translation);
+                    match visible {
+                        true => distance <= leave_radius,
+                        false => distance <= enter_radius,
+                    }
+                }
+                _ => true,
+            };
+
+            let relevant = in_room && near;
+            if relevant != visible {
+                visibility.set_visibility(entity, relevant);
+            }
+
+            if !relevant {
+                hidden_entities += 1;
+                if let Some(transform) = transform.as_ref().filter(|t| t.is_changed()) {
+                    let encoded_len = QuantizedTransform::from_transform(transform).encoded_len();
+                    saved_bytes += (ENTITY_BYTES + encoded_len) as u64;
+                }
+            }
+        }
+    }
+
+    metrics.record_interest(hidden_entities, saved_bytes);
+}
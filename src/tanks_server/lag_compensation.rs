@@ -0,0 +1,198 @@
+//! Lag compensation, the shells of a client hit the tanks where that client saw them
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use bevy_replicon::prelude::*;
+
+use crate::prelude::*;
+use utils::prelude::*;
+
+pub mod prelude {
+    pub use super::{
+        rewind_ticks, LagCompensated, LagCompensationPlugin, LagCompensationSet, PoseHistory,
+    };
+}
+
+/// How many ticks of poses of the tanks are kept, the rewind is never longer than this
+const POSE_HISTORY_TICKS: u32 = TICK_RATE as u32;
+
+/// The radius of the shell collider, the rewound hitboxes are inflated by it because the hits
+/// are checked with a ray
+const SHELL_RADIUS: f32 = 0.1;
+
+/// The PoseHistory holds the transforms of a tank in the recent ticks
+#[derive(Component, Debug, Clone, Default)]
+pub struct PoseHistory(VecDeque<(ServerTick, Transform)>);
+
+impl PoseHistory {
+    pub fn record(&mut self, tick: ServerTick, transform: Transform) {
+        self.0.push_back((tick, transform));
+        while self
+            .0
+            .front()
+            .is_some_and(|(recorded, _)| tick.since(*recorded) > POSE_HISTORY_TICKS)
+        {
+            self.0.pop_front();
+        }
+    }
+
+    /// The pose at the given tick, the ticks before the history give the oldest pose
+    pub fn pose_at(&self, tick: ServerTick) -> Option<Transform> {
+        let index = self.0.partition_point(|(recorded, _)| *recorded <= tick);
+        index
+            .checked_sub(1)
+            .and_then(|index| self.0.get(index))
+            .or(self.0.front())
+            .map(|(_, pose)| *pose)
+    }
+}
+
+/// The collider of a tank inflated by the shell radius
+#[derive(Component, Debug, Clone)]
+struct RewindHitbox(Collider);
+
+/// The number of ticks a shot is rewound by. The client saw the world at `seen`, plus the
+/// interpolation delay, and the rewind is capped by `ServerConfig::lag_compensation_max_ms`.
+pub fn rewind_ticks(now: ServerTick, seen: ServerTick, config: &ServerConfig) -> u32 {
+    let max_rewind =
+        ServerTick::ticks_in_millis(config.lag_compensation_max_ms).min(POSE_HISTORY_TICKS);
+    let interpolation_delay = ServerTick::ticks_in_millis(config.interpolation_delay_ms);
+
+    (now.since(seen) + interpolation_delay).min(max_rewind)
+}
+
+/// A LagCompensated shell hits the tanks at their pose `rewind` ticks ago instead of their
+/// current one, `RoomsPlugin` keeps it from colliding with the tanks themselves
+#[derive(Component, Debug, Clone, Copy)]
+pub struct LagCompensated {
+    pub owner: ClientId,
+    pub rewind: u32,
+    /// The position of the shell in the previous tick, the hits are checked along the segment
+    /// to the current position
+    pub previous: Vec3,
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LagCompensationSet;
+
+/// This plugin records the pose history of the tanks and checks the hits of the lag compensated
+/// shells against the rewound tanks. The cannon marks the shells fired by clients with
+/// `LagCompensated`.
+#[derive(Debug, Clone)]
+pub struct LagCompensationPlugin;
+
+impl Plugin for LagCompensationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            initialize_tanks
+                .in_set(LagCompensationSet)
+                .before(TankCannonSet),
+        );
+        app.add_systems(
+            FixedPostUpdate,
+            (
+                record_poses,
+                detect_rewound_hits,
+                send_rewound_hitboxes
+                    .run_if(|config: Res<ServerConfig>| config.lag_compensation_debug),
+            )
+                .chain()
+                .in_set(LagCompensationSet)
+                .after(PhysicsSet::Writeback),
+        );
+    }
+}
+
+fn initialize_tanks(mut commands: Commands, q_player: Query<(Entity, &Collider), Added<Player>>) {
+    for (entity, collider) in q_player.iter() {
+        let hitbox = match collider.as_cuboid() {
+            Some(cuboid) => {
+                let half_extents = cuboid.half_extents() + Vec3::splat(SHELL_RADIUS);
+                Collider::cuboid(half_extents.x, half_extents.y, half_extents.z)
+            }
+            None => collider.clone(),
+        };
+
+        commands
+            .entity(entity)
+            .insert((PoseHistory::default(), RewindHitbox(hitbox)));
+    }
+}
+
+fn record_poses(tick: Res<ServerTick>, mut q_tank: Query<(&Transform, &mut PoseHistory)>) {
+    for (transform, mut history) in q_tank.iter_mut() {
+        history.record(*tick, *transform);
+    }
+}
+
+fn detect_rewound_hits(
+    mut commands: Commands,
+    tick: Res<ServerTick>,
+    mut q_shell: Query<(Entity, &mut Transform, &mut LagCompensated, Option<&RoomId>)>,
+    q_tank: Query<(
+        Entity,
+        &Player,
+        &RewindHitbox,
+        &PoseHistory,
+        Option<&RoomId>,
+    )>,
+) {
+    for (entity, mut transform, mut compensated, room) in q_shell.iter_mut() {
+        let start = compensated.previous;
+        let path = transform.translation - start;
+        compensated.previous = transform.translation;
+
+        let rewound = ServerTick(tick.saturating_sub(compensated.rewind));
+        let hit = q_tank
+            .iter()
+            .filter(|(_, player, _, _, tank_room)| {
+                player.client_id != compensated.owner && *tank_room == room
+            })
+            .filter_map(|(tank, _, RewindHitbox(hitbox), history, _)| {
+                let pose = history.pose_at(rewound)?;
+                let toi =
+                    hitbox.cast_ray(pose.translation, pose.rotation, start, path, 1.0, true)?;
+                Some((tank, toi))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+
+        if let Some((tank, toi)) = hit {
+            transform.translation = start + path * toi;
+            commands
+                .entity(entity)
+                .insert(CollisionWith { entity: tank });
+        }
+    }
+}
+
+fn send_rewound_hitboxes(
+    tick: Res<ServerTick>,
+    q_shell: Query<(&LagCompensated, Option<&RoomId>), Added<LagCompensated>>,
+    q_tank: Query<(&Player, &RewindHitbox, &PoseHistory, Option<&RoomId>)>,
+    mut hitboxes: EventWriter<ToClients<RewoundHitboxesEvent>>,
+) {
+    for (compensated, room) in q_shell.iter() {
+        let rewound = ServerTick(tick.saturating_sub(compensated.rewind));
+        let boxes = q_tank
+            .iter()
+            .filter(|(player, _, _, tank_room)| {
+                player.client_id != compensated.owner && *tank_room == room
+            })
+            .filter_map(|(_, RewindHitbox(hitbox), history, _)| {
+                let size = hitbox.as_cuboid()?.half_extents() * 2.0;
+                Some(history.pose_at(rewound)?.with_scale(size))
+            })
+            .collect();
+
+        hitboxes.send(ToClients {
+            mode: SendMode::Direct(compensated.owner),
+            event: RewoundHitboxesEvent {
+                rewind_ticks: compensated.rewind,
+                hitboxes: boxes,
+            },
+        });
+    }
+}
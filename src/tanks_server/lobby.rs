@@ -0,0 +1,423 @@
+//! The pre-match lobby of each room
+
+use std::{collections::HashMap, time::Duration};
+
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+
+use crate::prelude::*;
+use crate::tanks_server::{rooms::add_room_event, server::PlayerInfoMap};
+
+pub mod prelude {
+    pub use super::{
+        Lobbies, MatchEndedEvent, MatchStartedEvent, RoomLobby, ServerLobbyPlugin, ServerLobbySet,
+    };
+}
+
+/// The RoomLobby holds the players of a room with their picks and the settings of the match
+#[derive(Debug, Clone)]
+pub struct RoomLobby {
+    pub settings: MatchSettings,
+    /// The players in the order they joined, the first one is the host
+    pub players: Vec<LobbyPlayer>,
+    pub in_match: bool,
+    /// The time left in the running match, None when it has no limit
+    time_left: Option<Timer>,
+    /// Whether the members need a new `LobbyStateEvent`
+    changed: bool,
+}
+
+impl RoomLobby {
+    fn new(settings: MatchSettings, in_match: bool) -> Self {
+        Self {
+            settings,
+            players: Vec::new(),
+            in_match,
+            time_left: None,
+            changed: true,
+        }
+    }
+
+    /// The player that picks the settings of the match
+    pub fn host(&self) -> Option<ClientId> {
+        self.players.first().map(|player| player.client_id)
+    }
+
+    /// Whether the match can start, an empty lobby is never ready
+    pub fn all_ready(&self) -> bool {
+        !self.players.is_empty() && self.players.iter().all(|player| player.ready)
+    }
+
+    /// Changes the map of the next match, like when the admin changes it
+    pub fn set_map(&mut self, map: &str) {
+        if self.settings.map != map {
+            self.settings.map = map.to_string();
+            self.changed = true;
+        }
+    }
+
+    /// Changes the mode of the next match, like when the admin changes it
+    pub fn set_mode(&mut self, mode: &str) {
+        if self.settings.mode != mode {
+            self.settings.mode = mode.to_string();
+            self.changed = true;
+        }
+    }
+
+    pub fn player(&self, client_id: ClientId) -> Option<&LobbyPlayer> {
+        self.players
+            .iter()
+            .find(|player| player.client_id == client_id)
+    }
+
+    fn player_mut(&mut self, client_id: ClientId) -> Option<&mut LobbyPlayer> {
+        self.players
+            .iter_mut()
+            .find(|player| player.client_id == client_id)
+    }
+
+    /// The team with the fewest players, new players join it
+    fn smallest_team(&self) -> Team {
+        Team::ALL
+            .into_iter()
+            .min_by_key(|team| {
+                self.players
+                    .iter()
+                    .filter(|player| player.team == *team)
+                    .count()
+            })
+            .unwrap_or_default()
+    }
+
+    fn state(&self, maps: &[String]) -> LobbyStateEvent {
+        LobbyStateEvent {
+            settings: self.settings.clone(),
+            players: self.players.clone(),
+            in_match: self.in_match,
+            maps: maps.to_vec(),
+        }
+    }
+}
+
+/// The Lobbies resource holds the lobby of each open room
+#[derive(Resource, Debug, Default, Deref, DerefMut)]
+pub struct Lobbies(HashMap<RoomId, RoomLobby>);
+
+impl Lobbies {
+    /// Whether the players of a room can spawn
+    pub fn in_match(&self, room_id: RoomId) -> bool {
+        self.get(&room_id).is_some_and(|lobby| lobby.in_match)
+    }
+}
+
+/// The MatchStartedEvent is sent when all the players of a room are ready, the scores of the room
+/// are reset
+#[derive(Event, Debug, Clone)]
+pub struct MatchStartedEvent {
+    pub room: RoomId,
+}
+
+/// The MatchEndedEvent is sent when the time limit of a match runs out, the tanks of the room
+/// are destroyed and its players go back to the lobby
+#[derive(Event, Debug, Clone)]
+pub struct MatchEndedEvent {
+    pub room: RoomId,
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ServerLobbySet;
+
+/// This plugin keeps a lobby for each room. The players pick their team, color and class and
+/// mark themselves ready, the host picks the mode, map and time limit. The match starts when all
+/// the players are ready and ends with its time limit, then the room moves to the next map of
+/// `ServerConfig::maps`. With `ServerConfig::lobby` unset the rooms are always in a match.
+#[derive(Debug, Clone)]
+pub struct ServerLobbyPlugin;
+
+impl Plugin for ServerLobbyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Lobbies>();
+        app.add_event::<MatchStartedEvent>();
+        app.add_event::<MatchEndedEvent>();
+        add_room_event::<LobbyStateEvent>(app);
+
+        app.add_systems(
+            Update,
+            (
+                open_lobbies,
+                sync_lobby_players,
+                handle_lobby_select,
+                handle_lobby_ready,
+                handle_match_settings,
+                start_matches,
+                end_matches,
+                send_lobby_states,
+            )
+                .chain()
+                .in_set(ServerLobbySet)
+                .after(RoomsSet),
+        );
+    }
+}
+
+fn open_lobbies(
+    mut opened: EventReader<RoomOpenedEvent>,
+    rooms: Res<Rooms>,
+    config: Res<ServerConfig>,
+    mut lobbies: ResMut<Lobbies>,
+) {
+    for RoomOpenedEvent { room, map } in opened.read() {
+        let Some(mode) = rooms.get(*room).map(|room| room.settings.mode.clone()) else {
+            continue;
+        };
+
+        let settings = MatchSettings {
+            mode,
+            map: map.clone(),
+            time_limit_secs: config.match_time_limit_secs,
+        };
+        lobbies.insert(*room, RoomLobby::new(settings, !config.lobby));
+    }
+}
+
+// Follows the members of the rooms, the players join the lobby once their join is accepted
+fn sync_lobby_players(
+    rooms: Res<Rooms>,
+    player_info_map: Res<PlayerInfoMap>,
+    mut lobbies: ResMut<Lobbies>,
+) {
+    lobbies.retain(|room_id, _| rooms.get(*room_id).is_some());
+
+    for (room_id, lobby) in lobbies.iter_mut() {
+        let count = lobby.players.len();
+        lobby.players.retain(|player| {
+            rooms.room_of(player.client_id) == Some(*room_id)
+                && player_info_map.contains_key(&player.client_id)
+        });
+        lobby.changed |= lobby.players.len() != count;
+
+        for client_id in rooms.members(*room_id) {
+            if lobby
+                .players
+                .iter()
+                .any(|player| player.client_id == client_id)
+            {
+                continue;
+            }
+            let Some(info) = player_info_map.get(&client_id) else {
+                continue;
+            };
+
+            let team = lobby.smallest_team();
+            lobby.players.push(LobbyPlayer {
+                client_id,
+                name: info.name.clone(),
+                team,
+                color: info.color,
+                class: TankClass::default(),
+                ready: false,
+            });
+            lobby.changed = true;
+        }
+    }
+}
+
+fn handle_lobby_select(
+    mut select: EventReader<FromClient<LobbySelectEvent>>,
+    rooms: Res<Rooms>,
+    mut lobbies: ResMut<Lobbies>,
+    mut player_info_map: ResMut<PlayerInfoMap>,
+    mut metrics: ResMut<ServerMetrics>,
+    mut violations: ResMut<ClientViolations>,
+) {
+    for FromClient { client_id, event } in select.read() {
+        let Some(color) = sanitize_color(event.color) else {
+            violations.record(*client_id, Violation::Malformed, &mut metrics);
+            continue;
+        };
+
+        // The picks are locked while the match is running
+        let Some(lobby) = rooms
+            .room_of(*client_id)
+            .and_then(|room_id| lobbies.get_mut(&room_id))
+            .filter(|lobby| !lobby.in_match)
+        else {
+            metrics.record_dropped_event("lobby_select");
+            continue;
+        };
+        let Some(player) = lobby.player_mut(*client_id) else {
+            metrics.record_dropped_event("lobby_select");
+            continue;
+        };
+
+        player.team = event.team;
+        player.color = color;
+        player.class = event.class;
+        lobby.changed = true;
+
+        if let Some(info) = player_info_map.get_mut(client_id) {
+            info.color = color;
+        }
+    }
+}
+
+fn handle_lobby_ready(
+    mut ready: EventReader<FromClient<LobbyReadyEvent>>,
+    rooms: Res<Rooms>,
+    mut lobbies: ResMut<Lobbies>,
+    mut metrics: ResMut<ServerMetrics>,
+) {
+    for FromClient { client_id, event } in ready.read() {
+        let Some(lobby) = rooms
+            .room_of(*client_id)
+            .and_then(|room_id| lobbies.get_mut(&room_id))
+            .filter(|lobby| !lobby.in_match)
+        else {
+            metrics.record_dropped_event("lobby_ready");
+            continue;
+        };
+        let Some(player) = lobby.player_mut(*client_id) else {
+            metrics.record_dropped_event("lobby_ready");
+            continue;
+        };
+
+        player.ready = event.ready;
+        lobby.changed = true;
+    }
+}
+
+fn handle_match_settings(
+    mut settings: EventReader<FromClient<MatchSettingsEvent>>,
+    rooms: Res<Rooms>,
+    config: Res<ServerConfig>,
+    mut lobbies: ResMut<Lobbies>,
+    mut metrics: ResMut<ServerMetrics>,
+    mut violations: ResMut<ClientViolations>,
+) {
+    for FromClient { client_id, event } in settings.read() {
+        let Some(lobby) = rooms
+            .room_of(*client_id)
+            .and_then(|room_id| lobbies.get_mut(&room_id))
+            .filter(|lobby| !lobby.in_match && lobby.host() == Some(*client_id))
+        else {
+            metrics.record_dropped_event("match_settings");
+            continue;
+        };
+
+        // The lobby of a client only offers valid settings
+        let validated = match validate_match_settings(&event.settings, &config.maps) {
+            Ok(validated) => validated,
+            Err(reason) => {
+                info!("Rejected match settings of {:?}: {}", client_id, reason);
+                violations.record(*client_id, Violation::Malformed, &mut metrics);
+                continue;
+            }
+        };
+
+        // The players agreed to the old settings
+        for player in lobby.players.iter_mut() {
+            player.ready = false;
+        }
+        lobby.settings = validated;
+        lobby.changed = true;
+    }
+}
+
+fn start_matches(
+    mut lobbies: ResMut<Lobbies>,
+    mut rooms: ResMut<Rooms>,
+    mut config: ResMut<ServerConfig>,
+    mut started: EventWriter<MatchStartedEvent>,
+    mut change_map: EventWriter<ChangeMapEvent>,
+) {
+    for (room_id, lobby) in lobbies.iter_mut() {
+        if lobby.in_match || !lobby.all_ready() {
+            continue;
+        }
+        let Some(room) = rooms.get_mut(*room_id) else {
+            continue;
+        };
+
+        info!(
+            "Starting a {} match on {} in room {}",
+            lobby.settings.mode, lobby.settings.map, room.settings.name
+        );
+        room.settings.mode = lobby.settings.mode.clone();
+        if *room_id == RoomId::DEFAULT {
+            config.mode = lobby.settings.mode.clone();
+        }
+
+        lobby.in_match = true;
+        lobby.time_left = (lobby.settings.time_limit_secs > 0).then(|| {
+            Timer::new(
+                Duration::from_secs(lobby.settings.time_limit_secs as u64),
+                TimerMode::Once,
+            )
+        });
+        lobby.changed = true;
+
+        started.send(MatchStartedEvent { room: *room_id });
+
+        // The level is already loaded unless the host picked another map
+        if room.settings.map != lobby.settings.map {
+            change_map.send(ChangeMapEvent {
+                room: *room_id,
+                level: lobby.settings.map.clone(),
+                reset_scores: false,
+            });
+        }
+    }
+}
+
+fn end_matches(
+    time: Res<Time>,
+    config: Res<ServerConfig>,
+    mut lobbies: ResMut<Lobbies>,
+    mut ended: EventWriter<MatchEndedEvent>,
+    mut change_map: EventWriter<ChangeMapEvent>,
+) {
+    for (room_id, lobby) in lobbies.iter_mut() {
+        let finished = lobby
+            .time_left
+            .as_mut()
+            .is_some_and(|timer| timer.tick(time.delta()).finished());
+        if !finished {
+            continue;
+        }
+
+        info!("The match in room {:?} is over", room_id);
+        lobby.in_match = false;
+        lobby.time_left = None;
+        for player in lobby.players.iter_mut() {
+            player.ready = false;
+        }
+        lobby.changed = true;
+
+        ended.send(MatchEndedEvent { room: *room_id });
+
+        if let Some(next) = config
+            .next_map(&lobby.settings.map)
+            .filter(|next| *next != lobby.settings.map)
+        {
+            change_map.send(ChangeMapEvent {
+                room: *room_id,
+                level: next.to_string(),
+                reset_scores: false,
+            });
+        }
+    }
+}
+
+fn send_lobby_states(
+    config: Res<ServerConfig>,
+    mut lobbies: ResMut<Lobbies>,
+    mut lobby_state: EventWriter<ToRoom<LobbyStateEvent>>,
+) {
+    for (room_id, lobby) in lobbies.iter_mut().filter(|(_, lobby)| lobby.changed) {
+        lobby.changed = false;
+        lobby_state.send(ToRoom {
+            room: *room_id,
+            event: lobby.state(&config.maps),
+        });
+    }
+}
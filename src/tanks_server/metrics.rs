@@ -0,0 +1,367 @@
+//! Prometheus metrics that are served on the `/metrics` HTTP endpoint
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write,
+    time::{Duration, Instant},
+};
+
+use bevy::{prelude::*, time::common_conditions::on_timer};
+use bevy_replicon_renet2::{netcode::NetcodeServerTransport, renet2::RenetServer};
+use tokio::sync::watch;
+
+use crate::prelude::*;
+use crate::tanks_server::server::PlayerInfoMap;
+
+pub mod prelude {
+    pub use super::{ServerMetrics, ServerMetricsPlugin};
+}
+
+/// How often the metrics are rendered
+const METRICS_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The upper bounds of the tick duration histogram buckets (in seconds)
+const TICK_DURATION_BUCKETS: [f64; 9] =
+    [0.001, 0.002, 0.004, 0.008, 0.0167, 0.025, 0.05, 0.1, 0.25];
+
+/// The names of the transports in the order of the server sockets
+const TRANSPORTS: [&str; 3] = ["native", "webtransport", "websocket"];
+
+/// The ServerMetrics resource holds the counters that are updated by the game systems
+#[derive(Resource, Debug, Default)]
+pub struct ServerMetrics {
+    kills_total: u64,
+    /// The time of the kills in the last minute
+    recent_kills: VecDeque<Duration>,
+    /// The number of client events that were ignored, by event name
+    dropped_events: BTreeMap<&'static str, u64>,
+    /// The number of client violations, by kind
+    violations: BTreeMap<&'static str, u64>,
+    /// The number of clients kicked for misbehaving
+    kicked_clients: u64,
+    /// The number of entities hidden from the clients in the last tick, summed over the clients
+    interest_hidden_entities: u64,
+    /// An estimate of the bytes not sent thanks to the interest management
+    interest_saved_bytes: u64,
+}
+
+impl ServerMetrics {
+    /// Records a kill that happened at the given elapsed time
+    pub fn record_kill(&mut self, now: Duration) {
+        self.kills_total += 1;
+        self.recent_kills.push_back(now);
+    }
+
+    /// Records a client event that was ignored by the server
+    pub fn record_dropped_event(&mut self, event: &'static str) {
+        *self.dropped_events.entry(event).or_default() += 1;
+    }
+
+    /// Records a client event that failed the validation
+    pub fn record_violation(&mut self, violation: &'static str) {
+        *self.violations.entry(violation).or_default() += 1;
+    }
+
+    /// Records a client that was kicked for misbehaving
+    pub fn record_kick(&mut self) {
+        self.kicked_clients += 1;
+    }
+
+    /// Records the entities hidden by the interest management in a tick and the bytes it saved
+    pub fn record_interest(&mut self, hidden_entities: u64, saved_bytes: u64) {
+        self.interest_hidden_entities = hidden_entities;
+        self.interest_saved_bytes += saved_bytes;
+    }
+
+    fn kills_per_minute(&mut self, now: Duration) -> usize {
+        let minute = Duration::from_secs(60);
+        while self
+            .recent_kills
+            .front()
+            .is_some_and(|time| now.saturating_sub(*time) > minute)
+        {
+            self.recent_kills.pop_front();
+        }
+
+        self.recent_kills.len()
+    }
+}
+
+#[derive(Resource, Debug, Default)]
+struct TickDurationHistogram {
+    buckets: [u64; TICK_DURATION_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl TickDurationHistogram {
+    fn observe(&mut self, value: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(TICK_DURATION_BUCKETS) {
+            if value <= bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+/// The time at which the current tick started
+#[derive(Resource, Debug, Deref)]
+struct TickStart(Instant);
+
+#[derive(Resource, Debug, Deref)]
+struct MetricsSender(watch::Sender<String>);
+
+/// The receiving half of the metrics channel, it is cloned into the HTTP server
+#[derive(Resource, Debug, Clone, Deref)]
+pub(crate) struct MetricsReceiver(watch::Receiver<String>);
+
+/// This plugin collects the server metrics and renders them in the Prometheus text format once
+/// per second.
+#[derive(Debug, Clone)]
+pub struct ServerMetricsPlugin;
+
+impl Plugin for ServerMetricsPlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = watch::channel(String::new());
+        app.insert_resource(MetricsSender(sender));
+        app.insert_resource(MetricsReceiver(receiver));
+        app.init_resource::<ServerMetrics>();
+        app.init_resource::<TickDurationHistogram>();
+        app.insert_resource(TickStart(Instant::now()));
+
+        app.add_systems(First, start_tick);
+        app.add_systems(Last, end_tick);
+        app.add_systems(
+            Last,
+            render_metrics
+                .after(end_tick)
+                .run_if(on_timer(METRICS_UPDATE_INTERVAL)),
+        );
+    }
+}
+
+fn start_tick(mut tick_start: ResMut<TickStart>) {
+    tick_start.0 = Instant::now();
+}
+
+fn end_tick(tick_start: Res<TickStart>, mut histogram: ResMut<TickDurationHistogram>) {
+    histogram.observe(tick_start.elapsed().as_secs_f64());
+}
+
+fn render_metrics(
+    time: Res<Time>,
+    mut metrics: ResMut<ServerMetrics>,
+    histogram: Res<TickDurationHistogram>,
+    player_info_map: Res<PlayerInfoMap>,
+    server: Option<Res<RenetServer>>,
+    transport: Option<Res<NetcodeServerTransport>>,
+    q_entity: Query<Entity>,
+    q_shell: Query<(), With<Shell>>,
+    sender: Res<MetricsSender>,
+) {
+    let mut out = String::new();
+
+    let mut clients = [0u64; TRANSPORTS.len()];
+    if let (Some(server), Some(transport)) = (server.as_ref(), transport.as_ref()) {
+        for client_id in server.clients_id() {
+            if let Some((socket_id, _)) = transport.client_addr(client_id) {
+                if let Some(count) = clients.get_mut(socket_id) {
+                    *count += 1;
+                }
+            }
+        }
+    }
+    write_header(
+        &mut out,
+        "tanks_connected_clients",
+        "The number of connected clients",
+        "gauge",
+    );
+    for (transport, count) in TRANSPORTS.iter().zip(clients) {
+        writeln!(
+            out,
+            "tanks_connected_clients{{transport=\"{}\"}} {}",
+            transport, count
+        )
+        .unwrap();
+    }
+
+    write_header(
+        &mut out,
+        "tanks_tick_duration_seconds",
+        "The duration of a server tick",
+        "histogram",
+    );
+    for (bound, count) in TICK_DURATION_BUCKETS.iter().zip(histogram.buckets) {
+        writeln!(
+            out,
+            "tanks_tick_duration_seconds_bucket{{le=\"{}\"}} {}",
+            bound, count
+        )
+        .unwrap();
+    }
+    writeln!(
+        out,
+        "tanks_tick_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+        histogram.count
+    )
+    .unwrap();
+    writeln!(out, "tanks_tick_duration_seconds_sum {}", histogram.sum).unwrap();
+    writeln!(out, "tanks_tick_duration_seconds_count {}", histogram.count).unwrap();
+
+    write_header(
+        &mut out,
+        "tanks_entities",
+        "The number of entities in the world",
+        "gauge",
+    );
+    writeln!(out, "tanks_entities {}", q_entity.iter().count()).unwrap();
+
+    write_header(
+        &mut out,
+        "tanks_shells_in_flight",
+        "The number of shells in flight",
+        "gauge",
+    );
+    writeln!(out, "tanks_shells_in_flight {}", q_shell.iter().count()).unwrap();
+
+    write_header(
+        &mut out,
+        "tanks_client_sent_bytes_per_second",
+        "The bytes sent to a client per second",
+        "gauge",
+    );
+    let mut received = String::new();
+    if let Some(server) = server.as_ref() {
+        for client_id in server.clients_id() {
+            let Ok(network_info) = server.network_info(client_id) else {
+                continue;
+            };
+            let name = player_info_map
+                .get(&ClientId::new(client_id))
+                .map(|info| escape_label(&info.name))
+                .unwrap_or_default();
+
+            writeln!(
+                out,
+                "tanks_client_sent_bytes_per_second{{client_id=\"{}\",name=\"{}\"}} {}",
+                client_id, name, network_info.bytes_sent_per_second
+            )
+            .unwrap();
+            writeln!(
+                received,
+                "tanks_client_received_bytes_per_second{{client_id=\"{}\",name=\"{}\"}} {}",
+                client_id, name, network_info.bytes_received_per_second
+            )
+            .unwrap();
+        }
+    }
+    write_header(
+        &mut out,
+        "tanks_client_received_bytes_per_second",
+        "The bytes received from a client per second",
+        "gauge",
+    );
+    out.push_str(&received);
+
+    write_header(
+        &mut out,
+        "tanks_kills_total",
+        "The number of kills since the server started",
+        "counter",
+    );
+    writeln!(out, "tanks_kills_total {}", metrics.kills_total).unwrap();
+
+    write_header(
+        &mut out,
+        "tanks_kills_per_minute",
+        "The number of kills in the last minute",
+        "gauge",
+    );
+    writeln!(
+        out,
+        "tanks_kills_per_minute {}",
+        metrics.kills_per_minute(time.elapsed())
+    )
+    .unwrap();
+
+    write_header(
+        &mut out,
+        "tanks_dropped_events_total",
+        "The number of client events ignored by the server",
+        "counter",
+    );
+    for (event, count) in metrics.dropped_events.iter() {
+        writeln!(
+            out,
+            "tanks_dropped_events_total{{event=\"{}\"}} {}",
+            event, count
+        )
+        .unwrap();
+    }
+
+    write_header(
+        &mut out,
+        "tanks_violations_total",
+        "The number of client events that failed the validation",
+        "counter",
+    );
+    for (violation, count) in metrics.violations.iter() {
+        writeln!(
+            out,
+            "tanks_violations_total{{kind=\"{}\"}} {}",
+            violation, count
+        )
+        .unwrap();
+    }
+
+    write_header(
+        &mut out,
+        "tanks_kicked_clients_total",
+        "The number of clients kicked for misbehaving",
+        "counter",
+    );
+    writeln!(out, "tanks_kicked_clients_total {}", metrics.kicked_clients).unwrap();
+
+    write_header(
+        &mut out,
+        "tanks_interest_hidden_entities",
+        "The number of entities hidden from the clients, summed over the clients",
+        "gauge",
+    );
+    writeln!(
+        out,
+        "tanks_interest_hidden_entities {}",
+        metrics.interest_hidden_entities
+    )
+    .unwrap();
+
+    write_header(
+        &mut out,
+        "tanks_interest_saved_bytes_total",
+        "An estimate of the bytes not replicated to the clients thanks to the interest management",
+        "counter",
+    );
+    writeln!(
+        out,
+        "tanks_interest_saved_bytes_total {}",
+        metrics.interest_saved_bytes
+    )
+    .unwrap();
+
+    sender.send_replace(out);
+}
+
+fn write_header(out: &mut String, name: &str, help: &str, kind: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -1,11 +1,35 @@
 //! This module contains the server-side game logic.
 
+pub mod admin;
 pub mod cannon;
+pub mod config;
+pub mod interest;
+pub mod lag_compensation;
+pub mod lobby;
+pub mod metrics;
 pub mod protocol;
+pub mod replay;
+pub mod rooms;
 pub mod server;
+pub mod shutdown;
+pub mod status;
+pub mod tick;
+pub mod validation;
 
 pub mod prelude {
+    pub use super::admin::prelude::*;
     pub use super::cannon::prelude::*;
+    pub use super::config::prelude::*;
+    pub use super::interest::prelude::*;
+    pub use super::lag_compensation::prelude::*;
+    pub use super::lobby::prelude::*;
+    pub use super::metrics::prelude::*;
     pub use super::protocol::prelude::*;
+    pub use super::replay::prelude::*;
+    pub use super::rooms::prelude::*;
     pub use super::server::prelude::*;
+    pub use super::shutdown::prelude::*;
+    pub use super::status::prelude::*;
+    pub use super::tick::prelude::*;
+    pub use super::validation::prelude::*;
 }
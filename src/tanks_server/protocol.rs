@@ -1,7 +1,8 @@
 use std::{
     net::{Ipv4Addr, SocketAddr, UdpSocket},
-    time::SystemTime,
+    time::{Duration, SystemTime},
 };
+use tokio::sync::watch;
 use warp::Filter;
 
 use bevy::prelude::*;
@@ -18,13 +19,20 @@ use bevy_replicon_renet2::{
 use serde::{Deserialize, Serialize};
 
 use crate::prelude::*;
+use crate::tanks_server::{
+    admin::AdminApi, metrics::MetricsReceiver, rooms::RoomApi, status::ServerStatusReceiver,
+};
 
 pub mod prelude {
     pub use super::{
-        ClientConnectedEvent, ClientDisconnectedEvent, ServerProtocolPlugin, ServerProtocolSet,
+        ClientConnectedEvent, ClientDisconnectedEvent, ServerNetworkPlugin, ServerProtocolPlugin,
+        ServerProtocolSet,
     };
 }
 
+/// The largest body accepted by `POST /rooms`, the settings of a room fit in much less
+const MAX_ROOM_BODY_SIZE: u64 = 4 * 1024;
+
 /// The ClientConnectedEvent is an event that is sent when a client connects to the server
 #[derive(Debug, Clone, Event)]
 pub struct ClientConnectedEvent {
@@ -41,10 +49,12 @@ pub struct ClientDisconnectedEvent {
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ServerProtocolSet;
 
+/// This plugin registers the network protocol and turns the replicon server events into
+/// `ClientConnectedEvent` and `ClientDisconnectedEvent`. It does not open any socket.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ServerProtocolPlugin;
+pub struct ServerNetworkPlugin;
 
-impl Plugin for ServerProtocolPlugin {
+impl Plugin for ServerNetworkPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(NetworkPlugin);
         app.add_plugins(RepliconRenetPlugins);
@@ -52,14 +62,34 @@ impl Plugin for ServerProtocolPlugin {
         app.add_event::<ClientConnectedEvent>();
         app.add_event::<ClientDisconnectedEvent>();
 
+        app.add_systems(
+            Update,
+            handle_server_events
+                .in_set(ServerProtocolSet)
+                .run_if(resource_exists::<RenetServer>),
+        );
+    }
+}
+
+/// This plugin starts the transport and the HTTP server of the game server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerProtocolPlugin;
+
+impl Plugin for ServerProtocolPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(ServerNetworkPlugin);
+
         let runtime = tokio::runtime::Runtime::new().unwrap();
         app.insert_resource(TokioRuntime(runtime));
 
+        app.init_resource::<ServerConfig>();
+        app.add_plugins(NetworkSimulatorPlugin);
+
         app.add_systems(Startup, start_server);
 
         app.add_systems(
             Update,
-            (handle_server_events)
+            update_server_announcement
                 .in_set(ServerProtocolSet)
                 .run_if(resource_exists::<RenetServer>),
         );
@@ -67,7 +97,11 @@ impl Plugin for ServerProtocolPlugin {
 }
 
 #[derive(Resource, Debug, Deref, DerefMut)]
-struct TokioRuntime(tokio::runtime::Runtime);
+pub(crate) struct TokioRuntime(tokio::runtime::Runtime);
+
+/// The latest announcement of the server, it is sent to the master server with each heartbeat
+#[derive(Resource, Debug, Deref)]
+struct ServerAnnouncementSender(watch::Sender<ServerAnnouncement>);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct ClientConnectionInfo {
@@ -81,23 +115,29 @@ fn start_server(
     mut commands: Commands,
     channels: Res<RepliconChannels>,
     runtime: Res<TokioRuntime>,
+    config: Res<ServerConfig>,
+    status: Res<ServerStatusReceiver>,
+    metrics: Res<MetricsReceiver>,
+    admin: Res<AdminApi>,
+    rooms: Res<RoomApi>,
+    network_conditions: Res<SharedNetworkConditions>,
 ) {
     let server = RenetServer::new(ConnectionConfig::from_channels(
         channels.get_server_configs(),
         channels.get_client_configs(),
     ));
 
-    let max_clients = 64;
+    let max_clients = config.max_clients;
 
     // HTTP server
-    let http_addr = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 5000);
+    let http_addr = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), config.http_port);
 
     // Native socket
-    let native_addr = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 5001);
+    let native_addr = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), config.native_port);
     let native_socket = NativeSocket::new(UdpSocket::bind(native_addr).unwrap()).unwrap();
 
     // WebTransport socket
-    let wt_addr = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 5002);
+    let wt_addr = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), config.wt_port);
     let (wt_socket, cert_hash) = {
         let (config, cert_hash) = WebTransportServerConfig::new_selfsigned(wt_addr, max_clients);
         (
@@ -107,7 +147,7 @@ fn start_server(
     };
 
     // WebSocket socket
-    let ws_addr = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 5003);
+    let ws_addr = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), config.ws_port);
     let ws_socket = {
         let config = WebSocketServerConfig::new(ws_addr, max_clients);
         WebSocketServer::new(config, runtime.handle().clone()).unwrap()
@@ -136,23 +176,133 @@ fn start_server(
         authentication: ServerAuthentication::Unsecure,
     };
 
-    let transport = NetcodeServerTransport::new_with_sockets(
-        server_config,
-        Vec::from([
+    let sockets = match network_conditions.get().is_enabled() {
+        true => Vec::from([
+            BoxedSocket::new(SimulatedSocket::new(
+                native_socket,
+                network_conditions.clone(),
+            )),
+            BoxedSocket::new(SimulatedSocket::new(wt_socket, network_conditions.clone())),
+            BoxedSocket::new(SimulatedSocket::new(ws_socket, network_conditions.clone())),
+        ]),
+        false => Vec::from([
             BoxedSocket::new(native_socket),
             BoxedSocket::new(wt_socket),
             BoxedSocket::new(ws_socket),
         ]),
-    )
-    .unwrap();
+    };
+    let transport = NetcodeServerTransport::new_with_sockets(server_config, sockets).unwrap();
+
+    let (announcement_sender, announcement_receiver) =
+        watch::channel(server_announcement(&config, 0));
+    if let Some(master_address) = config.master_address.clone() {
+        info!("Registering with the master server at {}", master_address);
+        runtime.spawn(async move { run_heartbeat(master_address, announcement_receiver).await });
+    }
 
     commands.insert_resource(server);
     commands.insert_resource(transport);
+    commands.insert_resource(ServerAnnouncementSender(announcement_sender));
+
+    let status = (**status).clone();
+    let metrics = (**metrics).clone();
+    let admin = admin.clone();
+    let rooms = rooms.clone();
+    runtime.spawn(async move {
+        run_http_server(
+            http_addr,
+            client_connection_info,
+            status,
+            metrics,
+            admin,
+            rooms,
+        )
+        .await
+    });
+}
 
-    runtime.spawn(async move { run_http_server(http_addr, client_connection_info).await });
+fn server_announcement(config: &ServerConfig, players: u32) -> ServerAnnouncement {
+    ServerAnnouncement {
+        address: config.public_address.clone(),
+        name: config.name.clone(),
+        map: config.level.clone(),
+        mode: config.mode.clone(),
+        players,
+        max_players: config.max_clients as u32,
+        http_port: config.http_port,
+        native_port: config.native_port,
+        wt_port: config.wt_port,
+        ws_port: config.ws_port,
+    }
 }
 
-async fn run_http_server(http_addr: SocketAddr, client_connection_info: ClientConnectionInfo) {
+fn update_server_announcement(
+    config: Res<ServerConfig>,
+    server: Res<RenetServer>,
+    sender: Res<ServerAnnouncementSender>,
+) {
+    let announcement = server_announcement(&config, server.connected_clients() as u32);
+
+    sender.send_if_modified(|current| {
+        if *current == announcement {
+            return false;
+        }
+
+        *current = announcement;
+        true
+    });
+}
+
+/// Registers the server with the master server periodically and whenever the announcement changes
+async fn run_heartbeat(
+    master_address: String,
+    mut announcement: watch::Receiver<ServerAnnouncement>,
+) {
+    let url = format!("http://{}/register", master_address);
+    let mut interval = tokio::time::interval(Duration::from_secs(HEARTBEAT_INTERVAL_SECS));
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            changed = announcement.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+            }
+        }
+
+        let body = match serde_json::to_string(&*announcement.borrow_and_update()) {
+            Ok(body) => body,
+            Err(error) => {
+                error!("Failed to serialize the server announcement: {}", error);
+                continue;
+            }
+        };
+
+        let url = url.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            ureq::post(&url)
+                .set("Content-Type", "application/json")
+                .send_string(&body)
+        })
+        .await;
+
+        match result {
+            Ok(Ok(_)) => debug!("Sent heartbeat to the master server"),
+            Ok(Err(error)) => warn!("Failed to register with the master server: {}", error),
+            Err(error) => warn!("Heartbeat task failed: {}", error),
+        }
+    }
+}
+
+async fn run_http_server(
+    http_addr: SocketAddr,
+    client_connection_info: ClientConnectionInfo,
+    status: watch::Receiver<ServerStatus>,
+    metrics: watch::Receiver<String>,
+    admin: AdminApi,
+    rooms: RoomApi,
+) {
     let native_port = client_connection_info.native_port;
     let wt_port = client_connection_info.wt_port;
     let ws_port = client_connection_info.ws_port;
@@ -174,7 +324,61 @@ async fn run_http_server(http_addr: SocketAddr, client_connection_info: ClientCo
         })
         .with(cors);
 
-    let routes = warp::get().and(native.or(wasm));
+    let cors = warp::cors().allow_any_origin();
+    let version = warp::path!("info")
+        .map(|| warp::reply::json(&ServerVersion::current()))
+        .with(cors);
+
+    let cors = warp::cors().allow_any_origin();
+    let status_rooms = status.clone();
+    let status = warp::path!("status")
+        .map(move || warp::reply::json(&*status.borrow()))
+        .with(cors);
+
+    let cors = warp::cors().allow_any_origin();
+    let room_list = warp::path!("rooms")
+        .map(move || warp::reply::json(&status_rooms.borrow().rooms))
+        .with(cors);
+
+    let cors = warp::cors().allow_any_origin();
+    let create_room = warp::path!("rooms")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(MAX_ROOM_BODY_SIZE))
+        .and(warp::body::bytes())
+        .then(move |body: warp::hyper::body::Bytes| {
+            let rooms = rooms.clone();
+            async move { rooms.handle(&body).await }
+        })
+        .with(cors);
+
+    let metrics = warp::path!("metrics").map(move || {
+        warp::reply::with_header(
+            metrics.borrow().clone(),
+            "Content-Type",
+            "text/plain; version=0.0.4",
+        )
+    });
+
+    let admin = warp::path!("admin")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::bytes())
+        .then(move |authorization, body: warp::hyper::body::Bytes| {
+            let admin = admin.clone();
+            async move { admin.handle(authorization, &body).await }
+        });
+
+    let routes = warp::get()
+        .and(
+            native
+                .or(wasm)
+                .or(version)
+                .or(status)
+                .or(room_list)
+                .or(metrics),
+        )
+        .or(admin)
+        .or(create_room);
 
     warp::serve(routes).run(http_addr).await;
 }
@@ -0,0 +1,364 @@
+//! Recording of the matches into replay files
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::BufWriter,
+    mem,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+
+use crate::prelude::*;
+
+pub mod prelude {
+    pub use super::ReplayRecorderPlugin;
+}
+
+/// The replay file of a room that is being written
+struct ReplayRecorder {
+    path: PathBuf,
+    writer: ReplayWriter<BufWriter<File>>,
+    /// The elapsed time of the server when the recording started
+    started: f32,
+    /// The entities that are in the replay, by the bits of their entity
+    recorded: HashSet<u64>,
+    /// The frame of the current tick
+    frame: ReplayFrame,
+    /// The entities of the room that still exist in the current tick
+    alive: HashSet<u64>,
+}
+
+impl ReplayRecorder {
+    fn create(dir: &str, room: RoomId, level: &str, elapsed: f32) -> Result<Self, String> {
+        let started_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or_default();
+        let level_name = Path::new(level)
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .unwrap_or("level");
+        let path = Path::new(dir).join(format!(
+            "{}-room{}-{}.{}",
+            started_at, room.0, level_name, REPLAY_EXTENSION
+        ));
+
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        let file = File::create(&path).map_err(|e| e.to_string())?;
+        let header = ReplayHeader {
+            protocol_id: PROTOCOL_ID,
+            game_version: GAME_VERSION.to_string(),
+            level: level.to_string(),
+            started_at,
+        };
+        let writer = ReplayWriter::new(BufWriter::new(file), &header).map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            path,
+            writer,
+            started: elapsed,
+            recorded: HashSet::new(),
+            frame: ReplayFrame::default(),
+            alive: HashSet::new(),
+        })
+    }
+
+    /// Starts the frame of a new tick
+    fn begin_frame(&mut self, elapsed: f32, tick: ServerTick) {
+        self.frame = ReplayFrame {
+            time: elapsed - self.started,
+            tick,
+            ..default()
+        };
+        self.alive.clear();
+    }
+
+    /// Writes the frame of the current tick if something changed in the room
+    fn end_frame(&mut self) -> Result<(), String> {
+        let alive = mem::take(&mut self.alive);
+        self.frame.despawned = self
+            .recorded
+            .iter()
+            .filter(|id| !alive.contains(id))
+            .copied()
+            .collect();
+        self.recorded = alive;
+
+        if self.frame.is_empty() {
+            return Ok(());
+        }
+
+        self.writer
+            .write_frame(&self.frame)
+            .map_err(|e| e.to_string())
+    }
+
+    fn finish(&mut self) {
+        match self.writer.flush() {
+            Ok(()) => info!("Saved the replay to {}", self.path.display()),
+            Err(error) => error!(
+                "Failed to save the replay {}: {}",
+                self.path.display(),
+                error
+            ),
+        }
+    }
+}
+
+/// The replays that are being recorded, one for each open room
+#[derive(Resource, Default)]
+struct ReplayRecorders(HashMap<RoomId, ReplayRecorder>);
+
+impl ReplayRecorders {
+    fn start(&mut self, dir: &str, room: RoomId, level: &str, elapsed: f32) {
+        match ReplayRecorder::create(dir, room, level, elapsed) {
+            Ok(recorder) => {
+                info!("Recording the replay to {}", recorder.path.display());
+                self.0.insert(room, recorder);
+            }
+            Err(error) => error!("Failed to start the replay in {}: {}", dir, error),
+        }
+    }
+
+    fn finish(&mut self, room: RoomId) {
+        if let Some(mut recorder) = self.0.remove(&room) {
+            recorder.finish();
+        }
+    }
+
+    fn push_event(&mut self, room: RoomId, event: ReplayEvent) {
+        if let Some(recorder) = self.0.get_mut(&room) {
+            recorder.frame.events.push(event);
+        }
+    }
+}
+
+/// This plugin records the replicated tanks, shells and stats and the events sent to the clients
+/// of each room into its own replay file in `ServerConfig::replay_dir`, one frame for each tick
+/// that changed something. A new file is started when the map of the room changes and the file
+/// is closed with the room.
+#[derive(Debug, Clone)]
+pub struct ReplayRecorderPlugin;
+
+impl Plugin for ReplayRecorderPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, init_recorders);
+        app.add_systems(
+            PostUpdate,
+            (
+                handle_room_opened,
+                record_frame,
+                handle_change_map,
+                finish_closed_rooms,
+            )
+                .chain()
+                .before(ServerSet::Send)
+                .run_if(resource_exists::<ReplayRecorders>),
+        );
+        app.add_systems(
+            Last,
+            flush_on_exit.run_if(resource_exists::<ReplayRecorders>),
+        );
+    }
+}
+
+fn init_recorders(mut commands: Commands, config: Res<ServerConfig>) {
+    if config.replay_dir.is_some() {
+        commands.init_resource::<ReplayRecorders>();
+    }
+}
+
+fn handle_room_opened(
+    mut opened: EventReader<RoomOpenedEvent>,
+    mut recorders: ResMut<ReplayRecorders>,
+    config: Res<ServerConfig>,
+    time: Res<Time>,
+) {
+    let Some(dir) = &config.replay_dir else {
+        return;
+    };
+
+    for event in opened.read() {
+        recorders.start(dir, event.room, &event.map, time.elapsed_secs());
+    }
+}
+
+fn record_frame(
+    time: Res<Time>,
+    tick: Res<ServerTick>,
+    mut recorders: ResMut<ReplayRecorders>,
+    q_entity: Query<
+        (
+            Entity,
+            Ref<Transform>,
+            Option<Ref<Player>>,
+            Option<&TankSkin>,
+            Option<Ref<Throttle>>,
+            Has<Shell>,
+            Option<&RoomId>,
+        ),
+        (With<Replicated>, Or<(With<Player>, With<Shell>)>),
+    >,
+    q_stats: Query<(Entity, Ref<PlayerStats>, Option<&RoomId>), With<Replicated>>,
+    mut fired: EventReader<ToRoom<CannonFiredEvent>>,
+    mut impact: EventReader<ToRoom<ShellImpactEvent>>,
+    mut joined: EventReader<ToRoom<PlayerJoinedEvent>>,
+    mut left: EventReader<ToRoom<PlayerLeftEvent>>,
+    mut died: EventReader<ToRoom<PlayerDiedEvent>>,
+) {
+    for recorder in recorders.0.values_mut() {
+        recorder.begin_frame(time.elapsed_secs(), *tick);
+    }
+
+    for (entity, transform, player, skin, throttle, is_shell, room) in q_entity.iter() {
+        let Some(recorder) = recorders.0.get_mut(&room.copied().unwrap_or_default()) else {
+            continue;
+        };
+        let id = entity.to_bits();
+        recorder.alive.insert(id);
+
+        let is_new = recorder.recorded.insert(id);
+        let throttle_changed = throttle.as_ref().is_some_and(|t| t.is_changed());
+        let replay_entity = match player {
+            Some(player) if is_new || player.is_changed() || throttle_changed => {
+                Some(ReplayEntity::Player {
+                    player: player.clone(),
+                    skin: skin.copied().unwrap_or_default(),
+                    transform: *transform,
+                    throttle: throttle.map(|t| t.value).unwrap_or_default(),
+                })
+            }
+            None if is_new && is_shell => Some(ReplayEntity::Shell {
+                transform: *transform,
+            }),
+            _ => None,
+        };
+
+        match replay_entity {
+            Some(replay_entity) => recorder.frame.entities.push((id, replay_entity)),
+            None if transform.is_changed() => recorder.frame.transforms.push((id, *transform)),
+            None => {}
+        }
+    }
+
+    for (entity, stats, room) in q_stats.iter() {
+        let Some(recorder) = recorders.0.get_mut(&room.copied().unwrap_or_default()) else {
+            continue;
+        };
+        let id = entity.to_bits();
+        recorder.alive.insert(id);
+
+        if recorder.recorded.insert(id) || stats.is_changed() {
+            recorder
+                .frame
+                .entities
+                .push((id, ReplayEntity::Stats(stats.clone())));
+        }
+    }
+
+    for e in fired.read() {
+        recorders.push_event(
+            e.room,
+            ReplayEvent::CannonFired {
+                position: e.event.position,
+                rotation: e.event.rotation,
+            },
+        );
+    }
+    for e in impact.read() {
+        recorders.push_event(e.room, ReplayEvent::ShellImpact(e.event.position));
+    }
+    for e in joined.read() {
+        recorders.push_event(
+            e.room,
+            ReplayEvent::PlayerJoined {
+                client_id: e.event.client_id,
+                name: e.event.name.clone(),
+            },
+        );
+    }
+    for e in left.read() {
+        recorders.push_event(
+            e.room,
+            ReplayEvent::PlayerLeft {
+                client_id: e.event.client_id,
+            },
+        );
+    }
+    for e in died.read() {
+        recorders.push_event(
+            e.room,
+            ReplayEvent::PlayerDied {
+                client_id: e.event.client_id,
+                position: e.event.position,
+            },
+        );
+    }
+
+    recorders
+        .0
+        .retain(|_, recorder| match recorder.end_frame() {
+            Ok(()) => true,
+            Err(error) => {
+                error!("Failed to write the replay frame: {}", error);
+                recorder.finish();
+                false
+            }
+        });
+}
+
+// The replay of a match ends when the next match starts or with the map of its room
+fn handle_change_map(
+    mut change_map: EventReader<ChangeMapEvent>,
+    mut started: EventReader<MatchStartedEvent>,
+    rooms: Res<Rooms>,
+    mut recorders: ResMut<ReplayRecorders>,
+    config: Res<ServerConfig>,
+    time: Res<Time>,
+) {
+    let Some(dir) = &config.replay_dir else {
+        return;
+    };
+
+    // A match that starts on another map also changes it, the replay restarts once
+    let restarted = change_map
+        .read()
+        .map(|event| event.room)
+        .chain(started.read().map(|event| event.room))
+        .collect::<HashSet<_>>();
+
+    for room in restarted {
+        let Some(level) = rooms.get(room).map(|room| room.settings.map.clone()) else {
+            continue;
+        };
+        if recorders.0.contains_key(&room) {
+            recorders.finish(room);
+            recorders.start(dir, room, &level, time.elapsed_secs());
+        }
+    }
+}
+
+fn finish_closed_rooms(rooms: Res<Rooms>, mut recorders: ResMut<ReplayRecorders>) {
+    let closed = recorders
+        .0
+        .keys()
+        .filter(|room| rooms.get(**room).is_none())
+        .copied()
+        .collect::<Vec<_>>();
+
+    for room in closed {
+        recorders.finish(room);
+    }
+}
+
+fn flush_on_exit(mut exit: EventReader<AppExit>, mut recorders: ResMut<ReplayRecorders>) {
+    if exit.read().last().is_some() {
+        for recorder in recorders.0.values_mut() {
+            recorder.finish();
+        }
+    }
+}
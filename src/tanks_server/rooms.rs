@@ -0,0 +1,469 @@
+//! Rooms, the isolated matches hosted by one server process
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use bevy_replicon::prelude::*;
+use tokio::sync::oneshot;
+use warp::{
+    http::StatusCode,
+    reply::{with_status, WithStatus},
+};
+
+use crate::prelude::*;
+
+pub mod prelude {
+    pub use super::{
+        Room, RoomOpenedEvent, Rooms, RoomsPlugin, RoomsSet, ToRoom, LEVEL_COLLISION_GROUP,
+        MAX_ROOMS,
+    };
+}
+
+/// The collision group of the colliders that are shared by all the rooms. The colliders without
+/// a room keep `CollisionGroups::default()`, all the memberships and filters, so they still
+/// overlap every room group.
+pub const LEVEL_COLLISION_GROUP: Group = Group::GROUP_1;
+
+/// The most rooms a server can host, each room takes one of the collision groups after
+/// `LEVEL_COLLISION_GROUP`
+pub const MAX_ROOMS: usize = 31;
+
+/// How long a room stays open without players, the default room is never closed
+const ROOM_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long the HTTP API waits for the game loop to open a room
+const ROOM_REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A Room is a match with its own level and players. Its members only see and collide with the
+/// entities that have its `RoomId`.
+#[derive(Debug, Clone)]
+pub struct Room {
+    pub settings: RoomSettings,
+    pub members: HashSet<ClientId>,
+    /// The collision group of the room
+    group: Group,
+    /// How long the room has been empty
+    idle: Timer,
+}
+
+/// The Rooms resource holds the open rooms and the room of each joined player
+#[derive(Resource, Debug, Default)]
+pub struct Rooms {
+    rooms: BTreeMap<RoomId, Room>,
+    clients: HashMap<ClientId, RoomId>,
+    next_id: u32,
+}
+
+impl Rooms {
+    /// Opens a room, returns None when all the collision groups are taken
+    pub fn open(&mut self, settings: RoomSettings) -> Option<RoomId> {
+        let group = (1..=MAX_ROOMS as u32)
+            .map(|bit| Group::from_bits_truncate(1 << bit))
+            .find(|group| self.rooms.values().all(|room| room.group != *group))?;
+
+        let room_id = RoomId(self.next_id);
+        self.next_id += 1;
+        self.rooms.insert(
+            room_id,
+            Room {
+                settings,
+                members: HashSet::new(),
+                group,
+                idle: Timer::new(ROOM_IDLE_TIMEOUT, TimerMode::Once),
+            },
+        );
+
+        Some(room_id)
+    }
+
+    pub fn get(&self, room_id: RoomId) -> Option<&Room> {
+        self.rooms.get(&room_id)
+    }
+
+    pub fn get_mut(&mut self, room_id: RoomId) -> Option<&mut Room> {
+        self.rooms.get_mut(&room_id)
+    }
+
+    /// The room a player joined
+    pub fn room_of(&self, client_id: ClientId) -> Option<RoomId> {
+        self.clients.get(&client_id).copied()
+    }
+
+    /// Moves a player into a room, the player stays in its current room if it cannot join
+    pub fn join(&mut self, client_id: ClientId, room_id: RoomId) -> Result<(), String> {
+        let room = self
+            .rooms
+            .get(&room_id)
+            .ok_or_else(|| "The room does not exist".to_string())?;
+        if !room.members.contains(&client_id)
+            && room.members.len() >= room.settings.max_players as usize
+        {
+            return Err(format!("The room {} is full", room.settings.name));
+        }
+
+        self.leave(client_id);
+        self.clients.insert(client_id, room_id);
+        if let Some(room) = self.rooms.get_mut(&room_id) {
+            room.members.insert(client_id);
+        }
+
+        Ok(())
+    }
+
+    pub fn leave(&mut self, client_id: ClientId) {
+        let Some(room_id) = self.clients.remove(&client_id) else {
+            return;
+        };
+
+        if let Some(room) = self.rooms.get_mut(&room_id) {
+            room.members.remove(&client_id);
+        }
+    }
+
+    /// The players in a room
+    pub fn members(&self, room_id: RoomId) -> impl Iterator<Item = ClientId> + '_ {
+        self.rooms
+            .get(&room_id)
+            .into_iter()
+            .flat_map(|room| room.members.iter().copied())
+    }
+
+    /// The list of the open rooms
+    pub fn info(&self) -> Vec<RoomInfo> {
+        self.rooms
+            .iter()
+            .map(|(room_id, room)| RoomInfo {
+                id: *room_id,
+                settings: room.settings.clone(),
+                players: room.members.len() as u32,
+            })
+            .collect()
+    }
+}
+
+/// The ToRoom event is sent to the members of a room, like a `ToClients` broadcast that stays
+/// inside the room
+#[derive(Event, Debug, Clone)]
+pub struct ToRoom<E> {
+    pub room: RoomId,
+    pub event: E,
+}
+
+/// The RoomOpenedEvent is sent when a room is opened, the server spawns its level
+#[derive(Event, Debug, Clone)]
+pub struct RoomOpenedEvent {
+    pub room: RoomId,
+    pub map: String,
+}
+
+struct RoomRequest {
+    settings: RoomSettings,
+    reply: oneshot::Sender<Result<RoomInfo, String>>,
+}
+
+#[derive(Resource)]
+struct RoomRequestReceiver(Mutex<Receiver<RoomRequest>>);
+
+/// The HTTP rooms API, it forwards the requests to open a room to the game loop
+#[derive(Resource, Clone)]
+pub(crate) struct RoomApi {
+    requests: Sender<RoomRequest>,
+}
+
+impl RoomApi {
+    /// Handles a `POST /rooms` request with the `RoomSettings` as JSON in the body, it answers
+    /// with the `RoomInfo` of the new room
+    pub(crate) async fn handle(&self, body: &[u8]) -> WithStatus<String> {
+        let settings = match serde_json::from_slice::<RoomSettings>(body) {
+            Ok(settings) => settings,
+            Err(error) => return with_status(error.to_string(), StatusCode::BAD_REQUEST),
+        };
+
+        let (reply, receiver) = oneshot::channel();
+        if self.requests.send(RoomRequest { settings, reply }).is_err() {
+            return with_status(
+                "The server is not running".to_string(),
+                StatusCode::SERVICE_UNAVAILABLE,
+            );
+        }
+
+        match tokio::time::timeout(ROOM_REPLY_TIMEOUT, receiver).await {
+            Ok(Ok(Ok(room))) => match serde_json::to_string(&room) {
+                Ok(room) => with_status(room, StatusCode::CREATED),
+                Err(error) => with_status(error.to_string(), StatusCode::INTERNAL_SERVER_ERROR),
+            },
+            Ok(Ok(Err(error))) => with_status(error, StatusCode::BAD_REQUEST),
+            _ => with_status(
+                "The server did not answer".to_string(),
+                StatusCode::GATEWAY_TIMEOUT,
+            ),
+        }
+    }
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RoomsSet;
+
+/// This plugin hosts the rooms of the server. It opens the default room on startup, opens the
+/// rooms requested over the HTTP API or with `CreateRoomEvent`, keeps the colliders of each room
+/// in its own collision group and closes the rooms that stay empty. `InterestPlugin` hides the
+/// entities of the other rooms from each client.
+#[derive(Debug, Clone)]
+pub struct RoomsPlugin;
+
+impl Plugin for RoomsPlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = mpsc::channel();
+        app.insert_resource(RoomApi { requests: sender });
+        app.insert_resource(RoomRequestReceiver(Mutex::new(receiver)));
+        app.init_resource::<Rooms>();
+        app.add_event::<RoomOpenedEvent>();
+
+        add_room_event::<PlayerJoinedEvent>(app);
+        add_room_event::<PlayerLeftEvent>(app);
+        add_room_event::<PlayerDiedEvent>(app);
+        add_room_event::<CannonFiredEvent>(app);
+        add_room_event::<ShellImpactEvent>(app);
+        add_room_event::<PlayerHitEvent>(app);
+
+        app.add_systems(Startup, open_default_room);
+        app.add_systems(
+            Update,
+            (
+                handle_list_rooms,
+                handle_create_room,
+                handle_room_requests,
+                close_idle_rooms,
+            )
+                .in_set(RoomsSet),
+        );
+        // The new tanks and shells get their groups before the physics step
+        app.add_systems(
+            FixedUpdate,
+            (assign_collider_rooms, apply_collision_groups)
+                .chain()
+                .in_set(RoomsSet)
+                .after(TankCannonSet),
+        );
+    }
+}
+
+/// Adds the `ToRoom` event of a server event and fans it out to the members of the room
+pub(crate) fn add_room_event<E: Event + Clone>(app: &mut App) {
+    app.add_event::<ToRoom<E>>();
+    app.add_systems(
+        PostUpdate,
+        send_room_events::<E>
+            .in_set(RoomsSet)
+            .before(ServerSet::Send),
+    );
+}
+
+fn send_room_events<E: Event + Clone>(
+    rooms: Res<Rooms>,
+    mut events: EventReader<ToRoom<E>>,
+    mut sent: EventWriter<ToClients<E>>,
+) {
+    for ToRoom { room, event } in events.read() {
+        for client_id in rooms.members(*room) {
+            sent.send(ToClients {
+                mode: SendMode::Direct(client_id),
+                event: event.clone(),
+            });
+        }
+    }
+}
+
+/// Validates the settings and opens the room
+fn create_room(
+    settings: &RoomSettings,
+    rooms: &mut Rooms,
+    config: &ServerConfig,
+    opened: &mut EventWriter<RoomOpenedEvent>,
+) -> Result<RoomInfo, String> {
+    if rooms.rooms.len() >= config.max_rooms.min(MAX_ROOMS) {
+        return Err("The server cannot host more rooms".to_string());
+    }
+
+    let taken = rooms.rooms.values().map(|room| room.settings.name.as_str());
+    let settings =
+        validate_room_settings(settings, taken, config.max_clients as u32, &config.maps)?;
+    let room_id = rooms
+        .open(settings.clone())
+        .ok_or_else(|| "The server cannot host more rooms".to_string())?;
+
+    info!("Opened room {} with {}", settings.name, settings.map);
+    opened.send(RoomOpenedEvent {
+        room: room_id,
+        map: settings.map.clone(),
+    });
+
+    Ok(RoomInfo {
+        id: room_id,
+        settings,
+        players: 0,
+    })
+}
+
+fn open_default_room(
+    config: Res<ServerConfig>,
+    mut rooms: ResMut<Rooms>,
+    mut opened: EventWriter<RoomOpenedEvent>,
+) {
+    let settings = RoomSettings {
+        name: config.name.clone(),
+        mode: config.mode.clone(),
+        map: config.level.clone(),
+        max_players: config.max_clients as u32,
+    };
+
+    if let Some(room_id) = rooms.open(settings) {
+        opened.send(RoomOpenedEvent {
+            room: room_id,
+            map: config.level.clone(),
+        });
+    }
+}
+
+fn handle_list_rooms(
+    mut list: EventReader<FromClient<ListRoomsEvent>>,
+    rooms: Res<Rooms>,
+    mut room_list: EventWriter<ToClients<RoomListEvent>>,
+) {
+    for FromClient { client_id, .. } in list.read() {
+        room_list.send(ToClients {
+            mode: SendMode::Direct(*client_id),
+            event: RoomListEvent {
+                rooms: rooms.info(),
+            },
+        });
+    }
+}
+
+fn handle_create_room(
+    mut create: EventReader<FromClient<CreateRoomEvent>>,
+    mut rooms: ResMut<Rooms>,
+    config: Res<ServerConfig>,
+    mut opened: EventWriter<RoomOpenedEvent>,
+    mut created: EventWriter<ToClients<RoomCreatedEvent>>,
+    mut rejected: EventWriter<ToClients<RoomRejectedEvent>>,
+) {
+    for FromClient { client_id, event } in create.read() {
+        match create_room(&event.settings, &mut rooms, &config, &mut opened) {
+            Ok(room) => {
+                created.send(ToClients {
+                    mode: SendMode::Direct(*client_id),
+                    event: RoomCreatedEvent { room },
+                });
+            }
+            Err(reason) => {
+                info!("Rejected room {:?}: {}", event.settings.name, reason);
+                rejected.send(ToClients {
+                    mode: SendMode::Direct(*client_id),
+                    event: RoomRejectedEvent { reason },
+                });
+            }
+        }
+    }
+}
+
+fn handle_room_requests(
+    receiver: Res<RoomRequestReceiver>,
+    mut rooms: ResMut<Rooms>,
+    config: Res<ServerConfig>,
+    mut opened: EventWriter<RoomOpenedEvent>,
+) {
+    let requests = receiver.0.lock().unwrap().try_iter().collect::<Vec<_>>();
+
+    for RoomRequest { settings, reply } in requests {
+        let _ = reply.send(create_room(&settings, &mut rooms, &config, &mut opened));
+    }
+}
+
+fn close_idle_rooms(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut rooms: ResMut<Rooms>,
+    q_room: Query<(Entity, &RoomId), Without<Parent>>,
+) {
+    rooms.rooms.retain(|room_id, room| {
+        if *room_id == RoomId::DEFAULT || !room.members.is_empty() {
+            room.idle.reset();
+            return true;
+        }
+        if !room.idle.tick(time.delta()).finished() {
+            return true;
+        }
+
+        info!("Closing the empty room {}", room.settings.name);
+        for (entity, _) in q_room
+            .iter()
+            .filter(|(_, entity_room)| *entity_room == room_id)
+        {
+            commands.entity(entity).despawn_recursive();
+        }
+        false
+    });
+}
+
+// The colliders of a level are spawned by blenvy under the level entity, they join its room
+fn assign_collider_rooms(
+    mut commands: Commands,
+    q_collider: Query<Entity, (Added<Collider>, Without<RoomId>)>,
+    q_parent: Query<&Parent>,
+    q_room: Query<&RoomId>,
+) {
+    for entity in q_collider.iter() {
+        let room = q_parent
+            .iter_ancestors(entity)
+            .find_map(|ancestor| q_room.get(ancestor).ok());
+
+        if let Some(room) = room {
+            commands.entity(entity).insert(*room);
+        }
+    }
+}
+
+fn apply_collision_groups(
+    mut commands: Commands,
+    rooms: Res<Rooms>,
+    mut q_collider: Query<
+        (
+            Entity,
+            &RoomId,
+            Has<Player>,
+            Has<Shell>,
+            Has<LagCompensated>,
+            Option<&mut KinematicCharacterController>,
+        ),
+        (With<Collider>, Changed<RoomId>),
+    >,
+) {
+    for (entity, room_id, is_tank, is_shell, is_compensated, controller) in q_collider.iter_mut() {
+        let Some(room) = rooms.get(*room_id) else {
+            continue;
+        };
+
+        let groups = match (is_compensated, is_tank || is_shell) {
+            // The lag compensated shells only hit the level, the tanks are hit at their rewound
+            // pose by `LagCompensationPlugin`
+            (true, _) => CollisionGroups::new(room.group, LEVEL_COLLISION_GROUP),
+            (false, true) => CollisionGroups::new(room.group, room.group | LEVEL_COLLISION_GROUP),
+            (false, false) => CollisionGroups::new(room.group | LEVEL_COLLISION_GROUP, room.group),
+        };
+
+        commands.entity(entity).insert(groups);
+        // The character controller does its own shape casts
+        if let Some(mut controller) = controller {
+            controller.filter_groups = Some(groups);
+        }
+    }
+}
@@ -5,30 +5,60 @@ use bevy::{
     app::ScheduleRunnerPlugin,
     log::{Level, LogPlugin},
     prelude::*,
+    time::common_conditions::on_timer,
     winit::WinitPlugin,
 };
 use bevy_rapier3d::prelude::*;
 use bevy_replicon::prelude::*;
+use bevy_replicon_renet2::{netcode::NetcodeServerTransport, renet2::RenetServer};
 use blenvy::*;
 
 use crate::prelude::*;
 
 pub mod prelude {
-    pub use super::ServerPlugin;
+    pub use super::{ChangeMapEvent, ServerGamePlugin, ServerPlugin};
+}
+
+/// The ChangeMapEvent is an event that is sent to replace the level of a room. All the tanks of
+/// the room are destroyed and its players respawn in the new level, the clients follow the
+/// `CurrentLevel` of the room.
+#[derive(Debug, Clone, Event)]
+pub struct ChangeMapEvent {
+    pub room: RoomId,
+    pub level: String,
+    /// Whether the scores of the players are reset
+    pub reset_scores: bool,
 }
 
 #[derive(Clone, Debug)]
-struct PlayerInfo {
-    name: String,
-    color: Color,
+pub(crate) struct PlayerInfo {
+    pub(crate) name: String,
+    pub(crate) color: Color,
+    pub(crate) skin: TankSkin,
+    /// The number of kills of the player
+    pub(crate) score: u32,
+    /// The token that identifies the player across reconnects
+    pub(crate) session_token: u64,
 }
 
 #[derive(Resource, Debug, Default, Clone, Deref, DerefMut)]
-struct PlayerInfoMap(HashMap<ClientId, PlayerInfo>);
+pub(crate) struct PlayerInfoMap(HashMap<ClientId, PlayerInfo>);
 
 #[derive(Resource, Debug, Default, Clone, Deref, DerefMut)]
 struct PlayerEntityMap(HashMap<ClientId, Entity>);
 
+/// The entities with the replicated `PlayerStats` of the connected players
+#[derive(Resource, Debug, Default, Clone, Deref, DerefMut)]
+struct PlayerStatsMap(HashMap<ClientId, Entity>);
+
+/// How often the scores and pings in `PlayerStats` are updated
+const PLAYER_STATS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The players that lost their connection, by session token. They resume with their score if
+/// they join again before the grace period ends.
+#[derive(Resource, Debug, Default, Clone, Deref, DerefMut)]
+struct DisconnectedPlayerMap(HashMap<u64, (PlayerInfo, Timer)>);
+
 pub struct ServerPlugin;
 
 impl Plugin for ServerPlugin {
@@ -44,21 +74,50 @@ impl Plugin for ServerPlugin {
                 }),
             ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(1.0 / 60.0)),
         ));
+        app.insert_resource(ServerConfig::from_env());
         app.add_plugins(BlenvyPlugin {
             export_registry: true,
             ..default()
         });
         app.add_plugins(ServerProtocolPlugin);
-        app.add_plugins(RapierPhysicsPlugin::<NoUserData>::default());
+        app.add_plugins(ServerStatusPlugin);
+        app.add_plugins(ServerMetricsPlugin);
+        app.add_plugins(AdminPlugin);
+        app.add_plugins(ShutdownPlugin);
+        app.add_plugins(ReplayRecorderPlugin);
+        app.add_plugins(ServerGamePlugin);
+
+        app.add_systems(Update, spawn_room_levels);
+    }
+}
+
+/// This plugin contains the game logic of the server without the windowing, the level loading
+/// and the transport, so it can also run headless in tests.
+pub struct ServerGamePlugin;
+
+impl Plugin for ServerGamePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(ServerTickPlugin);
+        app.add_plugins(RapierPhysicsPlugin::<NoUserData>::default().in_fixed_schedule());
         app.add_plugins(CollisionPlugin);
         app.add_plugins(TankControllerPlugin);
         app.add_plugins(TankCannonPlugin);
         app.add_plugins(HealthPlugin);
+        app.add_plugins(ValidationPlugin);
+        app.add_plugins(RoomsPlugin);
+        app.add_plugins(ServerLobbyPlugin);
+        app.add_plugins(InterestPlugin);
+        app.add_plugins(LagCompensationPlugin);
 
         app.init_resource::<PlayerInfoMap>();
         app.init_resource::<PlayerEntityMap>();
+        app.init_resource::<DisconnectedPlayerMap>();
+        app.init_resource::<PlayerStatsMap>();
+        app.init_resource::<ServerConfig>();
+        app.init_resource::<ServerMetrics>();
+        app.init_resource::<BanList>();
+        app.add_event::<ChangeMapEvent>();
 
-        app.add_systems(Startup, setup_game);
         app.add_systems(
             Update,
             (
@@ -66,18 +125,48 @@ impl Plugin for ServerPlugin {
                 handle_client_connected,
                 handle_client_disconnected,
                 handle_player_join,
-                handle_player_spawn,
-                handle_player_input,
-                handle_player_fire,
-                handle_player_dead,
-                handle_player_throttle,
+                spawn_current_levels,
+                // The tanks of an ended match are gone before the rotation changes the map
+                (handle_match_ended, handle_change_map, handle_match_started)
+                    .chain()
+                    .after(ServerLobbySet),
+                expire_disconnected_players,
+            ),
+        );
+        // The gameplay runs in the fixed tick, the inputs are applied before the tanks move
+        app.add_systems(
+            FixedUpdate,
+            (
+                (handle_player_spawn, handle_player_input, handle_player_fire)
+                    .before(TankControllerSet)
+                    .before(TankCannonSet),
+                handle_player_dead.after(HealthSet),
+                handle_player_throttle.after(TankControllerSet),
                 handle_player_outside_world,
             ),
         );
+        app.add_systems(
+            PostUpdate,
+            check_level_bounds.after(TransformSystem::TransformPropagate),
+        );
+        app.add_systems(
+            Update,
+            update_player_stats
+                .run_if(resource_exists::<RenetServer>)
+                .run_if(on_timer(PLAYER_STATS_INTERVAL)),
+        );
     }
 }
 
-fn spawn_player(commands: &mut Commands, client_id: &ClientId, info: &PlayerInfo) -> Entity {
+/// Spawns the tank of a player with the stats of its class, the team is only set in team modes
+fn spawn_player(
+    commands: &mut Commands,
+    client_id: &ClientId,
+    info: &PlayerInfo,
+    room: RoomId,
+    class: TankClass,
+    team: Option<Team>,
+) -> Entity {
     let position = Vec3::new(
         rand::random::<f32>() * 20. - 10.,
         5.0,
@@ -96,6 +185,7 @@ fn spawn_player(commands: &mut Commands, client_id: &ClientId, info: &PlayerInfo
                 name: info.name.clone(),
                 color: info.color,
             },
+            info.skin,
             Collider::cuboid(0.4, 0.2, 0.4),
             KinematicCharacterController {
                 custom_mass: Some(5.0),
@@ -116,26 +206,202 @@ fn spawn_player(commands: &mut Commands, client_id: &ClientId, info: &PlayerInfo
                 ..default()
             },
             TankControllerInput::default(),
-            TankController::default(),
+            TankController {
+                move_speed: class.move_speed(),
+                ..default()
+            },
             TankCannonInput::default(),
-            TankCannon::default(),
-            Health::default(),
+            TankCannon {
+                fire_rate_secs: class.fire_rate_secs(),
+                ..default()
+            },
+            Health {
+                value: class.health(),
+            },
             Throttle { value: 0.0 },
+            room,
         ))
         .id();
+    if let Some(team) = team {
+        commands.entity(entity).insert(team);
+    }
 
     entity
 }
 
-fn setup_game(mut commands: Commands) {
+fn spawn_level(commands: &mut Commands, level: &str, room: RoomId) {
     commands.spawn((
-        BlueprintInfo::from_path("levels/World.glb"), // all we need is a Blueprint info...
+        BlueprintInfo::from_path(level), // all we need is a Blueprint info...
         SpawnBlueprint, // and spawnblueprint to tell blenvy to spawn the blueprint now
         HideUntilReady, // only reveal the level once it is ready
         GameWorldTag,
+        room,
     ));
 }
 
+fn spawn_room_levels(mut commands: Commands, mut opened: EventReader<RoomOpenedEvent>) {
+    for RoomOpenedEvent { room, map } in opened.read() {
+        spawn_level(&mut commands, map, *room);
+    }
+}
+
+// Each room replicates its level to its members, the entity is closed with the room
+fn spawn_current_levels(mut commands: Commands, mut opened: EventReader<RoomOpenedEvent>) {
+    for RoomOpenedEvent { room, map } in opened.read() {
+        commands.spawn((
+            Replicated,
+            Name::new("CurrentLevel"),
+            NetworkEntity,
+            AlwaysRelevant,
+            CurrentLevel(map.clone()),
+            *room,
+        ));
+    }
+}
+
+fn handle_change_map(
+    mut commands: Commands,
+    mut change_map: EventReader<ChangeMapEvent>,
+    mut config: ResMut<ServerConfig>,
+    mut rooms: ResMut<Rooms>,
+    mut lobbies: ResMut<Lobbies>,
+    q_world: Query<(Entity, &RoomId), With<GameWorldTag>>,
+    mut q_level: Query<(&mut CurrentLevel, &RoomId)>,
+    q_player: Query<(Entity, &Transform, &Player, &RoomId)>,
+    mut player_entity_map: ResMut<PlayerEntityMap>,
+    mut player_info_map: ResMut<PlayerInfoMap>,
+    tick: Res<ServerTick>,
+    mut died: EventWriter<ToRoom<PlayerDiedEvent>>,
+) {
+    for event in change_map.read() {
+        let Some(room) = rooms.get_mut(event.room) else {
+            warn!("Cannot change the map of the closed room {:?}", event.room);
+            continue;
+        };
+
+        info!(
+            "Changing the map of {} to {}",
+            room.settings.name, event.level
+        );
+        room.settings.map = event.level.clone();
+        if event.room == RoomId::DEFAULT {
+            config.level = event.level.clone();
+        }
+        if let Some(lobby) = lobbies.get_mut(&event.room) {
+            lobby.set_map(&event.level);
+        }
+
+        for (entity, _) in q_world.iter().filter(|(_, room)| **room == event.room) {
+            commands.entity(entity).despawn_recursive();
+        }
+        spawn_level(&mut commands, &event.level, event.room);
+        for (mut level, _) in q_level.iter_mut().filter(|(_, room)| **room == event.room) {
+            level.set_if_neq(CurrentLevel(event.level.clone()));
+        }
+
+        // The players respawn in the new level like after a death
+        despawn_room_tanks(
+            &mut commands,
+            event.room,
+            &q_player,
+            &mut player_entity_map,
+            *tick,
+            &mut died,
+        );
+
+        if event.reset_scores {
+            for client_id in rooms.members(event.room) {
+                if let Some(info) = player_info_map.get_mut(&client_id) {
+                    info.score = 0;
+                }
+            }
+        }
+    }
+}
+
+fn handle_match_ended(
+    mut commands: Commands,
+    mut ended: EventReader<MatchEndedEvent>,
+    q_player: Query<(Entity, &Transform, &Player, &RoomId)>,
+    mut player_entity_map: ResMut<PlayerEntityMap>,
+    tick: Res<ServerTick>,
+    mut died: EventWriter<ToRoom<PlayerDiedEvent>>,
+) {
+    for MatchEndedEvent { room } in ended.read() {
+        despawn_room_tanks(
+            &mut commands,
+            *room,
+            &q_player,
+            &mut player_entity_map,
+            *tick,
+            &mut died,
+        );
+    }
+}
+
+// Each match starts with the scores of the room reset
+fn handle_match_started(
+    mut started: EventReader<MatchStartedEvent>,
+    rooms: Res<Rooms>,
+    mut player_info_map: ResMut<PlayerInfoMap>,
+) {
+    for MatchStartedEvent { room } in started.read() {
+        for client_id in rooms.members(*room) {
+            if let Some(info) = player_info_map.get_mut(&client_id) {
+                info.score = 0;
+            }
+        }
+    }
+}
+
+/// Destroys the tanks of a room, its players can spawn again like after a death
+fn despawn_room_tanks(
+    commands: &mut Commands,
+    room: RoomId,
+    q_player: &Query<(Entity, &Transform, &Player, &RoomId)>,
+    player_entity_map: &mut PlayerEntityMap,
+    tick: ServerTick,
+    died: &mut EventWriter<ToRoom<PlayerDiedEvent>>,
+) {
+    for (entity, transform, player, _) in q_player
+        .iter()
+        .filter(|(_, _, _, tank_room)| **tank_room == room)
+    {
+        commands.entity(entity).despawn_recursive();
+        player_entity_map.remove(&player.client_id);
+
+        died.send(ToRoom {
+            room,
+            event: PlayerDiedEvent {
+                client_id: player.client_id,
+                position: transform.translation,
+                tick,
+            },
+        });
+    }
+}
+
+// The replicated positions are clamped to the map bounds, a level that does not fit is reported
+// when its colliders spawn
+fn check_level_bounds(
+    q_collider: Query<
+        (&GlobalTransform, Option<&Name>),
+        (Added<Collider>, Without<Player>, Without<Shell>),
+    >,
+) {
+    for (transform, name) in q_collider.iter() {
+        if !in_map_bounds(transform.translation()) {
+            warn!(
+                "The collider {} at {} is outside of the map bounds {} to {}",
+                name.map(|name| name.as_str()).unwrap_or("without a name"),
+                transform.translation(),
+                MAP_BOUNDS_MIN,
+                MAP_BOUNDS_MAX
+            );
+        }
+    }
+}
+
 fn handle_collider_mapping(
     mut commands: Commands,
     q_collider: Query<(Entity, &BoxCollider), Without<Collider>>,
@@ -149,18 +415,16 @@ fn handle_collider_mapping(
 
 fn handle_client_connected(
     mut connected: EventReader<ClientConnectedEvent>,
-    mut joined: EventWriter<ToClients<PlayerJoinedEvent>>,
-    player_info_map: Res<PlayerInfoMap>,
+    bans: Res<BanList>,
+    mut server: ResMut<RenetServer>,
+    transport: Res<NetcodeServerTransport>,
 ) {
     for ClientConnectedEvent { client_id } in connected.read() {
-        for (id, info) in player_info_map.iter() {
-            joined.send(ToClients {
-                mode: SendMode::Direct(*client_id),
-                event: PlayerJoinedEvent {
-                    client_id: *id,
-                    name: info.name.clone(),
-                },
-            });
+        if let Some((_, addr)) = transport.client_addr(client_id.get()) {
+            if bans.is_ip_banned(&addr.ip()) {
+                info!("Rejected banned address {}", addr.ip());
+                server.disconnect(client_id.get());
+            }
         }
     }
 }
@@ -170,7 +434,10 @@ fn handle_client_disconnected(
     mut disconnected: EventReader<ClientDisconnectedEvent>,
     mut player_entity_map: ResMut<PlayerEntityMap>,
     mut player_info_map: ResMut<PlayerInfoMap>,
-    mut left: EventWriter<ToClients<PlayerLeftEvent>>,
+    mut disconnected_player_map: ResMut<DisconnectedPlayerMap>,
+    mut rooms: ResMut<Rooms>,
+    config: Res<ServerConfig>,
+    mut left: EventWriter<ToRoom<PlayerLeftEvent>>,
 ) {
     for ClientDisconnectedEvent {
         client_id,
@@ -180,67 +447,218 @@ fn handle_client_disconnected(
         if let Some(entity) = player_entity_map.remove(client_id) {
             commands.entity(entity).despawn_recursive();
         }
+        let room = rooms.room_of(*client_id);
+        rooms.leave(*client_id);
 
         if let Some(player_info) = player_info_map.remove(client_id) {
             info!("Player {} disconnected", player_info.name);
 
-            left.send(ToClients {
-                mode: SendMode::BroadcastExcept(*client_id),
-                event: PlayerLeftEvent {
-                    client_id: *client_id,
-                },
-            });
+            disconnected_player_map.insert(
+                player_info.session_token,
+                (
+                    player_info,
+                    Timer::from_seconds(config.reconnect_grace_secs, TimerMode::Once),
+                ),
+            );
+
+            if let Some(room) = room {
+                left.send(ToRoom {
+                    room,
+                    event: PlayerLeftEvent {
+                        client_id: *client_id,
+                    },
+                });
+            }
         }
     }
 }
 
 fn handle_player_join(
     mut join: EventReader<FromClient<PlayerJoinEvent>>,
-    mut joined: EventWriter<ToClients<PlayerJoinedEvent>>,
+    mut joined: EventWriter<ToRoom<PlayerJoinedEvent>>,
+    mut joined_direct: EventWriter<ToClients<PlayerJoinedEvent>>,
     mut player_info_map: ResMut<PlayerInfoMap>,
+    mut disconnected_player_map: ResMut<DisconnectedPlayerMap>,
+    mut metrics: ResMut<ServerMetrics>,
+    bans: Res<BanList>,
+    mut server: ResMut<RenetServer>,
+    mut left: EventWriter<ToRoom<PlayerLeftEvent>>,
+    mut rejected: EventWriter<ToClients<PlayerJoinRejectedEvent>>,
+    mut violations: ResMut<ClientViolations>,
+    mut rooms: ResMut<Rooms>,
 ) {
     for FromClient { client_id, event } in join.read() {
         if player_info_map.contains_key(client_id) {
+            metrics.record_dropped_event("player_join");
             continue;
         }
 
-        info!("Player {} joined", event.name);
+        // The old connection of a reconnecting player may not have timed out yet
+        let stale_client_id = player_info_map
+            .iter()
+            .find(|(_, info)| info.session_token == event.session_token)
+            .map(|(id, _)| *id);
+
+        let taken = player_info_map
+            .iter()
+            .filter(|(id, _)| Some(**id) != stale_client_id)
+            .map(|(_, info)| info.name.as_str());
+        let validated = validate_name(&event.name, taken)
+            .map_err(|reason| (Violation::InvalidName, reason))
+            .and_then(|name| match sanitize_color(event.color) {
+                Some(color) => Ok((name, color)),
+                None => Err((Violation::Malformed, "The color is invalid".to_string())),
+            })
+            .and_then(|validated| match event.skin.0 < TankSkin::COUNT {
+                true => Ok(validated),
+                false => Err((Violation::Malformed, "The skin is invalid".to_string())),
+            });
+        let (name, color) = match validated {
+            Ok(validated) => validated,
+            Err((violation, reason)) => {
+                info!("Rejected player {:?}: {}", event.name, reason);
+                violations.record(*client_id, violation, &mut metrics);
+                rejected.send(ToClients {
+                    mode: SendMode::Direct(*client_id),
+                    event: PlayerJoinRejectedEvent { reason },
+                });
+                continue;
+            }
+        };
+
+        if bans.is_name_banned(&name) {
+            info!("Rejected banned player {}", name);
+            server.disconnect(client_id.get());
+            continue;
+        }
+
+        // The stale connection does not take a place in the room
+        let stale_room = stale_client_id.and_then(|stale_client_id| rooms.room_of(stale_client_id));
+        if let Some(stale_client_id) = stale_client_id {
+            rooms.leave(stale_client_id);
+        }
+        let room = event.room.unwrap_or(RoomId::DEFAULT);
+        if let Err(reason) = rooms.join(*client_id, room) {
+            info!("Rejected player {} in room {:?}: {}", name, room, reason);
+            rejected.send(ToClients {
+                mode: SendMode::Direct(*client_id),
+                event: PlayerJoinRejectedEvent { reason },
+            });
+            continue;
+        }
+
+        let mut previous = disconnected_player_map
+            .remove(&event.session_token)
+            .map(|(player_info, _)| player_info);
+
+        if let Some(stale_client_id) = stale_client_id {
+            server.disconnect(stale_client_id.get());
+            previous = player_info_map.remove(&stale_client_id);
+
+            if let Some(room) = stale_room {
+                left.send(ToRoom {
+                    room,
+                    event: PlayerLeftEvent {
+                        client_id: stale_client_id,
+                    },
+                });
+            }
+        }
+
+        let score = match previous {
+            Some(player_info) => {
+                info!("Player {} reconnected", name);
+                player_info.score
+            }
+            None => {
+                info!("Player {} joined", name);
+                0
+            }
+        };
 
         player_info_map.insert(
             *client_id,
             PlayerInfo {
-                name: event.name.clone(),
-                color: event.color,
+                name: name.clone(),
+                color,
+                skin: event.skin,
+                score,
+                session_token: event.session_token,
             },
         );
 
-        joined.send(ToClients {
-            mode: SendMode::BroadcastExcept(*client_id),
+        // The new player learns about the players that are already in the room
+        for member in rooms.members(room).filter(|member| member != client_id) {
+            if let Some(info) = player_info_map.get(&member) {
+                joined_direct.send(ToClients {
+                    mode: SendMode::Direct(*client_id),
+                    event: PlayerJoinedEvent {
+                        client_id: member,
+                        name: info.name.clone(),
+                    },
+                });
+            }
+        }
+
+        joined.send(ToRoom {
+            room,
             event: PlayerJoinedEvent {
                 client_id: *client_id,
-                name: event.name.clone(),
+                name,
             },
         });
     }
 }
 
+fn expire_disconnected_players(
+    time: Res<Time>,
+    mut disconnected_player_map: ResMut<DisconnectedPlayerMap>,
+) {
+    disconnected_player_map.retain(|_, (player_info, timer)| {
+        let expired = timer.tick(time.delta()).finished();
+        if expired {
+            debug!("Forgetting player {}", player_info.name);
+        }
+        !expired
+    });
+}
+
 fn handle_player_spawn(
     mut commands: Commands,
     mut spawn: EventReader<FromClient<PlayerSpawnEvent>>,
     mut player_entity_map: ResMut<PlayerEntityMap>,
     player_info_map: Res<PlayerInfoMap>,
+    rooms: Res<Rooms>,
+    lobbies: Res<Lobbies>,
+    mut metrics: ResMut<ServerMetrics>,
 ) {
     for FromClient { client_id, .. } in spawn.read() {
         if player_entity_map.contains_key(client_id) {
+            metrics.record_dropped_event("player_spawn");
             continue;
         }
 
-        if let Some(player_info) = player_info_map.get(client_id) {
+        // The players wait in the lobby until the match starts
+        let player = player_info_map.get(client_id).zip(
+            rooms
+                .room_of(*client_id)
+                .filter(|room| lobbies.in_match(*room)),
+        );
+        if let Some((player_info, room)) = player {
             info!("Player {} spawned", player_info.name);
 
-            let entity = spawn_player(&mut commands, client_id, player_info);
+            let lobby = lobbies.get(&room);
+            let pick = lobby.and_then(|lobby| lobby.player(*client_id));
+            let class = pick.map(|pick| pick.class).unwrap_or_default();
+            let team = pick
+                .filter(|_| lobby.is_some_and(|lobby| lobby.settings.mode == TEAM_DEATHMATCH))
+                .map(|pick| pick.team);
+
+            let entity = spawn_player(&mut commands, client_id, player_info, room, class, team);
 
             player_entity_map.insert(*client_id, entity);
+        } else {
+            metrics.record_dropped_event("player_spawn");
         }
     }
 }
@@ -249,14 +667,31 @@ fn handle_player_input(
     mut input: EventReader<FromClient<PlayerInputEvent>>,
     mut q_player: Query<&mut TankControllerInput>,
     player_entity_map: Res<PlayerEntityMap>,
+    mut metrics: ResMut<ServerMetrics>,
+    mut violations: ResMut<ClientViolations>,
+    time: Res<Time>,
+    tick: Res<ServerTick>,
 ) {
     for FromClient { client_id, event } in input.read() {
-        if let Some(entity) = player_entity_map.get(client_id) {
-            if let Ok(mut player_input) = q_player.get_mut(*entity) {
-                player_input.forward = event.y;
-                player_input.steer = event.x;
-            }
+        if !violations.allow_input(*client_id, time.elapsed(), &mut metrics) {
+            continue;
         }
+        // A client cannot have seen a tick that was not simulated yet
+        let Some(axes) = sanitize_input(event.axes).filter(|_| event.tick <= *tick) else {
+            violations.record(*client_id, Violation::Malformed, &mut metrics);
+            continue;
+        };
+
+        let Some(mut player_input) = player_entity_map
+            .get(client_id)
+            .and_then(|entity| q_player.get_mut(*entity).ok())
+        else {
+            metrics.record_dropped_event("player_input");
+            continue;
+        };
+
+        player_input.forward = axes.y;
+        player_input.steer = axes.x;
     }
 }
 
@@ -264,21 +699,42 @@ fn handle_player_fire(
     mut fire: EventReader<FromClient<PlayerFireEvent>>,
     mut q_player: Query<&mut TankCannonInput>,
     player_entity_map: Res<PlayerEntityMap>,
+    mut metrics: ResMut<ServerMetrics>,
+    mut violations: ResMut<ClientViolations>,
+    time: Res<Time>,
+    tick: Res<ServerTick>,
 ) {
-    for FromClient { client_id, .. } in fire.read() {
-        if let Some(entity) = player_entity_map.get(client_id) {
-            if let Ok(mut player_fire) = q_player.get_mut(*entity) {
-                player_fire.fire = true;
-            }
+    for FromClient { client_id, event } in fire.read() {
+        if !violations.allow_fire(*client_id, time.elapsed(), &mut metrics) {
+            continue;
+        }
+        if event.tick > *tick {
+            violations.record(*client_id, Violation::Malformed, &mut metrics);
+            continue;
         }
+
+        let Some(mut player_fire) = player_entity_map
+            .get(client_id)
+            .and_then(|entity| q_player.get_mut(*entity).ok())
+        else {
+            metrics.record_dropped_event("player_fire");
+            continue;
+        };
+
+        player_fire.fire = true;
+        player_fire.seen_tick = event.tick;
     }
 }
 
 fn handle_player_dead(
     mut commands: Commands,
-    q_player: Query<(Entity, &Transform, &Player), With<Dead>>,
+    q_player: Query<(Entity, &Transform, &Player, &RoomId, Option<&DamagedBy>), With<Dead>>,
     mut player_entity_map: ResMut<PlayerEntityMap>,
-    mut died: EventWriter<ToClients<PlayerDiedEvent>>,
+    mut player_info_map: ResMut<PlayerInfoMap>,
+    mut metrics: ResMut<ServerMetrics>,
+    time: Res<Time>,
+    tick: Res<ServerTick>,
+    mut died: EventWriter<ToRoom<PlayerDiedEvent>>,
 ) {
     for (
         entity,
@@ -286,19 +742,29 @@ fn handle_player_dead(
         Player {
             client_id, name, ..
         },
+        room,
+        damaged_by,
     ) in q_player.iter()
     {
         println!("Player {} is dead", name);
 
+        if let Some(DamagedBy(killer)) = damaged_by.filter(|killer| killer.0 != *client_id) {
+            if let Some(killer_info) = player_info_map.get_mut(killer) {
+                killer_info.score += 1;
+            }
+            metrics.record_kill(time.elapsed());
+        }
+
         player_entity_map.remove(client_id);
 
         commands.entity(entity).despawn_recursive();
 
-        died.send(ToClients {
-            mode: SendMode::Broadcast,
+        died.send(ToRoom {
+            room: *room,
             event: PlayerDiedEvent {
                 client_id: *client_id,
                 position: transform.translation,
+                tick: *tick,
             },
         });
     }
@@ -312,9 +778,10 @@ fn handle_player_throttle(mut q_player: Query<(&TankControllerInput, &mut Thrott
 
 fn handle_player_outside_world(
     mut commands: Commands,
-    q_player: Query<(Entity, &Transform, &Player), Without<Dead>>,
+    q_player: Query<(Entity, &Transform, &Player, &RoomId), Without<Dead>>,
     mut player_entity_map: ResMut<PlayerEntityMap>,
-    mut died: EventWriter<ToClients<PlayerDiedEvent>>,
+    tick: Res<ServerTick>,
+    mut died: EventWriter<ToRoom<PlayerDiedEvent>>,
 ) {
     for (
         entity,
@@ -322,6 +789,7 @@ fn handle_player_outside_world(
         Player {
             client_id, name, ..
         },
+        room,
     ) in q_player.iter()
     {
         if transform.translation.y < -10. {
@@ -331,13 +799,64 @@ fn handle_player_outside_world(
 
             commands.entity(entity).despawn_recursive();
 
-            died.send(ToClients {
-                mode: SendMode::Broadcast,
+            died.send(ToRoom {
+                room: *room,
                 event: PlayerDiedEvent {
                     client_id: *client_id,
                     position: transform.translation,
+                    tick: *tick,
                 },
             });
         }
     }
 }
+
+// Keeps one `PlayerStats` entity for each player in the `PlayerInfoMap`
+fn update_player_stats(
+    mut commands: Commands,
+    server: Res<RenetServer>,
+    player_info_map: Res<PlayerInfoMap>,
+    rooms: Res<Rooms>,
+    mut player_stats_map: ResMut<PlayerStatsMap>,
+    mut q_stats: Query<&mut PlayerStats>,
+) {
+    player_stats_map.retain(|client_id, entity| {
+        if player_info_map.contains_key(client_id) {
+            return true;
+        }
+
+        commands.entity(*entity).despawn();
+        false
+    });
+
+    for (client_id, info) in player_info_map.iter() {
+        let stats = PlayerStats {
+            client_id: *client_id,
+            name: info.name.clone(),
+            score: info.score,
+            ping_ms: server
+                .network_info(client_id.get())
+                .map(|network_info| network_info.rtt.round() as u32)
+                .unwrap_or_default(),
+        };
+
+        if let Some(entity) = player_stats_map.get(client_id) {
+            if let Ok(mut current) = q_stats.get_mut(*entity) {
+                current.set_if_neq(stats);
+            }
+            continue;
+        }
+
+        let entity = commands
+            .spawn((
+                Replicated,
+                Name::new("PlayerStats"),
+                NetworkEntity,
+                AlwaysRelevant,
+                rooms.room_of(*client_id).unwrap_or_default(),
+                stats,
+            ))
+            .id();
+        player_stats_map.insert(*client_id, entity);
+    }
+}
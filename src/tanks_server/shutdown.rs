@@ -0,0 +1,185 @@
+//! Graceful shutdown of the server on SIGINT/SIGTERM
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+use bevy_replicon_renet2::{netcode::NetcodeServerTransport, renet2::RenetServer};
+
+use crate::prelude::*;
+use crate::tanks_server::protocol::TokioRuntime;
+
+pub mod prelude {
+    pub use super::{RequestShutdownEvent, ShutdownPlugin, ShutdownSet};
+}
+
+/// The time the clients get between the announcement and the shutdown (in seconds)
+const SHUTDOWN_COUNTDOWN_SECS: f32 = 5.0;
+
+/// The RequestShutdownEvent is an event that is sent to start the shutdown countdown
+#[derive(Debug, Clone, Event)]
+pub struct RequestShutdownEvent {
+    pub reason: String,
+}
+
+/// The number of termination signals received by the process
+#[derive(Resource, Debug, Clone, Default, Deref)]
+struct SignalCount(Arc<AtomicUsize>);
+
+#[derive(Resource, Debug, Clone)]
+struct ShuttingDown {
+    countdown: Timer,
+    /// Whether the clients were disconnected, the server exits on the next tick
+    disconnected: bool,
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ShutdownSet;
+
+/// This plugin announces the shutdown to the clients with a countdown, disconnects them and
+/// flushes the persistent state before exiting. A second signal exits immediately.
+#[derive(Debug, Clone)]
+pub struct ShutdownPlugin;
+
+impl Plugin for ShutdownPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SignalCount>();
+        app.add_event::<RequestShutdownEvent>();
+
+        app.add_systems(Startup, listen_for_signals);
+        app.add_systems(
+            Update,
+            (handle_signals, start_shutdown, update_shutdown)
+                .chain()
+                .in_set(ShutdownSet),
+        );
+        app.add_systems(Last, flush_on_exit);
+    }
+}
+
+fn listen_for_signals(runtime: Res<TokioRuntime>, signals: Res<SignalCount>) {
+    let signals = (*signals).clone();
+
+    runtime.spawn(async move {
+        #[cfg(unix)]
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+                .expect("Failed to listen for SIGTERM");
+
+        loop {
+            #[cfg(unix)]
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+
+            #[cfg(not(unix))]
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+
+            signals.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+}
+
+fn handle_signals(
+    signals: Res<SignalCount>,
+    mut handled: Local<usize>,
+    mut requests: EventWriter<RequestShutdownEvent>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let count = signals.load(Ordering::SeqCst);
+    if count == *handled {
+        return;
+    }
+
+    if count > 1 {
+        warn!("Received a second termination signal, exiting now");
+        exit.send(AppExit::Success);
+    } else {
+        requests.send(RequestShutdownEvent {
+            reason: "The server is shutting down".to_string(),
+        });
+    }
+    *handled = count;
+}
+
+fn start_shutdown(
+    mut commands: Commands,
+    mut requests: EventReader<RequestShutdownEvent>,
+    shutting_down: Option<Res<ShuttingDown>>,
+    mut announce: EventWriter<ToClients<ServerShuttingDownEvent>>,
+) {
+    let Some(request) = requests.read().last() else {
+        return;
+    };
+    if shutting_down.is_some() {
+        return;
+    }
+
+    info!(
+        "{}, stopping in {} seconds",
+        request.reason, SHUTDOWN_COUNTDOWN_SECS
+    );
+
+    announce.send(ToClients {
+        mode: SendMode::Broadcast,
+        event: ServerShuttingDownEvent {
+            reason: request.reason.clone(),
+            countdown: SHUTDOWN_COUNTDOWN_SECS,
+        },
+    });
+
+    commands.insert_resource(ShuttingDown {
+        countdown: Timer::from_seconds(SHUTDOWN_COUNTDOWN_SECS, TimerMode::Once),
+        disconnected: false,
+    });
+}
+
+fn update_shutdown(
+    time: Res<Time>,
+    shutting_down: Option<ResMut<ShuttingDown>>,
+    server: Option<ResMut<RenetServer>>,
+    transport: Option<ResMut<NetcodeServerTransport>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let Some(mut shutting_down) = shutting_down else {
+        return;
+    };
+
+    if shutting_down.disconnected {
+        exit.send(AppExit::Success);
+        return;
+    }
+
+    if shutting_down.countdown.tick(time.delta()).finished() {
+        if let (Some(mut server), Some(mut transport)) = (server, transport) {
+            info!("Disconnecting {} clients", server.connected_clients());
+            transport.disconnect_all(&mut server);
+        }
+        shutting_down.disconnected = true;
+    }
+}
+
+fn flush_on_exit(
+    mut exit: EventReader<AppExit>,
+    bans: Option<Res<BanList>>,
+    config: Res<ServerConfig>,
+) {
+    if exit.read().last().is_none() {
+        return;
+    }
+
+    if let Some(bans) = bans {
+        match bans.save(&config.bans_file) {
+            Ok(()) => info!("Saved the bans to {}", config.bans_file),
+            Err(error) => error!("Failed to save the bans: {}", error),
+        }
+    }
+
+    info!("Server stopped");
+}
@@ -0,0 +1,117 @@
+//! Snapshot of the server state that is served on the `/status` HTTP endpoint
+
+use std::time::Duration;
+
+use bevy::{prelude::*, time::common_conditions::on_timer};
+use bevy_replicon_renet2::renet2::RenetServer;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::prelude::*;
+use crate::tanks_server::server::PlayerInfoMap;
+
+pub mod prelude {
+    pub use super::{PlayerStatus, ServerStatus, ServerStatusPlugin};
+}
+
+/// How often the status snapshot is refreshed
+const STATUS_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PlayerStatus {
+    pub client_id: u64,
+    pub name: String,
+    pub score: u32,
+    /// The round trip time of the player (in milliseconds)
+    pub ping_ms: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerStatus {
+    pub name: String,
+    pub protocol_id: u64,
+    pub map: String,
+    pub mode: String,
+    pub uptime_secs: f64,
+    /// The measured number of simulation ticks per second
+    pub tick_rate: f64,
+    pub players: Vec<PlayerStatus>,
+    /// The open rooms, they are also served on the `/rooms` HTTP endpoint
+    pub rooms: Vec<RoomInfo>,
+}
+
+/// The sending half of the status channel, the game loop publishes the snapshots with it
+#[derive(Resource, Debug, Deref)]
+struct ServerStatusSender(watch::Sender<ServerStatus>);
+
+/// The receiving half of the status channel, it is cloned into the HTTP server
+#[derive(Resource, Debug, Clone, Deref)]
+pub(crate) struct ServerStatusReceiver(watch::Receiver<ServerStatus>);
+
+/// The number of simulation ticks since the last snapshot, counted in the fixed schedule so it
+/// does not depend on the frame rate
+#[derive(Resource, Debug, Default)]
+struct TickCounter(u32);
+
+/// This plugin publishes a snapshot of the server state once per second. The HTTP server only
+/// reads the latest snapshot, so it never blocks the game loop.
+#[derive(Debug, Clone)]
+pub struct ServerStatusPlugin;
+
+impl Plugin for ServerStatusPlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = watch::channel(ServerStatus::default());
+        app.insert_resource(ServerStatusSender(sender));
+        app.insert_resource(ServerStatusReceiver(receiver));
+        app.init_resource::<TickCounter>();
+
+        app.add_systems(FixedFirst, count_ticks);
+        app.add_systems(
+            Last,
+            update_server_status.run_if(on_timer(STATUS_UPDATE_INTERVAL)),
+        );
+    }
+}
+
+fn count_ticks(mut ticks: ResMut<TickCounter>) {
+    ticks.0 += 1;
+}
+
+fn update_server_status(
+    time: Res<Time>,
+    config: Res<ServerConfig>,
+    player_info_map: Res<PlayerInfoMap>,
+    rooms: Res<Rooms>,
+    server: Option<Res<RenetServer>>,
+    sender: Res<ServerStatusSender>,
+    mut ticks: ResMut<TickCounter>,
+) {
+    let tick_rate = ticks.0 as f64 / STATUS_UPDATE_INTERVAL.as_secs_f64();
+    ticks.0 = 0;
+
+    let mut players = player_info_map
+        .iter()
+        .map(|(client_id, info)| PlayerStatus {
+            client_id: client_id.get(),
+            name: info.name.clone(),
+            score: info.score,
+            ping_ms: server
+                .as_ref()
+                .and_then(|server| server.network_info(client_id.get()).ok())
+                .map(|network_info| network_info.rtt)
+                .unwrap_or_default(),
+        })
+        .collect::<Vec<_>>();
+    players.sort_by(|a, b| b.score.cmp(&a.score).then(a.name.cmp(&b.name)));
+
+    sender.send_replace(ServerStatus {
+        name: config.name.clone(),
+        protocol_id: PROTOCOL_ID,
+        map: config.level.clone(),
+        mode: config.mode.clone(),
+        uptime_secs: time.elapsed_secs_f64(),
+        tick_rate,
+        players,
+        rooms: rooms.info(),
+    });
+}
@@ -0,0 +1,57 @@
+//! The fixed simulation tick of the server
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use bevy_replicon::prelude::*;
+
+use crate::prelude::*;
+
+pub mod prelude {
+    pub use super::{ServerTickPlugin, ServerTickSet};
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ServerTickSet;
+
+/// This plugin runs the simulation at `TICK_RATE` in the fixed schedules. The `ServerTick` is
+/// advanced at the start of each tick and replicated to the clients once per frame.
+#[derive(Debug, Clone)]
+pub struct ServerTickPlugin;
+
+impl Plugin for ServerTickPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Time::<Fixed>::from_hz(TICK_RATE));
+        app.insert_resource(TimestepMode::Fixed {
+            dt: (1.0 / TICK_RATE) as f32,
+            substeps: 1,
+        });
+        app.init_resource::<ServerTick>();
+
+        app.add_systems(Startup, spawn_server_tick);
+        app.add_systems(FixedFirst, advance_tick.in_set(ServerTickSet));
+        app.add_systems(
+            PostUpdate,
+            replicate_tick.in_set(ServerTickSet).before(ServerSet::Send),
+        );
+    }
+}
+
+fn spawn_server_tick(mut commands: Commands, tick: Res<ServerTick>) {
+    commands.spawn((
+        Replicated,
+        Name::new("ServerTick"),
+        NetworkEntity,
+        AlwaysRelevant,
+        *tick,
+    ));
+}
+
+fn advance_tick(mut tick: ResMut<ServerTick>) {
+    tick.0 += 1;
+}
+
+fn replicate_tick(tick: Res<ServerTick>, mut q_tick: Query<&mut ServerTick>) {
+    for mut replicated in q_tick.iter_mut() {
+        replicated.set_if_neq(*tick);
+    }
+}
@@ -0,0 +1,380 @@
+//! Validation of the client events and sanity checks against flooding and cheating
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+use bevy_replicon_renet2::renet2::RenetServer;
+
+use crate::prelude::*;
+
+pub mod prelude {
+    pub use super::{
+        sanitize_color, sanitize_input, validate_map, validate_match_settings, validate_mode,
+        validate_name, validate_room_settings, ClientViolations, RateLimiter, ValidationPlugin,
+        ValidationSet, Violation, MAX_NAME_LENGTH,
+    };
+}
+
+/// The longest name a player can join with (in characters)
+pub const MAX_NAME_LENGTH: usize = 20;
+
+/// The input events a client can send per second, and in a burst. The client sends at most one
+/// input per `TICK_RATE` tick.
+const INPUT_RATE: (f32, f32) = (90.0, 30.0);
+
+/// The fire events a client can send per second, and in a burst. The cannon fires once per
+/// second, so a client never needs more.
+const FIRE_RATE: (f32, f32) = (4.0, 4.0);
+
+/// How much of the violation score is forgiven each second
+const VIOLATION_DECAY_PER_SEC: f32 = 1.0;
+
+/// A kind of client misbehavior
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Violation {
+    /// More input events than `INPUT_RATE`
+    InputFlood,
+    /// More fire events than `FIRE_RATE`
+    FireFlood,
+    /// Values that a real client never sends, like NaN axes
+    Malformed,
+    /// A name that is empty, too long or already taken
+    InvalidName,
+}
+
+impl Violation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Violation::InputFlood => "input_flood",
+            Violation::FireFlood => "fire_flood",
+            Violation::Malformed => "malformed",
+            Violation::InvalidName => "invalid_name",
+        }
+    }
+
+    /// How much the violation adds to the score of the client. The inputs over the rate are only
+    /// dropped, a client on a slow connection can receive and send them in bursts.
+    fn weight(&self) -> f32 {
+        match self {
+            Violation::InputFlood => 0.0,
+            Violation::FireFlood => 1.0,
+            Violation::Malformed => 5.0,
+            Violation::InvalidName => 2.0,
+        }
+    }
+}
+
+/// A token bucket that allows `rate` events per second with bursts of up to `burst` events
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    rate: f32,
+    burst: f32,
+    tokens: f32,
+    last: Duration,
+}
+
+impl RateLimiter {
+    pub fn new(rate: f32, burst: f32) -> Self {
+        Self {
+            rate,
+            burst,
+            tokens: burst,
+            last: Duration::ZERO,
+        }
+    }
+
+    /// Takes a token at the given elapsed time, returns false if there was none left
+    pub fn allow(&mut self, now: Duration) -> bool {
+        let elapsed = now.saturating_sub(self.last).as_secs_f32();
+        self.last = now;
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ClientLimits {
+    input: RateLimiter,
+    fire: RateLimiter,
+    /// The number of violations of the client, by kind
+    counts: BTreeMap<Violation, u32>,
+    /// The weighted violations that were not forgiven yet, the client is kicked when it goes
+    /// over `ServerConfig::max_violations`
+    score: f32,
+}
+
+impl Default for ClientLimits {
+    fn default() -> Self {
+        Self {
+            input: RateLimiter::new(INPUT_RATE.0, INPUT_RATE.1),
+            fire: RateLimiter::new(FIRE_RATE.0, FIRE_RATE.1),
+            counts: BTreeMap::new(),
+            score: 0.0,
+        }
+    }
+}
+
+/// The ClientViolations resource holds the rate limiters and the violation counters of the
+/// connected clients
+#[derive(Resource, Debug, Default)]
+pub struct ClientViolations(HashMap<ClientId, ClientLimits>);
+
+impl ClientViolations {
+    /// Records a violation of a client
+    pub fn record(
+        &mut self,
+        client_id: ClientId,
+        violation: Violation,
+        metrics: &mut ServerMetrics,
+    ) {
+        let limits = self.0.entry(client_id).or_default();
+        *limits.counts.entry(violation).or_default() += 1;
+        limits.score += violation.weight();
+        metrics.record_violation(violation.name());
+
+        debug!("Client {:?} violation: {}", client_id, violation.name());
+    }
+
+    /// Takes an input token of the client, a denied event is counted but does not get the client
+    /// kicked
+    pub fn allow_input(
+        &mut self,
+        client_id: ClientId,
+        now: Duration,
+        metrics: &mut ServerMetrics,
+    ) -> bool {
+        let allowed = self.0.entry(client_id).or_default().input.allow(now);
+        if !allowed {
+            self.record(client_id, Violation::InputFlood, metrics);
+        }
+        allowed
+    }
+
+    /// Takes a fire token of the client, a denied event counts as a violation
+    pub fn allow_fire(
+        &mut self,
+        client_id: ClientId,
+        now: Duration,
+        metrics: &mut ServerMetrics,
+    ) -> bool {
+        let allowed = self.0.entry(client_id).or_default().fire.allow(now);
+        if !allowed {
+            self.record(client_id, Violation::FireFlood, metrics);
+        }
+        allowed
+    }
+
+    /// The number of violations of a kind by a client
+    pub fn count(&self, client_id: ClientId, violation: Violation) -> u32 {
+        self.0
+            .get(&client_id)
+            .and_then(|limits| limits.counts.get(&violation))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// Checks the name a player wants to join with, returns the trimmed name
+pub fn validate_name<'a>(
+    name: &str,
+    mut taken: impl Iterator<Item = &'a str>,
+) -> Result<String, String> {
+    let name = name.trim();
+
+    if name.is_empty() {
+        return Err("The name is empty".to_string());
+    }
+    if name.chars().count() > MAX_NAME_LENGTH {
+        return Err(format!(
+            "The name is longer than {} characters",
+            MAX_NAME_LENGTH
+        ));
+    }
+    if name.chars().any(char::is_control) {
+        return Err("The name contains invalid characters".to_string());
+    }
+    if taken.any(|other| other.eq_ignore_ascii_case(name)) {
+        return Err(format!("The name {} is already taken", name));
+    }
+
+    Ok(name.to_string())
+}
+
+/// Checks the settings of a new room, returns them with the trimmed name and mode and the number
+/// of players capped by `max_players`. The map has to be one of `maps`.
+pub fn validate_room_settings<'a>(
+    settings: &RoomSettings,
+    taken: impl Iterator<Item = &'a str>,
+    max_players: u32,
+    maps: &[String],
+) -> Result<RoomSettings, String> {
+    let name = validate_name(&settings.name, taken)?;
+    let mode = validate_mode(&settings.mode)?;
+    let map = validate_map(&settings.map, maps)?;
+
+    if settings.max_players == 0 {
+        return Err("The room needs at least one player".to_string());
+    }
+
+    Ok(RoomSettings {
+        name,
+        mode,
+        map,
+        max_players: settings.max_players.min(max_players),
+    })
+}
+
+/// Checks the settings the host picked in the lobby, returns them with the trimmed mode and map.
+/// The map has to be one of `maps`.
+pub fn validate_match_settings(
+    settings: &MatchSettings,
+    maps: &[String],
+) -> Result<MatchSettings, String> {
+    let mode = validate_mode(&settings.mode)?;
+    let map = validate_map(&settings.map, maps)?;
+
+    if settings.time_limit_secs > MAX_TIME_LIMIT_SECS {
+        return Err(format!(
+            "The time limit cannot be longer than {} seconds",
+            MAX_TIME_LIMIT_SECS
+        ));
+    }
+
+    Ok(MatchSettings {
+        mode,
+        map,
+        time_limit_secs: settings.time_limit_secs,
+    })
+}
+
+/// Checks the name of a game mode, returns the trimmed mode
+pub fn validate_mode(mode: &str) -> Result<String, String> {
+    let trimmed = mode.trim();
+    let valid = !trimmed.is_empty()
+        && trimmed.chars().count() <= MAX_NAME_LENGTH
+        && trimmed
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        return Err(format!("The game mode {:?} is invalid", mode));
+    }
+
+    Ok(trimmed.to_string())
+}
+
+/// The map is loaded by the server, so it must stay inside the levels directory and be one of the
+/// maps the server offers, returns the trimmed map
+pub fn validate_map(map: &str, maps: &[String]) -> Result<String, String> {
+    let trimmed = map.trim();
+    let valid = trimmed.starts_with("levels/")
+        && trimmed.ends_with(".glb")
+        && trimmed
+            .split('/')
+            .all(|part| !part.is_empty() && part != ".." && !part.contains('\\'));
+    if !valid {
+        return Err(format!("The map {:?} is invalid", map));
+    }
+    if !maps.iter().any(|other| other == trimmed) {
+        return Err(format!("The map {:?} is not offered by the server", map));
+    }
+
+    Ok(trimmed.to_string())
+}
+
+/// Clamps the axes of an input event, returns None for values a client never sends
+pub fn sanitize_input(input: Vec2) -> Option<Vec2> {
+    input
+        .is_finite()
+        .then(|| input.clamp(Vec2::NEG_ONE, Vec2::ONE))
+}
+
+/// Returns the color as opaque sRGB, or None if it is not a valid color
+pub fn sanitize_color(color: Color) -> Option<Color> {
+    let Srgba {
+        red, green, blue, ..
+    } = color.to_srgba();
+
+    [red, green, blue]
+        .iter()
+        .all(|channel| channel.is_finite())
+        .then(|| {
+            Color::srgb(
+                red.clamp(0.0, 1.0),
+                green.clamp(0.0, 1.0),
+                blue.clamp(0.0, 1.0),
+            )
+        })
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ValidationSet;
+
+/// This plugin forgives the violations of the clients over time and kicks the clients whose
+/// violation score goes over `ServerConfig::max_violations`. The event handlers record the
+/// violations in `ClientViolations`.
+#[derive(Debug, Clone)]
+pub struct ValidationPlugin;
+
+impl Plugin for ValidationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ClientViolations>();
+
+        app.add_systems(
+            Update,
+            (
+                forget_disconnected_clients,
+                decay_violations,
+                kick_violating_clients.run_if(resource_exists::<RenetServer>),
+            )
+                .chain()
+                .in_set(ValidationSet),
+        );
+    }
+}
+
+fn forget_disconnected_clients(
+    mut disconnected: EventReader<ClientDisconnectedEvent>,
+    mut violations: ResMut<ClientViolations>,
+) {
+    for ClientDisconnectedEvent { client_id, .. } in disconnected.read() {
+        violations.0.remove(client_id);
+    }
+}
+
+fn decay_violations(time: Res<Time>, mut violations: ResMut<ClientViolations>) {
+    let decay = time.delta_secs() * VIOLATION_DECAY_PER_SEC;
+    for limits in violations.0.values_mut() {
+        limits.score = (limits.score - decay).max(0.0);
+    }
+}
+
+fn kick_violating_clients(
+    mut violations: ResMut<ClientViolations>,
+    config: Res<ServerConfig>,
+    mut server: ResMut<RenetServer>,
+    mut metrics: ResMut<ServerMetrics>,
+) {
+    for (client_id, limits) in violations.0.iter_mut() {
+        if limits.score <= config.max_violations as f32 {
+            continue;
+        }
+
+        warn!(
+            "Kicking client {:?} for misbehaving: {:?}",
+            client_id, limits.counts
+        );
+        server.disconnect(client_id.get());
+        metrics.record_kick();
+        limits.score = 0.0;
+    }
+}
@@ -0,0 +1,109 @@
+//! Gameplay tests with a headless server and clients
+
+mod harness;
+
+use bevy::prelude::*;
+use harness::TestGame;
+use tanks::prelude::*;
+use utils::prelude::*;
+
+/// The ticks between two shots, the cannon fires once per second
+const COOLDOWN_TICKS: usize = 70;
+
+#[test]
+fn players_see_each_other_join() {
+    let mut game = TestGame::new(2);
+    let first = game.client_id(0);
+    let second = game.client_id(1);
+
+    game.join(0, "first");
+    game.run(5);
+    game.join(1, "second");
+
+    assert!(game.run_until(|game| game.received(0).joined.contains(&second)));
+    assert!(game.run_until(|game| game
+        .client_stats(1, first)
+        .is_some_and(|s| s.name == "first")));
+}
+
+#[test]
+fn spawned_tank_is_replicated() {
+    let mut game = TestGame::new(2);
+    let first = game.client_id(0);
+
+    game.join(1, "second");
+    game.join_and_spawn(0, "first");
+
+    assert!(game.run_until(|game| game.client_player(1, first).is_some()));
+}
+
+#[test]
+fn tank_moves_forward() {
+    let mut game = TestGame::new(1);
+    let client_id = game.client_id(0);
+    let tank = game.join_and_spawn(0, "driver");
+
+    game.teleport(tank, Vec3::new(0.0, 0.5, 0.0));
+    game.run(30);
+    let start = game.client_player(0, client_id).unwrap().translation;
+
+    game.input(0, Vec2::new(0.0, 1.0));
+    assert!(game.run_until(|game| {
+        game.client_player(0, client_id)
+            .is_some_and(|transform| transform.translation.z > start.z + 1.0)
+    }));
+}
+
+#[test]
+fn fired_shell_hits_a_tank() {
+    let mut game = TestGame::new(2);
+    let shooter_id = game.client_id(0);
+    let target_id = game.client_id(1);
+    let shooter = game.join_and_spawn(0, "shooter");
+    let target = game.join_and_spawn(1, "target");
+
+    game.teleport(shooter, Vec3::new(0.0, 0.5, 0.0));
+    game.teleport(target, Vec3::new(0.0, 0.5, 4.0));
+    game.run(30);
+
+    game.fire(0);
+    assert!(game.run_until(|game| game.received(1).fired.contains(&Some(shooter_id))));
+    assert!(game.run_until(|game| game.received(1).impacts > 0));
+    assert!(game.run_until(|game| game.received(1).hits.contains(&target_id)));
+
+    let health = game.server.world().get::<Health>(target).unwrap();
+    assert!(health.value < Health::default().value);
+}
+
+#[test]
+fn tank_dies_after_two_hits() {
+    let mut game = TestGame::new(2);
+    let shooter_id = game.client_id(0);
+    let target_id = game.client_id(1);
+    let shooter = game.join_and_spawn(0, "shooter");
+    let target = game.join_and_spawn(1, "target");
+
+    game.teleport(shooter, Vec3::new(0.0, 0.5, 0.0));
+    game.teleport(target, Vec3::new(0.0, 0.5, 4.0));
+    game.run(30);
+
+    game.fire(0);
+    game.run(COOLDOWN_TICKS);
+    game.fire(0);
+
+    assert!(game.run_until(|game| game.received(0).died.contains(&target_id)));
+    assert!(game.run_until(|game| game.client_player(0, target_id).is_none()));
+    assert!(game.run_until(|game| {
+        game.client_stats(0, shooter_id)
+            .is_some_and(|stats| stats.score == 1)
+    }));
+}
+
+#[test]
+fn server_tick_is_replicated() {
+    let mut game = TestGame::new(1);
+
+    assert!(game.run_until(|game| game.server_tick(0) > ServerTick(10)));
+    let server_tick = *game.server.world().resource::<ServerTick>();
+    assert!(game.server_tick(0) <= server_tick);
+}
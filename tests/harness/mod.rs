@@ -0,0 +1,408 @@
+//! Headless in-process server and clients connected over loopback UDP
+
+#![allow(dead_code)]
+
+use std::{
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use bevy::{
+    asset::AssetPlugin, prelude::*, scene::ScenePlugin, state::app::StatesPlugin,
+    time::TimeUpdateStrategy,
+};
+use bevy_rapier3d::prelude::*;
+use bevy_replicon::prelude::*;
+use bevy_replicon_renet2::{
+    netcode::{
+        BoxedSocket, ClientAuthentication, ClientSocket, NativeSocket, NetcodeClientTransport,
+        NetcodeServerTransport, ServerAuthentication, ServerSetupConfig, ServerSocket,
+    },
+    renet2::{ConnectionConfig, RenetClient, RenetServer},
+    RenetChannelsExt,
+};
+use tanks::prelude::*;
+
+/// The duration of a tick of the server and the clients
+pub const TICK: Duration = Duration::from_micros(16_667);
+
+/// The most ticks `TestGame::run_until` waits for a condition
+pub const MAX_TICKS: usize = 600;
+
+/// The server events received by a test client
+#[derive(Resource, Debug, Default)]
+pub struct ReceivedEvents {
+    pub joined: Vec<ClientId>,
+    pub died: Vec<ClientId>,
+    /// The owners of the cannons that fired
+    pub fired: Vec<Option<ClientId>>,
+    pub impacts: usize,
+    pub hits: Vec<ClientId>,
+    /// The reasons of the rejected joins
+    pub rejected: Vec<String>,
+    pub created_rooms: Vec<RoomInfo>,
+    /// The latest lobby of the room of the client
+    pub lobby: Option<LobbyStateEvent>,
+}
+
+/// A server and its clients running in the same process
+pub struct TestGame {
+    pub server: App,
+    pub clients: Vec<App>,
+}
+
+impl TestGame {
+    /// Starts a server without the lobby, so the rooms are always in a match, and connects the
+    /// given number of clients to it
+    pub fn new(clients: usize) -> Self {
+        Self::with_config(
+            clients,
+            ServerConfig {
+                lobby: false,
+                ..default()
+            },
+        )
+    }
+
+    /// Starts a server with the given config and connects the given number of clients to it
+    pub fn with_config(clients: usize, config: ServerConfig) -> Self {
+        let (server, server_addr) = server_app(config);
+        let clients = (0..clients)
+            .map(|index| client_app(server_addr, index as u64 + 1))
+            .collect();
+
+        let mut game = Self { server, clients };
+        let connected = game.run_until(|game| {
+            game.clients.iter().all(|client| {
+                client
+                    .world()
+                    .get_resource::<RenetClient>()
+                    .is_some_and(|client| client.is_connected())
+            })
+        });
+        assert!(connected, "The clients did not connect to the server");
+
+        game
+    }
+
+    /// Runs one tick of the server and of every client
+    pub fn update(&mut self) {
+        self.server.update();
+        for client in self.clients.iter_mut() {
+            client.update();
+        }
+
+        // Give the loopback sockets time to deliver the packets
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    /// Runs ticks until the condition holds, returns false if it never did
+    pub fn run_until(&mut self, mut condition: impl FnMut(&mut Self) -> bool) -> bool {
+        for _ in 0..MAX_TICKS {
+            self.update();
+            if condition(self) {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Runs the given number of ticks
+    pub fn run(&mut self, ticks: usize) {
+        for _ in 0..ticks {
+            self.update();
+        }
+    }
+
+    /// The id the server gave to a client
+    pub fn client_id(&self, client: usize) -> ClientId {
+        **self.clients[client].world().resource::<LocalPlayer>()
+    }
+
+    /// Whether the server dropped the connection of a client
+    pub fn is_disconnected(&self, client: usize) -> bool {
+        self.clients[client]
+            .world()
+            .get_resource::<RenetClient>()
+            .is_none_or(|client| client.is_disconnected())
+    }
+
+    /// Sends a client event from a client to the server
+    pub fn send<E: Event>(&mut self, client: usize, event: E) {
+        self.clients[client].world_mut().send_event(event);
+    }
+
+    /// The latest server tick a client received
+    pub fn server_tick(&self, client: usize) -> ServerTick {
+        *self.clients[client].world().resource::<ServerTick>()
+    }
+
+    /// Sends the input axes of a client stamped with the tick it saw
+    pub fn input(&mut self, client: usize, axes: Vec2) {
+        let tick = self.server_tick(client);
+        self.send(client, PlayerInputEvent { axes, tick });
+    }
+
+    /// Fires the cannon of a client at the tick it saw
+    pub fn fire(&mut self, client: usize) {
+        let tick = self.server_tick(client);
+        self.send(client, PlayerFireEvent { tick });
+    }
+
+    pub fn join(&mut self, client: usize, name: &str) {
+        self.join_room(client, name, None);
+    }
+
+    pub fn join_room(&mut self, client: usize, name: &str, room: Option<RoomId>) {
+        self.send(
+            client,
+            PlayerJoinEvent {
+                name: name.to_string(),
+                color: Color::WHITE,
+                skin: TankSkin::default(),
+                session_token: client as u64 + 1,
+                room,
+            },
+        );
+    }
+
+    /// Opens a room from a client and waits for the server to answer
+    pub fn create_room(&mut self, client: usize, name: &str, max_players: u32) -> RoomInfo {
+        self.send(
+            client,
+            CreateRoomEvent {
+                settings: RoomSettings {
+                    name: name.to_string(),
+                    mode: "deathmatch".to_string(),
+                    map: "levels/World.glb".to_string(),
+                    max_players,
+                },
+            },
+        );
+
+        assert!(
+            self.run_until(|game| !game.received(client).created_rooms.is_empty()),
+            "The room {} was not created",
+            name
+        );
+        self.received(client).created_rooms[0].clone()
+    }
+
+    /// Marks a client ready in the lobby
+    pub fn ready(&mut self, client: usize, ready: bool) {
+        self.send(client, LobbyReadyEvent { ready });
+    }
+
+    /// The latest lobby a client received
+    pub fn lobby(&self, client: usize) -> Option<&LobbyStateEvent> {
+        self.received(client).lobby.as_ref()
+    }
+
+    /// The level of its room a client copied from the server
+    pub fn current_level(&self, client: usize) -> &str {
+        self.clients[client]
+            .world()
+            .resource::<CurrentLevel>()
+            .as_str()
+    }
+
+    /// Joins and spawns a client and waits until its tank is replicated back to it
+    pub fn join_and_spawn(&mut self, client: usize, name: &str) -> Entity {
+        self.join(client, name);
+        self.run(5);
+        self.spawn(client, name)
+    }
+
+    /// Spawns a joined client and waits until its tank is replicated back to it
+    pub fn spawn(&mut self, client: usize, name: &str) -> Entity {
+        self.send(client, PlayerSpawnEvent);
+
+        let client_id = self.client_id(client);
+        assert!(
+            self.run_until(|game| game.client_player(client, client_id).is_some()),
+            "The tank of {} was not replicated",
+            name
+        );
+
+        self.server_player(client_id).unwrap()
+    }
+
+    /// The tank of a player on the server
+    pub fn server_player(&mut self, client_id: ClientId) -> Option<Entity> {
+        self.server
+            .world_mut()
+            .query::<(Entity, &Player)>()
+            .iter(self.server.world())
+            .find(|(_, player)| player.client_id == client_id)
+            .map(|(entity, _)| entity)
+    }
+
+    /// The replicated tank of a player as seen by a client
+    pub fn client_player(&mut self, client: usize, client_id: ClientId) -> Option<Transform> {
+        let world = self.clients[client].world_mut();
+        world
+            .query::<(&Player, &Transform)>()
+            .iter(world)
+            .find(|(player, _)| player.client_id == client_id)
+            .map(|(_, transform)| *transform)
+    }
+
+    /// The replicated stats of a player as seen by a client
+    pub fn client_stats(&mut self, client: usize, client_id: ClientId) -> Option<PlayerStats> {
+        let world = self.clients[client].world_mut();
+        world
+            .query::<&PlayerStats>()
+            .iter(world)
+            .find(|stats| stats.client_id == client_id)
+            .cloned()
+    }
+
+    pub fn received(&self, client: usize) -> &ReceivedEvents {
+        self.clients[client].world().resource::<ReceivedEvents>()
+    }
+
+    /// Moves a tank on the server, it faces +Z
+    pub fn teleport(&mut self, entity: Entity, translation: Vec3) {
+        let mut transform = self
+            .server
+            .world_mut()
+            .get_mut::<Transform>(entity)
+            .unwrap();
+        transform.translation = translation;
+    }
+}
+
+fn current_time() -> Duration {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+}
+
+fn loopback_socket() -> NativeSocket {
+    let socket = UdpSocket::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0)).unwrap();
+    NativeSocket::new(socket).unwrap()
+}
+
+fn headless_app() -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        StatesPlugin,
+        TransformPlugin,
+        HierarchyPlugin,
+        AssetPlugin::default(),
+        ScenePlugin,
+    ));
+    app.init_asset::<Mesh>();
+    app.insert_resource(TimeUpdateStrategy::ManualDuration(TICK));
+    app
+}
+
+/// The server game logic with a flat ground instead of the level
+fn server_app(config: ServerConfig) -> (App, SocketAddr) {
+    let mut app = headless_app();
+    app.insert_resource(config);
+    app.add_plugins((ServerNetworkPlugin, ServerGamePlugin));
+    app.finish();
+    app.cleanup();
+
+    app.world_mut().spawn((
+        Name::new("Ground"),
+        Transform::from_xyz(0.0, -0.5, 0.0),
+        RigidBody::Fixed,
+        Collider::cuboid(50.0, 0.5, 50.0),
+    ));
+
+    let channels = app.world().resource::<RepliconChannels>();
+    let server = RenetServer::new(ConnectionConfig::from_channels(
+        channels.get_server_configs(),
+        channels.get_client_configs(),
+    ));
+
+    let socket = loopback_socket();
+    let server_addr = ServerSocket::addr(&socket).unwrap();
+    let server_config = ServerSetupConfig {
+        current_time: current_time(),
+        max_clients: 8,
+        protocol_id: PROTOCOL_ID,
+        socket_addresses: vec![vec![server_addr]],
+        authentication: ServerAuthentication::Unsecure,
+    };
+    let transport =
+        NetcodeServerTransport::new_with_sockets(server_config, vec![BoxedSocket::new(socket)])
+            .unwrap();
+
+    app.insert_resource(server);
+    app.insert_resource(transport);
+
+    (app, server_addr)
+}
+
+/// A client without rendering, input or UI
+fn client_app(server_addr: SocketAddr, client_id: u64) -> App {
+    let mut app = headless_app();
+    app.init_state::<GameStates>();
+    app.add_plugins(ClientProtocolPlugin);
+    app.init_resource::<ReceivedEvents>();
+    app.add_systems(Update, record_events);
+    app.finish();
+    app.cleanup();
+
+    let channels = app.world().resource::<RepliconChannels>();
+    let config = ConnectionConfig::from_channels(
+        channels.get_server_configs(),
+        channels.get_client_configs(),
+    );
+
+    let socket = loopback_socket();
+    let client = RenetClient::new(config, ClientSocket::is_reliable(&socket));
+    let authentication = ClientAuthentication::Unsecure {
+        socket_id: 0,
+        server_addr,
+        client_id,
+        user_data: None,
+        protocol_id: PROTOCOL_ID,
+    };
+    let transport = NetcodeClientTransport::new(current_time(), authentication, socket).unwrap();
+
+    app.insert_resource(LocalPlayer(ClientId::new(client_id)));
+    app.insert_resource(client);
+    app.insert_resource(transport);
+
+    app
+}
+
+fn record_events(
+    mut received: ResMut<ReceivedEvents>,
+    mut joined: EventReader<PlayerJoinedEvent>,
+    mut died: EventReader<PlayerDiedEvent>,
+    mut fired: EventReader<CannonFiredEvent>,
+    mut impacts: EventReader<ShellImpactEvent>,
+    mut hits: EventReader<PlayerHitEvent>,
+    mut rejected: EventReader<PlayerJoinRejectedEvent>,
+    mut created_rooms: EventReader<RoomCreatedEvent>,
+    mut lobby: EventReader<LobbyStateEvent>,
+) {
+    received
+        .joined
+        .extend(joined.read().map(|event| event.client_id));
+    received
+        .died
+        .extend(died.read().map(|event| event.client_id));
+    received.fired.extend(fired.read().map(|event| event.owner));
+    received.impacts += impacts.read().count();
+    received
+        .hits
+        .extend(hits.read().map(|event| event.client_id));
+    received
+        .rejected
+        .extend(rejected.read().map(|event| event.reason.clone()));
+    received
+        .created_rooms
+        .extend(created_rooms.read().map(|event| event.room.clone()));
+    if let Some(event) = lobby.read().last() {
+        received.lobby = Some(event.clone());
+    }
+}
@@ -0,0 +1,41 @@
+//! Interest management tests with a headless server and clients
+
+mod harness;
+
+use bevy::prelude::*;
+use harness::TestGame;
+
+#[test]
+fn far_tanks_are_not_replicated() {
+    let mut game = TestGame::new(2);
+    let target_id = game.client_id(1);
+    let observer = game.join_and_spawn(0, "observer");
+    let target = game.join_and_spawn(1, "target");
+
+    game.teleport(observer, Vec3::new(-40.0, 0.5, 0.0));
+    game.teleport(target, Vec3::new(-35.0, 0.5, 0.0));
+    assert!(game.run_until(|game| game.client_player(0, target_id).is_some()));
+
+    game.teleport(target, Vec3::new(40.0, 0.5, 0.0));
+    assert!(game.run_until(|game| game.client_player(0, target_id).is_none()));
+    assert!(game.client_player(1, target_id).is_some());
+    assert!(game.client_stats(0, target_id).is_some());
+
+    game.teleport(target, Vec3::new(-35.0, 0.5, 0.0));
+    assert!(game.run_until(|game| game.client_player(0, target_id).is_some()));
+}
+
+#[test]
+fn dead_clients_see_their_whole_room() {
+    let mut game = TestGame::new(2);
+    let target_id = game.client_id(1);
+    let observer = game.join_and_spawn(0, "observer");
+    let target = game.join_and_spawn(1, "target");
+
+    game.teleport(observer, Vec3::new(-40.0, 0.5, 0.0));
+    game.teleport(target, Vec3::new(40.0, 0.5, 0.0));
+    assert!(game.run_until(|game| game.client_player(0, target_id).is_none()));
+
+    game.server.world_mut().despawn(observer);
+    assert!(game.run_until(|game| game.client_player(0, target_id).is_some()));
+}
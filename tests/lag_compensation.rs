@@ -0,0 +1,44 @@
+//! Lag compensation tests with a headless server and clients
+
+mod harness;
+
+use bevy::prelude::*;
+use harness::TestGame;
+use tanks::prelude::*;
+use utils::prelude::*;
+
+/// Fires at a tank that moves away right before the shot, as a client with a high ping would
+fn fire_at_moving_tank(lag_compensation_max_ms: u32) -> f32 {
+    let mut game = TestGame::new(2);
+    {
+        let mut config = game.server.world_mut().resource_mut::<ServerConfig>();
+        config.lag_compensation_max_ms = lag_compensation_max_ms;
+        config.interpolation_delay_ms = 300;
+    }
+    let shooter = game.join_and_spawn(0, "shooter");
+    let target = game.join_and_spawn(1, "target");
+
+    game.teleport(shooter, Vec3::new(0.0, 0.5, 0.0));
+    game.teleport(target, Vec3::new(0.0, 0.5, 4.0));
+    game.run(60);
+
+    game.teleport(target, Vec3::new(10.0, 0.5, 4.0));
+    game.fire(0);
+    game.run(120);
+
+    game.server.world().get::<Health>(target).unwrap().value
+}
+
+#[test]
+fn shell_hits_the_rewound_tank() {
+    let health = fire_at_moving_tank(500);
+
+    assert!(health < Health::default().value);
+}
+
+#[test]
+fn shell_misses_the_moved_tank_without_lag_compensation() {
+    let health = fire_at_moving_tank(0);
+
+    assert_eq!(health, Health::default().value);
+}
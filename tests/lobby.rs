@@ -0,0 +1,253 @@
+//! Lobby tests with a headless server and clients
+
+mod harness;
+
+use bevy::prelude::*;
+use harness::TestGame;
+use tanks::prelude::*;
+use utils::prelude::*;
+
+fn lobby_game() -> TestGame {
+    lobby_game_with(ServerConfig {
+        lobby: true,
+        ..default()
+    })
+}
+
+fn lobby_game_with(config: ServerConfig) -> TestGame {
+    let mut game = TestGame::with_config(2, config);
+
+    game.join(0, "host");
+    game.run(5);
+    game.join(1, "guest");
+    assert!(game.run_until(|game| game.lobby(0).is_some_and(|lobby| lobby.players.len() == 2)));
+
+    game
+}
+
+/// The levels the server spawned for its rooms
+fn server_levels(game: &mut TestGame) -> Vec<Entity> {
+    game.server
+        .world_mut()
+        .query_filtered::<Entity, With<blenvy::GameWorldTag>>()
+        .iter(game.server.world())
+        .collect()
+}
+
+fn start_match(game: &mut TestGame) {
+    game.ready(0, true);
+    game.ready(1, true);
+    assert!(game.run_until(|game| game.lobby(1).is_some_and(|lobby| lobby.in_match)));
+}
+
+#[test]
+fn match_starts_when_everyone_is_ready() {
+    let mut game = lobby_game();
+    let host_id = game.client_id(0);
+
+    game.ready(0, true);
+    game.send(0, PlayerSpawnEvent);
+    game.run(30);
+    assert!(!game.lobby(0).unwrap().in_match);
+    assert!(game.server_player(host_id).is_none());
+
+    let levels = server_levels(&mut game);
+    game.ready(1, true);
+    assert!(game.run_until(|game| game.lobby(0).is_some_and(|lobby| lobby.in_match)));
+    game.spawn(0, "host");
+
+    // The match is on the map of the lobby, so the level is not loaded again
+    assert_eq!(server_levels(&mut game), levels);
+}
+
+#[test]
+fn only_the_host_changes_the_settings() {
+    let mut game = lobby_game();
+    let settings = game.lobby(0).unwrap().settings.clone();
+
+    game.send(
+        1,
+        MatchSettingsEvent {
+            settings: MatchSettings {
+                time_limit_secs: 120,
+                ..settings.clone()
+            },
+        },
+    );
+    game.run(30);
+    assert_eq!(game.lobby(1).unwrap().settings, settings);
+
+    game.ready(1, true);
+    assert!(game.run_until(|game| game.lobby(0).is_some_and(|lobby| lobby.players[1].ready)));
+    game.send(
+        0,
+        MatchSettingsEvent {
+            settings: MatchSettings {
+                time_limit_secs: 120,
+                ..settings
+            },
+        },
+    );
+    assert!(game.run_until(|game| game
+        .lobby(1)
+        .is_some_and(|lobby| lobby.settings.time_limit_secs == 120)));
+    let lobby = game.lobby(1).unwrap();
+    assert!(lobby.players.iter().all(|player| !player.ready));
+}
+
+#[test]
+fn host_only_picks_the_maps_of_the_server() {
+    let mut game = lobby_game();
+    let settings = game.lobby(0).unwrap().settings.clone();
+
+    game.send(
+        0,
+        MatchSettingsEvent {
+            settings: MatchSettings {
+                map: "levels/Arena.glb".to_string(),
+                ..settings.clone()
+            },
+        },
+    );
+    game.run(30);
+    assert_eq!(game.lobby(1).unwrap().settings, settings);
+}
+
+#[test]
+fn match_ends_with_the_time_limit() {
+    let mut game = lobby_game();
+    let host_id = game.client_id(0);
+    let settings = game.lobby(0).unwrap().settings.clone();
+
+    game.send(
+        0,
+        MatchSettingsEvent {
+            settings: MatchSettings {
+                time_limit_secs: 3,
+                ..settings
+            },
+        },
+    );
+    assert!(game.run_until(|game| game
+        .lobby(0)
+        .is_some_and(|lobby| lobby.settings.time_limit_secs == 3)));
+    start_match(&mut game);
+    game.spawn(0, "host");
+
+    assert!(game.run_until(|game| game.lobby(0).is_some_and(|lobby| !lobby.in_match)));
+    game.run(5);
+    assert!(game.server_player(host_id).is_none());
+    assert!(game.received(1).died.contains(&host_id));
+}
+
+#[test]
+fn maps_rotate_at_the_end_of_a_match() {
+    let maps = vec![
+        "levels/World.glb".to_string(),
+        "levels/Arena.glb".to_string(),
+    ];
+    let mut game = lobby_game_with(ServerConfig {
+        lobby: true,
+        level: maps[0].clone(),
+        maps: maps.clone(),
+        ..default()
+    });
+    assert!(game.run_until(|game| game.current_level(1) == maps[0]));
+    assert_eq!(game.lobby(0).unwrap().maps, maps);
+
+    let settings = game.lobby(0).unwrap().settings.clone();
+    game.send(
+        0,
+        MatchSettingsEvent {
+            settings: MatchSettings {
+                time_limit_secs: 3,
+                ..settings
+            },
+        },
+    );
+    assert!(game.run_until(|game| game
+        .lobby(0)
+        .is_some_and(|lobby| lobby.settings.time_limit_secs == 3)));
+    start_match(&mut game);
+
+    assert!(game.run_until(|game| game.lobby(0).is_some_and(|lobby| !lobby.in_match)));
+    assert!(game.run_until(|game| game.current_level(1) == maps[1]));
+    assert!(game.run_until(|game| game
+        .lobby(1)
+        .is_some_and(|lobby| lobby.settings.map == maps[1])));
+}
+
+#[test]
+fn teammates_cannot_hurt_each_other() {
+    let mut game = lobby_game();
+    let guest_id = game.client_id(1);
+    let settings = game.lobby(0).unwrap().settings.clone();
+
+    game.send(
+        0,
+        MatchSettingsEvent {
+            settings: MatchSettings {
+                mode: TEAM_DEATHMATCH.to_string(),
+                ..settings
+            },
+        },
+    );
+    game.send(
+        1,
+        LobbySelectEvent {
+            team: Team::Red,
+            color: Color::WHITE,
+            class: TankClass::default(),
+        },
+    );
+    assert!(game.run_until(|game| game.lobby(0).is_some_and(|lobby| {
+        lobby.settings.mode == TEAM_DEATHMATCH
+            && lobby.players.iter().all(|player| player.team == Team::Red)
+    })));
+    start_match(&mut game);
+
+    let shooter = game.spawn(0, "host");
+    let target = game.spawn(1, "guest");
+    game.teleport(shooter, Vec3::new(0.0, 0.5, 0.0));
+    game.teleport(target, Vec3::new(0.0, 0.5, 4.0));
+    game.run(30);
+
+    game.fire(0);
+    assert!(game.run_until(|game| game.received(1).impacts > 0));
+    game.run(5);
+    assert!(!game.received(1).hits.contains(&guest_id));
+
+    let health = game.server.world().get::<Health>(target).unwrap();
+    assert_eq!(health.value, TankClass::default().health());
+}
+
+#[test]
+fn class_sets_the_stats_of_the_tank() {
+    let mut game = lobby_game();
+
+    game.send(
+        1,
+        LobbySelectEvent {
+            team: Team::Blue,
+            color: Color::WHITE,
+            class: TankClass::Heavy,
+        },
+    );
+    assert!(game.run_until(|game| game
+        .lobby(0)
+        .is_some_and(|lobby| lobby.players[1].class == TankClass::Heavy)));
+    start_match(&mut game);
+
+    let tank = game.spawn(1, "guest");
+    let world = game.server.world();
+    assert_eq!(
+        world.get::<Health>(tank).unwrap().value,
+        TankClass::Heavy.health()
+    );
+    assert_eq!(
+        world.get::<TankCannon>(tank).unwrap().fire_rate_secs,
+        TankClass::Heavy.fire_rate_secs()
+    );
+    // Teams only matter in team modes
+    assert!(world.get::<Team>(tank).is_none());
+}
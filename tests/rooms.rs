@@ -0,0 +1,71 @@
+//! Room tests with a headless server and clients
+
+mod harness;
+
+use bevy::prelude::*;
+use harness::TestGame;
+use utils::prelude::*;
+
+#[test]
+fn rooms_are_isolated() {
+    let mut game = TestGame::new(2);
+    let shooter_id = game.client_id(0);
+    let target_id = game.client_id(1);
+    let room = game.create_room(1, "side room", 8);
+
+    let shooter = game.join_and_spawn(0, "shooter");
+    game.join_room(1, "target", Some(room.id));
+    game.run(5);
+    let target = game.spawn(1, "target");
+
+    game.teleport(shooter, Vec3::new(0.0, 0.5, 0.0));
+    game.teleport(target, Vec3::new(0.0, 0.5, 4.0));
+    game.run(30);
+
+    game.fire(0);
+    assert!(game.run_until(|game| !game.received(0).fired.is_empty()));
+    game.run(60);
+
+    let health = game.server.world().get::<Health>(target).unwrap();
+    assert_eq!(health.value, Health::default().value);
+    assert!(game.received(1).fired.is_empty());
+    assert!(game.client_player(0, target_id).is_none());
+    assert!(game.client_player(1, shooter_id).is_none());
+    assert!(game.client_stats(1, shooter_id).is_none());
+}
+
+#[test]
+fn full_room_rejects_the_join() {
+    let mut game = TestGame::new(2);
+    let room = game.create_room(0, "duel", 1).id;
+
+    game.join_room(0, "first", Some(room));
+    game.run(5);
+    game.join_room(1, "second", Some(room));
+
+    assert!(game.run_until(|game| !game.received(1).rejected.is_empty()));
+    assert!(game.received(0).rejected.is_empty());
+}
+
+#[test]
+fn joins_are_only_sent_to_the_room() {
+    let mut game = TestGame::new(3);
+    let first = game.client_id(0);
+    let second = game.client_id(1);
+    let third = game.client_id(2);
+    let room = game.create_room(1, "side room", 8);
+
+    game.join(0, "first");
+    game.join_room(1, "second", Some(room.id));
+    game.run(5);
+    game.join(2, "third");
+
+    assert!(game.run_until(|game| game.received(0).joined.contains(&third)));
+    assert!(game.run_until(|game| game.received(2).joined.contains(&first)));
+    game.run(30);
+
+    assert!(!game.received(0).joined.contains(&second));
+    assert!(!game.received(2).joined.contains(&second));
+    assert!(!game.received(1).joined.contains(&first));
+    assert!(!game.received(1).joined.contains(&third));
+}
@@ -0,0 +1,147 @@
+//! Validation tests with a headless server and clients
+
+mod harness;
+
+use bevy::prelude::*;
+use harness::TestGame;
+use tanks::prelude::*;
+use utils::prelude::*;
+
+#[test]
+fn duplicate_name_is_rejected() {
+    let mut game = TestGame::new(2);
+    let second = game.client_id(1);
+
+    game.join(0, "tank");
+    game.run(5);
+    game.join(1, " TANK ");
+
+    assert!(game.run_until(|game| !game.received(1).rejected.is_empty()));
+    game.run(30);
+    assert!(game.client_stats(0, second).is_none());
+}
+
+#[test]
+fn long_name_is_rejected() {
+    let mut game = TestGame::new(1);
+
+    game.join(0, &"a".repeat(MAX_NAME_LENGTH + 1));
+
+    assert!(game.run_until(|game| !game.received(0).rejected.is_empty()));
+}
+
+#[test]
+fn unknown_skin_is_rejected() {
+    let mut game = TestGame::new(1);
+    let client_id = game.client_id(0);
+
+    game.send(
+        0,
+        PlayerJoinEvent {
+            name: "tank".to_string(),
+            color: Color::WHITE,
+            skin: TankSkin(TankSkin::COUNT),
+            session_token: 1,
+            room: None,
+        },
+    );
+
+    assert!(game.run_until(|game| !game.received(0).rejected.is_empty()));
+    let violations = game.server.world().resource::<ClientViolations>();
+    assert_eq!(violations.count(client_id, Violation::Malformed), 1);
+}
+
+#[test]
+fn input_axes_are_clamped() {
+    let mut game = TestGame::new(1);
+    let tank = game.join_and_spawn(0, "driver");
+
+    game.input(0, Vec2::new(-5.0, 100.0));
+
+    assert!(game.run_until(|game| {
+        let input = game
+            .server
+            .world()
+            .get::<TankControllerInput>(tank)
+            .unwrap();
+        input.forward != 0.0
+    }));
+    let input = game
+        .server
+        .world()
+        .get::<TankControllerInput>(tank)
+        .unwrap();
+    assert_eq!((input.steer, input.forward), (-1.0, 1.0));
+}
+
+#[test]
+fn malformed_input_is_counted() {
+    let mut game = TestGame::new(1);
+    let client_id = game.client_id(0);
+    game.join_and_spawn(0, "driver");
+
+    game.input(0, Vec2::NAN);
+
+    assert!(game.run_until(|game| {
+        game.server
+            .world()
+            .resource::<ClientViolations>()
+            .count(client_id, Violation::Malformed)
+            > 0
+    }));
+}
+
+#[test]
+fn fire_flood_kicks_the_client() {
+    let mut game = TestGame::new(1);
+    game.join_and_spawn(0, "flooder");
+
+    for _ in 0..40 {
+        game.fire(0);
+    }
+
+    assert!(game.run_until(|game| game.is_disconnected(0)));
+}
+
+#[test]
+fn input_flood_is_dropped_without_a_kick() {
+    let mut game = TestGame::new(1);
+    let client_id = game.client_id(0);
+    game.join_and_spawn(0, "flooder");
+
+    for i in 0..200 {
+        game.input(0, Vec2::new(0.0, (i % 2) as f32));
+    }
+
+    assert!(game.run_until(|game| {
+        game.server
+            .world()
+            .resource::<ClientViolations>()
+            .count(client_id, Violation::InputFlood)
+            > 0
+    }));
+    game.run(30);
+    assert!(!game.is_disconnected(0));
+}
+
+#[test]
+fn future_tick_is_counted() {
+    let mut game = TestGame::new(1);
+    let client_id = game.client_id(0);
+    game.join_and_spawn(0, "time traveler");
+
+    game.send(
+        0,
+        PlayerFireEvent {
+            tick: ServerTick(u32::MAX),
+        },
+    );
+
+    assert!(game.run_until(|game| {
+        game.server
+            .world()
+            .resource::<ClientViolations>()
+            .count(client_id, Violation::Malformed)
+            > 0
+    }));
+}
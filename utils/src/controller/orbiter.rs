@@ -8,7 +8,9 @@ use leafwing_input_manager::prelude::*;
 use crate::meth::prelude::*;
 
 pub mod prelude {
-    pub use super::{OrbiterTransform, OrbiterTransformPlugin, OrbiterTransformSet};
+    pub use super::{
+        OrbiterTransform, OrbiterTransformAction, OrbiterTransformPlugin, OrbiterTransformSet,
+    };
 }
 
 #[derive(Component, Clone, Copy, Debug)]
@@ -20,6 +22,10 @@ pub struct OrbiterTransform {
     pub orbit_sensitivity: f32,
     /// The zoom sensitivity of the transform
     pub zoom_sensitivity: f32,
+    /// The orbit speed when using the orbit buttons or stick (in radians per second)
+    pub button_orbit_speed: f32,
+    /// The zoom speed when using the zoom buttons (in units per second)
+    pub button_zoom_speed: f32,
     /// Minimum zoom distance
     pub min_zoom: f32,
     /// Maximum zoom distance
@@ -40,6 +46,8 @@ impl Default for OrbiterTransform {
             focus: Vec3::ZERO,
             orbit_sensitivity: 0.002,
             zoom_sensitivity: 1.0,
+            button_orbit_speed: 2.0,
+            button_zoom_speed: 10.0,
             min_zoom: 5.0,
             max_zoom: 20.0,
             min_pitch: 0.0,
@@ -56,14 +64,27 @@ pub enum OrbiterTransformAction {
     Pan,
     #[actionlike(Axis)]
     Zoom,
+    #[actionlike(DualAxis)]
+    Orbit,
+    OrbitLeft,
+    OrbitRight,
+    OrbitUp,
+    OrbitDown,
+    ZoomIn,
+    ZoomOut,
 }
 
 impl OrbiterTransformAction {
-    // TODO: Allow customization of the input map
-    fn default_input_map() -> InputMap<Self> {
+    /// The input map that is used when the orbiter is spawned without one
+    pub fn default_input_map() -> InputMap<Self> {
         InputMap::default()
             .with_dual_axis(OrbiterTransformAction::Pan, MouseMove::default())
             .with_axis(OrbiterTransformAction::Zoom, MouseScrollAxis::Y)
+            .with(OrbiterTransformAction::OrbitLeft, KeyCode::ArrowLeft)
+            .with(OrbiterTransformAction::OrbitRight, KeyCode::ArrowRight)
+            .with(OrbiterTransformAction::OrbitUp, KeyCode::ArrowUp)
+            .with(OrbiterTransformAction::OrbitDown, KeyCode::ArrowDown)
+            .with_dual_axis(OrbiterTransformAction::Orbit, GamepadStick::RIGHT)
     }
 }
 
@@ -107,14 +128,19 @@ impl Plugin for OrbiterTransformPlugin {
 fn initialize_orbiter(
     mut commands: Commands,
     q_camera: Query<
-        (Entity, &Transform, &OrbiterTransform),
+        (
+            Entity,
+            &Transform,
+            &OrbiterTransform,
+            Has<InputMap<OrbiterTransformAction>>,
+        ),
         (
             Without<OrbiterTransformTarget>,
             Without<OrbiterTransformState>,
         ),
     >,
 ) {
-    for (entity, transform, camera) in q_camera.iter() {
+    for (entity, transform, camera, has_input_map) in q_camera.iter() {
         let comp_vec = transform.translation - camera.focus;
         let yaw = comp_vec.z.atan2(comp_vec.x);
         let radius = comp_vec.length().max(camera.min_zoom).min(camera.max_zoom);
@@ -123,14 +149,19 @@ fn initialize_orbiter(
         commands
             .entity(entity)
             .insert(OrbiterTransformTarget { yaw, pitch, radius })
-            .insert(OrbiterTransformState { yaw, pitch, radius })
-            .insert(InputManagerBundle::with_map(
+            .insert(OrbiterTransformState { yaw, pitch, radius });
+
+        // Keep the input map if the orbiter was spawned with a custom one
+        if !has_input_map {
+            commands.entity(entity).insert(InputManagerBundle::with_map(
                 OrbiterTransformAction::default_input_map(),
             ));
+        }
     }
 }
 
 fn update_orbiter_target(
+    time: Res<Time>,
     mut q_camera: Query<(
         &OrbiterTransform,
         &ActionState<OrbiterTransformAction>,
@@ -138,16 +169,38 @@ fn update_orbiter_target(
     )>,
 ) {
     for (camera, action, mut camera_target) in q_camera.iter_mut() {
-        let zoom = action.value(&OrbiterTransformAction::Zoom);
-        let orbit = action.axis_pair(&OrbiterTransformAction::Pan);
-
-        camera_target.yaw -= orbit.x * camera.orbit_sensitivity;
-
-        camera_target.pitch = (camera_target.pitch + orbit.y * camera.orbit_sensitivity)
-            .clamp(camera.min_pitch, camera.max_pitch);
-
-        camera_target.radius = (camera_target.radius - zoom * camera.zoom_sensitivity)
-            .clamp(camera.min_zoom, camera.max_zoom);
+        let button_value = |a: OrbiterTransformAction, b: OrbiterTransformAction| {
+            action.pressed(&a) as i32 as f32 - action.pressed(&b) as i32 as f32
+        };
+
+        let zoom = action.value(&OrbiterTransformAction::Zoom) * camera.zoom_sensitivity
+            + button_value(
+                OrbiterTransformAction::ZoomIn,
+                OrbiterTransformAction::ZoomOut,
+            ) * camera.button_zoom_speed
+                * time.delta_secs();
+        let orbit = action.axis_pair(&OrbiterTransformAction::Pan) * camera.orbit_sensitivity
+            + (Vec2::new(
+                button_value(
+                    OrbiterTransformAction::OrbitRight,
+                    OrbiterTransformAction::OrbitLeft,
+                ),
+                button_value(
+                    OrbiterTransformAction::OrbitUp,
+                    OrbiterTransformAction::OrbitDown,
+                ),
+            ) + action.clamped_axis_pair(&OrbiterTransformAction::Orbit))
+            .clamp(Vec2::NEG_ONE, Vec2::ONE)
+                * camera.button_orbit_speed
+                * time.delta_secs();
+
+        camera_target.yaw -= orbit.x;
+
+        camera_target.pitch =
+            (camera_target.pitch + orbit.y).clamp(camera.min_pitch, camera.max_pitch);
+
+        camera_target.radius =
+            (camera_target.radius - zoom).clamp(camera.min_zoom, camera.max_zoom);
     }
 }
 
@@ -32,7 +32,7 @@ pub struct HealthPlugin;
 
 impl Plugin for HealthPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, handle_damage);
+        app.add_systems(FixedUpdate, handle_damage.in_set(HealthSet));
     }
 }
 
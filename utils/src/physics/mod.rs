@@ -20,7 +20,7 @@ pub struct CollisionPlugin;
 impl Plugin for CollisionPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(
-            Update,
+            FixedUpdate,
             (handle_collision_events).in_set(CollisionSet).chain(),
         );
     }